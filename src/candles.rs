@@ -0,0 +1,192 @@
+use crate::SerializationError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/// The trades of a company over `resolution` ticks, starting at `start_tick`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Candle {
+    pub start_tick: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Number of shares traded
+    pub volume: u64,
+    /// Money that changed hands
+    pub turnover: f64,
+    pub trade_count: u64,
+}
+
+impl Candle {
+    pub fn new(start_tick: u64, price: f64, number_of_shares: u64) -> Self {
+        Self {
+            start_tick,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: number_of_shares,
+            turnover: price * number_of_shares as f64,
+            trade_count: 1,
+        }
+    }
+
+    pub fn add_trade(&mut self, price: f64, number_of_shares: u64) {
+        self.high = self.high.max(price);
+        self.low = self.low.min(price);
+        self.close = price;
+        self.volume += number_of_shares;
+        self.turnover += price * number_of_shares as f64;
+        self.trade_count += 1;
+    }
+
+    /// Volume weighted average price
+    pub fn vwap(&self) -> f64 {
+        if self.volume == 0 {
+            return self.close;
+        }
+        self.turnover / self.volume as f64
+    }
+}
+
+/// Candles of a single company at a single resolution, oldest first.
+/// Ticks without any trades don't get a candle.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CandleSeries {
+    pub resolution: u64,
+    pub candles: VecDeque<Candle>,
+}
+
+impl CandleSeries {
+    pub fn new(resolution: u64) -> Self {
+        Self {
+            resolution,
+            candles: VecDeque::new(),
+        }
+    }
+
+    fn add_trade(&mut self, tick: u64, price: f64, number_of_shares: u64, history_size: usize) {
+        let start_tick = tick - tick % self.resolution;
+        match self.candles.back_mut() {
+            Some(candle) if candle.start_tick == start_tick => {
+                candle.add_trade(price, number_of_shares)
+            }
+            _ => {
+                self.candles
+                    .push_back(Candle::new(start_tick, price, number_of_shares));
+                if self.candles.len() > history_size {
+                    self.candles.pop_front();
+                }
+            }
+        }
+    }
+
+    pub fn write_csv(&self, writer: &mut impl Write) -> Result<(), SerializationError> {
+        let mut write = |line: String| {
+            writer
+                .write_all(line.as_bytes())
+                .map_err(|_| SerializationError::FailedToWrite)
+        };
+        write("start_tick,open,high,low,close,volume,vwap,trade_count\n".to_string())?;
+        for candle in self.candles.iter() {
+            write(format!(
+                "{},{},{},{},{},{},{},{}\n",
+                candle.start_tick,
+                candle.open,
+                candle.high,
+                candle.low,
+                candle.close,
+                candle.volume,
+                candle.vwap(),
+                candle.trade_count
+            ))?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds the candles of every company at several resolutions from the trades done
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Candles {
+    /// Length of the candles in ticks
+    resolutions: Vec<u64>,
+    /// Number of candles kept for every company and resolution
+    pub history_size: usize,
+    series: BTreeMap<u64, Vec<CandleSeries>>,
+}
+
+impl Default for Candles {
+    fn default() -> Self {
+        Self::new(&[1, 5, 100], 1_000)
+    }
+}
+
+impl Candles {
+    /// Resolutions of 0 ticks are left out
+    pub fn new(resolutions: &[u64], history_size: usize) -> Self {
+        let mut resolutions: Vec<u64> = resolutions
+            .iter()
+            .copied()
+            .filter(|resolution| *resolution != 0)
+            .collect();
+        resolutions.sort_unstable();
+        resolutions.dedup();
+        Self {
+            resolutions,
+            history_size,
+            series: BTreeMap::new(),
+        }
+    }
+
+    pub fn resolutions(&self) -> &[u64] {
+        &self.resolutions
+    }
+
+    pub fn add_trade(&mut self, company_id: u64, tick: u64, price: f64, number_of_shares: u64) {
+        let resolutions = &self.resolutions;
+        let series = self.series.entry(company_id).or_insert_with(|| {
+            resolutions
+                .iter()
+                .map(|resolution| CandleSeries::new(*resolution))
+                .collect()
+        });
+        for series in series.iter_mut() {
+            series.add_trade(tick, price, number_of_shares, self.history_size);
+        }
+    }
+
+    pub fn get(&self, company_id: u64, resolution: u64) -> Option<&CandleSeries> {
+        self.series
+            .get(&company_id)?
+            .iter()
+            .find(|series| series.resolution == resolution)
+    }
+
+    /// The most recent candle of the company at the resolution
+    pub fn latest(&self, company_id: u64, resolution: u64) -> Option<&Candle> {
+        self.get(company_id, resolution)?.candles.back()
+    }
+
+    pub fn export_csv(
+        &self,
+        company_id: u64,
+        resolution: u64,
+        file_path: &str,
+    ) -> Result<(), SerializationError> {
+        let Ok(file) = File::create(file_path) else {
+            return Err(SerializationError::FailedToCreateFile);
+        };
+        let mut writer = BufWriter::new(file);
+        match self.get(company_id, resolution) {
+            Some(series) => series.write_csv(&mut writer)?,
+            None => CandleSeries::new(resolution).write_csv(&mut writer)?,
+        }
+        writer
+            .flush()
+            .map_err(|_| SerializationError::FailedToWrite)
+    }
+}
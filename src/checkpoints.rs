@@ -0,0 +1,92 @@
+use crate::{log, logger::Log, simulation::Simulation, snapshot::Snapshot, SerializationError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const CHECKPOINT_PREFIX: &str = "checkpoint-";
+const CHECKPOINT_EXTENSION: &str = ".snapshot";
+
+/// Snapshots of a running simulation taken every so often, so that a crash only loses
+/// the ticks since the last one. Only the newest `keep` of them are kept
+#[derive(Debug, Clone)]
+pub struct Checkpoints {
+    dir: PathBuf,
+    pub keep: u64,
+}
+
+impl Checkpoints {
+    pub fn new(dir: impl Into<PathBuf>, keep: u64) -> Self {
+        Self {
+            dir: dir.into(),
+            keep,
+        }
+    }
+
+    fn file(&self, tick: u64) -> PathBuf {
+        // padded so that the file names sort by tick
+        self.dir.join(format!(
+            "{}{:020}{}",
+            CHECKPOINT_PREFIX, tick, CHECKPOINT_EXTENSION
+        ))
+    }
+
+    /// Every checkpoint as `(tick, file_path)`, oldest first
+    pub fn list(&self) -> Vec<(u64, PathBuf)> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        let mut checkpoints: Vec<(u64, PathBuf)> = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let tick = path
+                    .file_name()?
+                    .to_str()?
+                    .strip_prefix(CHECKPOINT_PREFIX)?
+                    .strip_suffix(CHECKPOINT_EXTENSION)?
+                    .parse()
+                    .ok()?;
+                Some((tick, path))
+            })
+            .collect();
+        checkpoints.sort();
+        checkpoints
+    }
+
+    /// Saves the simulation as a checkpoint and removes the ones that are no longer kept
+    pub fn save(&self, simulation: &Simulation) -> Result<PathBuf, SerializationError> {
+        fs::create_dir_all(&self.dir).map_err(|_| SerializationError::FailedToCreateFile)?;
+        let file_path = self.file(simulation.tick());
+        simulation.save_snapshot(&file_path.to_string_lossy())?;
+
+        let checkpoints = self.list();
+        let number_to_remove = checkpoints.len().saturating_sub(self.keep as usize);
+        for (_, old_file_path) in checkpoints.iter().take(number_to_remove) {
+            if fs::remove_file(old_file_path).is_err() {
+                log!(warn "Failed to remove the old checkpoint {}", old_file_path.display());
+            }
+        }
+        Ok(file_path)
+    }
+
+    /// The newest checkpoint whose checksums match, damaged ones are skipped
+    pub fn newest_valid(&self) -> Option<Snapshot> {
+        self.list().iter().rev().find_map(|(_, file_path)| {
+            match Snapshot::load(&file_path.to_string_lossy()) {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    log!(warn "Skipping the damaged checkpoint {}\n{:?}", file_path.display(), e);
+                    None
+                }
+            }
+        })
+    }
+
+    /// Removes every checkpoint
+    pub fn clear(&self) -> Result<(), SerializationError> {
+        if !Path::new(&self.dir).exists() {
+            return Ok(());
+        }
+        fs::remove_dir_all(&self.dir).map_err(|_| SerializationError::FailedToWrite)
+    }
+}
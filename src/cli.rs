@@ -0,0 +1,720 @@
+use crate::{
+    checkpoints::Checkpoints,
+    config::{Config, MarketConfig},
+    entities::{agents::Agents, companies::Companies, portfolio::Position},
+    invariants::Totals,
+    journal::{Journal, Replayer},
+    ledger::{Ledger, LedgerRecord},
+    log,
+    logger::Log,
+    market::Market,
+    simulation::{Cadences, Simulation},
+    snapshot::Snapshot,
+    DeserializationError, SerializationError, SimulationError, AGENTS_DATA_FILENAME,
+    CHECKPOINTS_DIR, CHECKPOINTS_KEPT, CHECKPOINT_EVERY, COMPANIES_DATA_FILENAME, CONFIG_FILENAME,
+    DATA_DIR, JOURNAL_DATA_FILENAME, JOURNAL_SNAPSHOT_FILENAME, LEDGER_DATA_FILENAME,
+    SNAPSHOT_DATA_FILENAME,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Agent based stock market simulation
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generates a fresh world and saves it, replacing whatever was saved there
+    Init(InitArgs),
+    /// Runs the simulation on a saved world, generating one if there's none
+    Run(RunArgs),
+    /// Prints a summary of a saved world, or of one of its agents or companies
+    Inspect(InspectArgs),
+    /// Dumps a saved world to JSON or CSV files
+    Export(ExportArgs),
+    /// Goes through the trades in the ledger of a saved world
+    Replay(ReplayArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    #[arg(long, default_value = DATA_DIR)]
+    pub data_dir: String,
+    /// Config to generate the world with, the defaults are used if there's none
+    #[arg(long)]
+    pub config: Option<String>,
+    #[arg(long)]
+    pub agents: Option<u64>,
+    #[arg(long)]
+    pub companies: Option<u64>,
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+pub struct RunArgs {
+    #[arg(long, default_value = DATA_DIR)]
+    pub data_dir: String,
+    /// Config to run with, the one saved with the world or else config.yaml if there's none
+    #[arg(long)]
+    pub config: Option<String>,
+    /// Number of ticks to run for, runs until Ctrl-C if there's none
+    #[arg(long)]
+    pub ticks: Option<u64>,
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Check that no money or shares appear out of nowhere
+    #[arg(long)]
+    pub check_invariants: bool,
+    /// Pick up from the newest world saved or checkpointed that isn't damaged
+    #[arg(long)]
+    pub resume: bool,
+    /// Number of ticks between checkpoints, 0 turns them off
+    #[arg(long, default_value_t = CHECKPOINT_EVERY)]
+    pub checkpoint_every: u64,
+    /// Number of the newest checkpoints kept
+    #[arg(long, default_value_t = CHECKPOINTS_KEPT, value_parser = clap::value_parser!(u64).range(1..))]
+    pub keep_checkpoints: u64,
+    /// Journal every change made to the world, so it can be inspected at any tick
+    #[arg(long)]
+    pub journal: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct InspectArgs {
+    #[arg(long, default_value = DATA_DIR)]
+    pub data_dir: String,
+    #[arg(long, conflicts_with = "company")]
+    pub agent: Option<u64>,
+    #[arg(long)]
+    pub company: Option<u64>,
+    /// Rebuild the world as it was at the tick from the journal
+    #[arg(long)]
+    pub at_tick: Option<u64>,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Json,
+    Csv,
+}
+
+#[derive(Args, Debug)]
+pub struct ExportArgs {
+    #[arg(long, default_value = DATA_DIR)]
+    pub data_dir: String,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Json)]
+    pub format: ExportFormat,
+    /// Directory the files are written to
+    #[arg(long, default_value = "export")]
+    pub output: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ReplayArgs {
+    #[arg(long, default_value = DATA_DIR)]
+    pub data_dir: String,
+    /// First tick to replay
+    #[arg(long)]
+    pub from: Option<u64>,
+    /// Last tick to replay
+    #[arg(long)]
+    pub to: Option<u64>,
+    /// Only replay the trades of this company
+    #[arg(long)]
+    pub company: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum CliError {
+    Deserialization(DeserializationError),
+    Serialization(SerializationError),
+    Simulation(SimulationError),
+    CompanyNotFound(u64),
+    /// The journal doesn't go back or forward to the tick
+    TickNotJournaled(u64),
+}
+
+impl From<DeserializationError> for CliError {
+    fn from(e: DeserializationError) -> Self {
+        Self::Deserialization(e)
+    }
+}
+
+impl From<SerializationError> for CliError {
+    fn from(e: SerializationError) -> Self {
+        Self::Serialization(e)
+    }
+}
+
+impl From<SimulationError> for CliError {
+    fn from(e: SimulationError) -> Self {
+        Self::Simulation(e)
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(_: std::io::Error) -> Self {
+        Self::Serialization(SerializationError::FailedToWrite)
+    }
+}
+
+/// The directory a world is saved in
+#[derive(Debug, Clone)]
+pub struct DataDir(PathBuf);
+
+impl DataDir {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self(path.into())
+    }
+
+    fn file(&self, file_name: &str) -> String {
+        self.0.join(file_name).to_string_lossy().into_owned()
+    }
+
+    pub fn snapshot_file(&self) -> String {
+        self.file(SNAPSHOT_DATA_FILENAME)
+    }
+
+    /// Where agents were saved before snapshots, only read to migrate them
+    pub fn agents_file(&self) -> String {
+        self.file(AGENTS_DATA_FILENAME)
+    }
+
+    /// Where companies were saved before snapshots, only read to migrate them
+    pub fn companies_file(&self) -> String {
+        self.file(COMPANIES_DATA_FILENAME)
+    }
+
+    pub fn ledger_file(&self) -> String {
+        self.file(LEDGER_DATA_FILENAME)
+    }
+
+    pub fn journal_file(&self) -> String {
+        self.file(JOURNAL_DATA_FILENAME)
+    }
+
+    /// The world the journal starts from
+    pub fn journal_snapshot_file(&self) -> String {
+        self.file(JOURNAL_SNAPSHOT_FILENAME)
+    }
+
+    pub fn config_file(&self) -> String {
+        self.file(CONFIG_FILENAME)
+    }
+
+    pub fn checkpoints(&self, keep: u64) -> Checkpoints {
+        Checkpoints::new(self.0.join(CHECKPOINTS_DIR), keep)
+    }
+
+    pub fn create(&self) -> Result<(), SerializationError> {
+        fs::create_dir_all(&self.0).map_err(|_| SerializationError::FailedToCreateFile)
+    }
+
+    /// The saved snapshot, or the world migrated from the files saved before snapshots.
+    /// A migrated world gets an empty market set up from the given config
+    pub fn load_snapshot(
+        &self,
+        market_config: &MarketConfig,
+    ) -> Result<Snapshot, DeserializationError> {
+        if Path::new(&self.snapshot_file()).exists() {
+            return Snapshot::load(&self.snapshot_file());
+        }
+        let snapshot = Snapshot::from_legacy_files(
+            &self.agents_file(),
+            &self.companies_file(),
+            market_config,
+        )?;
+        log!(info "Migrated the world saved before snapshots in {}", self.0.display());
+        Ok(snapshot)
+    }
+
+    /// The newest of the saved world and its checkpoints, damaged ones are skipped
+    pub fn load_newest_snapshot(
+        &self,
+        market_config: &MarketConfig,
+        checkpoints: &Checkpoints,
+    ) -> Result<Snapshot, DeserializationError> {
+        let saved = self.load_snapshot(market_config);
+        let Some(checkpoint) = checkpoints.newest_valid() else {
+            return saved;
+        };
+        match saved {
+            Ok(snapshot) if snapshot.header.tick >= checkpoint.header.tick => Ok(snapshot),
+            Ok(_) => Ok(checkpoint),
+            Err(e) => {
+                log!(warn "Failed to load the saved world, resuming from a checkpoint\n{:?}", e);
+                Ok(checkpoint)
+            }
+        }
+    }
+
+    pub fn load_world(&self) -> Result<(Agents, Companies), DeserializationError> {
+        let snapshot = self.load_snapshot(&MarketConfig::default())?;
+        Ok((snapshot.agents, snapshot.companies))
+    }
+
+    pub fn save_world(&self, simulation: &Simulation) -> Result<(), CliError> {
+        self.create()?;
+        simulation.save_snapshot(&self.snapshot_file())?;
+        Ok(())
+    }
+
+    /// The journal the simulation goes on recording in. If the saved journal
+    /// doesn't lead up to the simulation, a new one is started from it
+    pub fn open_journal(&self, simulation: &Simulation) -> Result<Journal, CliError> {
+        let tick = simulation.tick();
+        let journaled_until = match Snapshot::load(&self.journal_snapshot_file()) {
+            Ok(base) if base.header.tick <= tick => Journal::read(&self.journal_file())
+                .unwrap_or_default()
+                .iter()
+                .map(|entry| entry.tick)
+                .take_while(|entry_tick| *entry_tick <= tick)
+                .last()
+                .or(Some(base.header.tick)),
+            _ => None,
+        };
+        if journaled_until == Some(tick) {
+            if Path::new(&self.journal_file()).exists() {
+                // after a crash the journal has changes the world doesn't
+                Journal::discard_after(&self.journal_file(), tick)?;
+            }
+        } else {
+            simulation.save_snapshot(&self.journal_snapshot_file())?;
+            remove_file_if_exists(&self.journal_file())?;
+        }
+        Ok(Journal::open(&self.journal_file())?)
+    }
+
+    /// Rebuilds the world as it was at the tick from the journal
+    pub fn replay_journal(&self, tick: u64) -> Result<(Agents, Companies), CliError> {
+        let base = Snapshot::load(&self.journal_snapshot_file())?;
+        if tick < base.header.tick {
+            return Err(CliError::TickNotJournaled(tick));
+        }
+        let entries = Journal::read(&self.journal_file())?;
+        let mut replayer = Replayer::new(base);
+        replayer.replay(&entries, Some(tick))?;
+        if replayer.tick() < tick {
+            return Err(CliError::TickNotJournaled(tick));
+        }
+        let (agents, companies, _) = replayer.into_world();
+        Ok((agents, companies))
+    }
+
+    /// The saved ledger, or an empty one if nothing was traded yet
+    pub fn read_ledger(&self) -> Result<Ledger, DeserializationError> {
+        if !Path::new(&self.ledger_file()).exists() {
+            return Ok(Ledger::new());
+        }
+        Ledger::open(&self.ledger_file())
+    }
+}
+
+fn remove_file_if_exists(file_path: &str) -> Result<(), SerializationError> {
+    match fs::remove_file(file_path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(SerializationError::FailedToWrite),
+        _ => Ok(()),
+    }
+}
+
+impl Cli {
+    /// Runs the command, printing its output to `out`
+    pub fn execute(self, out: &mut impl Write) -> Result<(), CliError> {
+        match self.command {
+            Command::Init(args) => init(args, out),
+            Command::Run(args) => run(args, out),
+            Command::Inspect(args) => inspect(args, out),
+            Command::Export(args) => export(args, out),
+            Command::Replay(args) => replay(args, out),
+        }
+    }
+}
+
+fn init(args: InitArgs, out: &mut impl Write) -> Result<(), CliError> {
+    let mut config = match args.config {
+        Some(config_file) => Config::load(&config_file)?,
+        None => Config::default(),
+    };
+    if let Some(num_of_agents) = args.agents {
+        config.world.num_of_agents = num_of_agents;
+    }
+    if let Some(num_of_companies) = args.companies {
+        config.world.num_of_companies = num_of_companies;
+    }
+    if args.seed.is_some() {
+        config.seed = args.seed;
+    }
+    config.validate()?;
+
+    let simulation = Simulation::rand(config)?;
+    let data_dir = DataDir::new(&args.data_dir);
+    data_dir.save_world(&simulation)?;
+    // the trades, checkpoints and journal of the world that was saved here before
+    // don't belong to this one
+    data_dir.checkpoints(CHECKPOINTS_KEPT).clear()?;
+    remove_file_if_exists(&data_dir.ledger_file())?;
+    remove_file_if_exists(&data_dir.journal_file())?;
+    remove_file_if_exists(&data_dir.journal_snapshot_file())?;
+    simulation.config().save(&data_dir.config_file())?;
+    writeln!(
+        out,
+        "Generated {} agents and {} companies with seed {} in {}",
+        simulation.agents().num_of_agents,
+        simulation.companies().num_of_companies,
+        simulation.seed(),
+        args.data_dir
+    )?;
+    Ok(())
+}
+
+fn run(args: RunArgs, out: &mut impl Write) -> Result<(), CliError> {
+    let data_dir = DataDir::new(&args.data_dir);
+    let config_file = args.config.unwrap_or_else(|| {
+        let saved_config_file = data_dir.config_file();
+        if Path::new(&saved_config_file).exists() {
+            saved_config_file
+        } else {
+            CONFIG_FILENAME.to_string()
+        }
+    });
+    let mut config = match Config::load(&config_file) {
+        Ok(config) => config,
+        Err(DeserializationError::FileNotFound) => {
+            log!(warn "Config file not found, running with the defaults");
+            Config::default()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if args.seed.is_some() {
+        config.seed = args.seed;
+    }
+    let seed = config.resolve_seed();
+    log!(info "Seed: {}", seed);
+
+    data_dir.create()?;
+    let mut ledger = Ledger::open(&data_dir.ledger_file()).unwrap_or_else(|e| {
+        log!(warn "Failed to open the ledger, trades are only kept in memory\n{:?}", e);
+        Ledger::new()
+    });
+    let checkpoints = data_dir.checkpoints(args.keep_checkpoints);
+    let snapshot = if args.resume {
+        data_dir.load_newest_snapshot(&config.market, &checkpoints)
+    } else {
+        data_dir.load_snapshot(&config.market)
+    };
+    let mut simulation = match snapshot {
+        Ok(mut snapshot) => {
+            log!(info "Loaded the world from {} at tick {}", args.data_dir, snapshot.header.tick);
+            if snapshot.header.config_hash != 0 && snapshot.header.config_hash != config.hash() {
+                log!(warn "The world was simulated with a different config");
+            }
+            // after a crash the ledger has trades the snapshot doesn't
+            if let Err(e) = ledger.discard_after(snapshot.header.tick) {
+                log!(warn "Failed to discard the trades after the snapshot\n{:?}", e);
+            }
+            snapshot.market.ledger = ledger;
+            Simulation::from_snapshot(config, snapshot)
+        }
+        Err(e) => {
+            log!(warn "No world saved in {}, generating one\n{:?}", args.data_dir, e);
+            let companies = Simulation::rand_companies(seed, config.world.num_of_companies);
+            let agents = Simulation::rand_agents(
+                seed,
+                config.world.num_of_agents,
+                companies.num_of_companies,
+            )?;
+            let mut market = Market::from_config(&config.market);
+            market.ledger = ledger;
+            Simulation::new(config, agents, companies, market)
+        }
+    };
+    if args.check_invariants {
+        simulation.check_invariants();
+    }
+    if args.journal {
+        let journal = data_dir.open_journal(&simulation)?;
+        simulation.record_journal(journal);
+    }
+
+    let running = Arc::new(AtomicBool::new(true));
+    let r = running.clone();
+    if ctrlc::set_handler(move || r.store(false, Ordering::SeqCst)).is_err() {
+        log!(warn "Failed to set the Ctrl-C handler");
+    }
+    let last_tick = args.ticks.map(|ticks| simulation.tick() + ticks);
+    while running.load(Ordering::SeqCst)
+        && last_tick.is_none_or(|last_tick| simulation.tick() < last_tick)
+    {
+        writeln!(out, "{}", simulation.tick() + 1)?;
+        match simulation.step() {
+            Ok(()) | Err(SimulationError::Unspendable | SimulationError::UnDoable) => {}
+            Err(SimulationError::AgentNotFound(agent_id)) => {
+                log!(warn "Agent not found: {}", agent_id);
+            }
+            Err(SimulationError::OfferNotFound(offer_id)) => {
+                log!(warn "Offer not found: {}", offer_id);
+            }
+            Err(SimulationError::NoData) => {
+                log!(warn "No data");
+            }
+            Err(SimulationError::Diverged(tick)) => {
+                log!(warn "Diverged from the journal at tick {}", tick);
+            }
+        }
+        if Cadences::is_due(args.checkpoint_every, simulation.tick()) {
+            if let Err(e) = checkpoints.save(&simulation) {
+                log!(warn "Failed to save a checkpoint at tick {}\n{:?}", simulation.tick(), e);
+            }
+        }
+    }
+    log!(info "Exiting at index {:?}", simulation.tick());
+    if let Some(violation) = simulation.first_violation() {
+        log!(warn "First invariant violation at tick {} after {}: {:?}", violation.tick, violation.event, violation.divergence);
+    }
+
+    log!(info "Saving data");
+    data_dir.save_world(&simulation)?;
+    log!(info "Saved the world at tick {}", simulation.tick());
+    Ok(())
+}
+
+fn inspect(args: InspectArgs, out: &mut impl Write) -> Result<(), CliError> {
+    let data_dir = DataDir::new(&args.data_dir);
+    let (agents, companies, ledger) = match args.at_tick {
+        Some(tick) => {
+            let (agents, companies) = data_dir.replay_journal(tick)?;
+            (agents, companies, data_dir.read_ledger()?.until(tick))
+        }
+        None => {
+            let (agents, companies) = data_dir.load_world()?;
+            (agents, companies, data_dir.read_ledger()?)
+        }
+    };
+
+    if let Some(agent_id) = args.agent {
+        writeln!(out, "Agent {}", agent_id)?;
+        writeln!(out, "Balance: {:.2}", agents.balances.get(agent_id)?)?;
+        writeln!(out, "Equity: {:.2}", agents.equity(agent_id, &companies)?)?;
+        writeln!(out, "Holdings:")?;
+        for (_, company_id, number_of_shares) in
+            agents
+                .holdings
+                .iter()
+                .filter(|(holder_id, _, number_of_shares)| {
+                    *holder_id == agent_id && *number_of_shares != 0
+                })
+        {
+            match agents
+                .position(agent_id, company_id)
+                .and_then(Position::average_cost)
+            {
+                Some(average_cost) => writeln!(
+                    out,
+                    "  company {}: {} shares at an average cost of {:.2}",
+                    company_id, number_of_shares, average_cost
+                )?,
+                None => writeln!(out, "  company {}: {} shares", company_id, number_of_shares)?,
+            }
+        }
+        writeln!(out, "Realized P&L: {:.2}", agents.realized_profit(agent_id))?;
+        writeln!(
+            out,
+            "Unrealized P&L: {:.2}",
+            agents.unrealized_profit(agent_id, &companies)
+        )?;
+        writeln!(out, "Trades: {}", ledger.by_agent(agent_id).count())?;
+        return Ok(());
+    }
+
+    if let Some(company_id) = args.company {
+        let (Some(market_value), Some(lots)) = (
+            companies.market_values.get(company_id as usize),
+            companies.lots.get(company_id as usize),
+        ) else {
+            return Err(CliError::CompanyNotFound(company_id));
+        };
+        let id = company_id as usize;
+        writeln!(out, "Company {}", company_id)?;
+        writeln!(
+            out,
+            "Price: {:.2} (low {:.2}, high {:.2})",
+            market_value.current_price, market_value.lowest_price, market_value.highest_price
+        )?;
+        writeln!(out, "Balance: {:.2}", companies.balances[id])?;
+        writeln!(
+            out,
+            "Expected profit: {:.2}",
+            companies.expected_profits[id]
+        )?;
+        writeln!(out, "News: {:.2}", companies.news[id])?;
+        writeln!(
+            out,
+            "Lots: {} of {} shares at {:.2}",
+            lots.number_of_lots, lots.lot_size, lots.strike_price
+        )?;
+        writeln!(out, "Trades: {}", ledger.by_company(company_id).count())?;
+        return Ok(());
+    }
+
+    let totals = Totals::measure(&agents, &companies);
+    writeln!(out, "Agents: {}", agents.num_of_agents)?;
+    writeln!(out, "Companies: {}", companies.num_of_companies)?;
+    writeln!(out, "Money: {:.2}", totals.cash)?;
+    writeln!(out, "Shares: {}", totals.shares.values().sum::<u64>())?;
+    writeln!(out, "Ledger records: {}", ledger.len())?;
+    if let Some(record) = ledger.records().last() {
+        writeln!(out, "Last trade at tick {}", record.tick())?;
+    }
+    Ok(())
+}
+
+fn write_json<T: Serialize + ?Sized>(file_path: &Path, data: &T) -> Result<(), SerializationError> {
+    let Ok(file) = File::create(file_path) else {
+        return Err(SerializationError::FailedToCreateFile);
+    };
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, data)
+        .map_err(|_| SerializationError::FailedToSerialize)?;
+    writer
+        .flush()
+        .map_err(|_| SerializationError::FailedToWrite)
+}
+
+fn write_csv(
+    file_path: &Path,
+    header: &str,
+    rows: impl Iterator<Item = String>,
+) -> Result<(), SerializationError> {
+    let Ok(file) = File::create(file_path) else {
+        return Err(SerializationError::FailedToCreateFile);
+    };
+    let mut writer = BufWriter::new(file);
+    for line in std::iter::once(header.to_string()).chain(rows) {
+        writeln!(writer, "{}", line).map_err(|_| SerializationError::FailedToWrite)?;
+    }
+    writer
+        .flush()
+        .map_err(|_| SerializationError::FailedToWrite)
+}
+
+fn export(args: ExportArgs, out: &mut impl Write) -> Result<(), CliError> {
+    let data_dir = DataDir::new(&args.data_dir);
+    let (agents, companies) = data_dir.load_world()?;
+    let ledger = data_dir.read_ledger()?;
+    let output = Path::new(&args.output);
+    fs::create_dir_all(output).map_err(|_| SerializationError::FailedToCreateFile)?;
+
+    match args.format {
+        ExportFormat::Json => {
+            write_json(&output.join("agents.json"), &agents.save()?)?;
+            write_json(&output.join("companies.json"), &companies.save())?;
+            write_json(&output.join("ledger.json"), ledger.records())?;
+        }
+        ExportFormat::Csv => {
+            write_csv(
+                &output.join("agents.csv"),
+                "agent_id,balance",
+                agents
+                    .balances
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(|(agent_id, balance)| format!("{},{}", agent_id, balance)),
+            )?;
+            write_csv(
+                &output.join("holdings.csv"),
+                "agent_id,company_id,number_of_shares",
+                agents
+                    .holdings
+                    .iter()
+                    .map(|(agent_id, company_id, number_of_shares)| {
+                        format!("{},{},{}", agent_id, company_id, number_of_shares)
+                    }),
+            )?;
+            write_csv(
+                &output.join("companies.csv"),
+                "company_id,price,lowest_price,highest_price,balance,expected_profit,news",
+                companies.save().iter().map(|company| {
+                    format!(
+                        "{},{},{},{},{},{},{}",
+                        company.id,
+                        company.market_value.current_price,
+                        company.market_value.lowest_price,
+                        company.market_value.highest_price,
+                        company.balance,
+                        company.expected_profit,
+                        company.news
+                    )
+                }),
+            )?;
+            write_csv(
+                &output.join("ledger.csv"),
+                "tick,kind,buyer_id,seller_id,company_id,number_of_shares,strike_price",
+                ledger.records().iter().map(|record| match record {
+                    LedgerRecord::Trade {
+                        tick, transaction, ..
+                    } => format!(
+                        "{},trade,{},{},{},{},{}",
+                        tick,
+                        transaction.buyer_id,
+                        transaction.seller_id,
+                        transaction.company_id,
+                        transaction.number_of_shares,
+                        transaction.strike_price
+                    ),
+                    // the seller is the company itself
+                    LedgerRecord::CompanyTrade { tick, transaction } => format!(
+                        "{},company,{},,{},{},{}",
+                        tick,
+                        transaction.buyer_agent_id,
+                        transaction.seller_company_id,
+                        transaction.number_of_shares,
+                        transaction.strike_price
+                    ),
+                }),
+            )?;
+        }
+    }
+    writeln!(out, "Exported the world to {}", args.output)?;
+    Ok(())
+}
+
+fn replay(args: ReplayArgs, out: &mut impl Write) -> Result<(), CliError> {
+    let ledger = DataDir::new(&args.data_dir).read_ledger()?;
+    let ticks = args.from.unwrap_or(0)..=args.to.unwrap_or(u64::MAX);
+    // number of trades, shares traded and last price of every company
+    let mut summaries: BTreeMap<u64, (u64, u64, f64)> = BTreeMap::new();
+    for record in ledger.by_tick_range(ticks).filter(|record| {
+        args.company
+            .is_none_or(|company_id| record.company_id() == company_id)
+    }) {
+        writeln!(out, "{}", record)?;
+        let summary = summaries.entry(record.company_id()).or_default();
+        summary.0 += 1;
+        summary.1 += record.number_of_shares();
+        summary.2 = record.strike_price();
+    }
+    for (company_id, (trade_count, volume, last_price)) in summaries {
+        writeln!(
+            out,
+            "company {}: {} trades, {} shares, last price {}",
+            company_id, trade_count, volume, last_price
+        )?;
+    }
+    Ok(())
+}
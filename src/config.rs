@@ -0,0 +1,251 @@
+use crate::{
+    entities::companies::{
+        MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION,
+        MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION,
+    },
+    market::MARKET_VALUE_RESOLUTION,
+    rng::fnv1a,
+    simulation::Cadences,
+    strategies::StrategyShare,
+    DeserializationError, SerializationError, EQUITY_HISTORY_SIZE, MIN_STRIKE_PRICE, NUM_OF_AGENTS,
+    NUM_OF_COMPANIES, OFFER_LIFETIME, TIMELINE_SIZE_LIMIT,
+};
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+
+/// Everything an experiment is set up with, usually loaded from a YAML file.
+/// Whatever the file leaves out keeps its default.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Master seed of every random number stream, a random one is picked if there's none
+    pub seed: Option<u64>,
+    pub world: WorldConfig,
+    pub cadences: Cadences,
+    pub market: MarketConfig,
+    pub agents: AgentsConfig,
+    pub companies: CompaniesConfig,
+}
+
+/// Size of a freshly generated world
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    pub num_of_agents: u64,
+    pub num_of_companies: u64,
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            num_of_agents: NUM_OF_AGENTS,
+            num_of_companies: NUM_OF_COMPANIES,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarketConfig {
+    /// Agents never put in orders below this price
+    pub min_strike_price: f64,
+    /// Number of ticks limit offers rest in the order book
+    pub offer_lifetime: u64,
+    /// How much worse than their strike price agents sending loose orders are willing
+    /// to trade, see `Strategy::loose_orders`
+    pub acceptable_strike_price_deviation: f64,
+    /// Chance of a loose buy order going to the lots of the company when it has any
+    pub lot_acceptance_probability: f64,
+    /// Length of the candles in ticks, the one market values are taken from has to be there
+    pub candle_resolutions: Vec<u64>,
+    /// Number of candles kept for every company and resolution
+    pub candle_history_size: usize,
+    /// Number of trades the rolling statistics of every company are taken over
+    pub statistics_window_size: usize,
+}
+
+impl Default for MarketConfig {
+    fn default() -> Self {
+        Self {
+            min_strike_price: MIN_STRIKE_PRICE,
+            offer_lifetime: OFFER_LIFETIME,
+            acceptable_strike_price_deviation: 5.0,
+            lot_acceptance_probability: 0.6,
+            candle_resolutions: vec![1, MARKET_VALUE_RESOLUTION, 100],
+            candle_history_size: 1_000,
+            statistics_window_size: 100,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentsConfig {
+    /// Most preferences an agent remembers
+    pub timeline_size_limit: usize,
+    /// Chance of an agent selling what it would like to buy and the other way around
+    pub contrarian_probability: f64,
+    /// Number of times the equity of every agent is remembered, it's recorded
+    /// whenever market values are updated. None are with 0
+    pub equity_history_size: usize,
+    /// The strategies agents trade with, and the share of the agents given each of them
+    pub strategies: Vec<StrategyShare>,
+}
+
+impl Default for AgentsConfig {
+    fn default() -> Self {
+        Self {
+            timeline_size_limit: TIMELINE_SIZE_LIMIT,
+            contrarian_probability: 0.05,
+            equity_history_size: EQUITY_HISTORY_SIZE,
+            strategies: vec![StrategyShare::default()],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CompaniesConfig {
+    /// News above this profit percentage makes a company hyped
+    pub min_profit_percent_for_positive_hype: f64,
+    /// News below this profit percentage makes a company hyped
+    pub max_profit_percent_for_negative_hype: f64,
+    /// Chance of a company re-releasing its lots with every news release
+    pub lot_release_probability: f64,
+}
+
+impl Default for CompaniesConfig {
+    fn default() -> Self {
+        Self {
+            min_profit_percent_for_positive_hype:
+                MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION,
+            max_profit_percent_for_negative_hype:
+                MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION,
+            lot_release_probability: 0.1,
+        }
+    }
+}
+
+fn invalid(reason: &str) -> DeserializationError {
+    DeserializationError::InvalidConfig(reason.to_string())
+}
+
+fn is_probability(probability: f64) -> bool {
+    (0.0..=1.0).contains(&probability)
+}
+
+impl Config {
+    pub fn from_yaml(yaml: &str) -> Result<Self, DeserializationError> {
+        let config: Self = serde_yaml::from_str(yaml)
+            .map_err(|e| DeserializationError::InvalidConfig(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, DeserializationError> {
+        let Ok(yaml) = std::fs::read_to_string(file_path) else {
+            return Err(DeserializationError::FileNotFound);
+        };
+        Self::from_yaml(&yaml)
+    }
+
+    pub fn to_yaml(&self) -> Result<String, SerializationError> {
+        serde_yaml::to_string(self).map_err(|_| SerializationError::FailedToSerialize)
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), SerializationError> {
+        std::fs::write(file_path, self.to_yaml()?).map_err(|_| SerializationError::FailedToWrite)
+    }
+
+    /// Fingerprint of the config, saved in snapshots to tell which config a world was simulated with
+    pub fn hash(&self) -> u64 {
+        fnv1a(&bincode::serialize(self).unwrap_or_default())
+    }
+
+    /// Picks a random seed if there's none, so the run can be repeated with it
+    pub fn resolve_seed(&mut self) -> u64 {
+        *self.seed.get_or_insert_with(|| thread_rng().gen())
+    }
+
+    /// Checks that the simulation can run with the config
+    pub fn validate(&self) -> Result<(), DeserializationError> {
+        if self.world.num_of_companies == 0 {
+            return Err(invalid("world.num_of_companies has to be at least 1"));
+        }
+        let market = &self.market;
+        if !(market.min_strike_price.is_finite() && market.min_strike_price > 0.0) {
+            return Err(invalid("market.min_strike_price has to be positive"));
+        }
+        if market.offer_lifetime == 0 {
+            return Err(invalid("market.offer_lifetime has to be at least 1"));
+        }
+        if !(market.acceptable_strike_price_deviation.is_finite()
+            && market.acceptable_strike_price_deviation >= 0.0)
+        {
+            return Err(invalid(
+                "market.acceptable_strike_price_deviation can't be negative",
+            ));
+        }
+        if !is_probability(market.lot_acceptance_probability) {
+            return Err(invalid(
+                "market.lot_acceptance_probability has to be between 0 and 1",
+            ));
+        }
+        if !market.candle_resolutions.contains(&MARKET_VALUE_RESOLUTION) {
+            return Err(DeserializationError::InvalidConfig(format!(
+                "market.candle_resolutions has to include {}, market values are taken from it",
+                MARKET_VALUE_RESOLUTION
+            )));
+        }
+        if market.candle_history_size == 0 {
+            return Err(invalid("market.candle_history_size has to be at least 1"));
+        }
+        if market.statistics_window_size == 0 {
+            return Err(invalid(
+                "market.statistics_window_size has to be at least 1",
+            ));
+        }
+        if self.agents.timeline_size_limit == 0 {
+            return Err(invalid("agents.timeline_size_limit has to be at least 1"));
+        }
+        if !is_probability(self.agents.contrarian_probability) {
+            return Err(invalid(
+                "agents.contrarian_probability has to be between 0 and 1",
+            ));
+        }
+        let strategies = &self.agents.strategies;
+        if strategies.iter().any(|strategy_share| {
+            !(strategy_share.share.is_finite() && strategy_share.share >= 0.0)
+        }) {
+            return Err(invalid("agents.strategies can't have negative shares"));
+        }
+        if strategies
+            .iter()
+            .map(|strategy_share| strategy_share.share)
+            .sum::<f64>()
+            <= 0.0
+        {
+            return Err(invalid(
+                "agents.strategies has to give some agents a strategy",
+            ));
+        }
+        for strategy_share in strategies.iter() {
+            strategy_share.strategy.validate(self)?;
+        }
+        let companies = &self.companies;
+        if (companies.max_profit_percent_for_negative_hype
+            ..=companies.min_profit_percent_for_positive_hype)
+            .is_empty()
+        {
+            return Err(invalid(
+                "companies.max_profit_percent_for_negative_hype can't be above companies.min_profit_percent_for_positive_hype",
+            ));
+        }
+        if !is_probability(companies.lot_release_probability) {
+            return Err(invalid(
+                "companies.lot_release_probability has to be between 0 and 1",
+            ));
+        }
+        Ok(())
+    }
+}
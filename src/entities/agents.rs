@@ -1,12 +1,19 @@
 use crate::{
-    entities::{companies::Companies, Balances},
-    trade_house::{FailedOffer, StockOption, Trade, TradeAction},
+    entities::{
+        companies::Companies,
+        escrow::{Escrow, Reservation},
+        portfolio::{Portfolios, Position},
+        Balances,
+    },
+    log,
+    logger::Log,
+    trade_house::{ExpiredOffers, OrderType, StockOption, Trade, TradeAction},
     transaction::{TodoTransaction, Transaction},
     SimulationError, NUM_OF_AGENTS, TIMELINE_SIZE_LIMIT,
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 fn combine(a: u64, b: u64) -> u128 {
     (a as u128) << 64 | b as u128
@@ -21,10 +28,10 @@ fn get_second(a: u128) -> u64 {
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
-pub struct AgentHoldings(pub HashMap<u64, u64>);
+pub struct AgentHoldings(pub BTreeMap<u64, u64>);
 
-#[derive(Debug, Clone, Default)]
-pub struct Holdings(HashMap<u128, u64>);
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Holdings(BTreeMap<u128, u64>);
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Timeline {
@@ -35,16 +42,39 @@ pub struct Timeline {
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AgentPreferences(Timeline);
 
-#[derive(Debug, Clone, Default)]
-pub struct Preferences(pub Vec<Timeline>);
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Preferences {
+    pub timelines: Vec<Timeline>,
+    /// Most preferences a timeline keeps, the oldest ones get overwritten
+    pub size_limit: usize,
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            timelines: Vec::new(),
+            size_limit: TIMELINE_SIZE_LIMIT,
+        }
+    }
+}
 
-#[derive(Default)]
+#[derive(Serialize, Deserialize, Default)]
 pub struct Agents {
     pub num_of_agents: u64,
     pub holdings: Holdings,
     pub balances: Balances,
     pub preferences: Preferences,
-    pub try_offers: HashMap<u128, f64>,
+    pub try_offers: BTreeMap<u128, f64>,
+    /// What is put up for the orders that are not resolved yet
+    pub escrow: Escrow,
+    /// The strategy every agent trades with, by id. Agents without one trade with
+    /// the first strategy. They're saved in a snapshot section of their own
+    #[serde(skip)]
+    pub strategy_ids: Vec<u64>,
+    /// What every agent paid for its shares and what it owns was worth over time.
+    /// They're saved in a snapshot section of their own
+    #[serde(skip)]
+    pub portfolios: Portfolios,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -82,11 +112,23 @@ impl Holdings {
     pub fn get(&self, agent_id: u64, company_id: u64) -> u64 {
         self.0
             .get(&combine(agent_id, company_id))
-            .map(|share_count| *share_count)
+            .copied()
             .unwrap_or(0)
     }
+    /// Every holding as `(agent_id, company_id, number_of_shares)`
+    pub fn iter(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.0
+            .iter()
+            .map(|(id, number_of_shares)| (get_first(*id), get_second(*id), *number_of_shares))
+    }
+    /// Every holding of the agent as `(company_id, number_of_shares)`
+    pub fn of_agent(&self, agent_id: u64) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.0
+            .range(combine(agent_id, 0)..=combine(agent_id, u64::MAX))
+            .map(|(id, number_of_shares)| (get_second(*id), *number_of_shares))
+    }
     pub fn get_u128(&self, id: u128) -> u64 {
-        self.0.get(&id).map(|share_count| *share_count).unwrap_or(0)
+        self.0.get(&id).copied().unwrap_or(0)
     }
     pub fn push_from_txn(&mut self, target_agent_id: u64, transaction: &Transaction) {
         self.0
@@ -149,18 +191,18 @@ impl Timeline {
             target_index: 0,
         }
     }
-    pub fn add(&mut self, data: &[(u64, TradeAction)]) {
-        if self.data.len() == TIMELINE_SIZE_LIMIT {
+    pub fn add(&mut self, data: &[(u64, TradeAction)], size_limit: usize) {
+        if self.data.len() >= size_limit {
             for (i, data_item) in data.iter().enumerate().take(self.data.len()) {
-                self.data[(i + self.target_index) % TIMELINE_SIZE_LIMIT] = *data_item;
+                self.data[(i + self.target_index) % size_limit] = *data_item;
             }
             return;
         }
-        if (data.len() + self.data.len()) <= TIMELINE_SIZE_LIMIT {
+        if (data.len() + self.data.len()) <= size_limit {
             self.data.extend(data.iter());
             return;
         }
-        let extend_size = TIMELINE_SIZE_LIMIT - self.data.len();
+        let extend_size = size_limit - self.data.len();
         let destination_index = data.len() - extend_size;
 
         self.data.extend(data[0..extend_size].iter());
@@ -200,10 +242,13 @@ impl Preferences {
         company_id: u64,
         preference: u64,
     ) -> Result<(), SimulationError> {
-        let Some(timeline) = self.0.get_mut(agent_id as usize) else {
+        let Some(timeline) = self.timelines.get_mut(agent_id as usize) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
-        timeline.add(&vec![(company_id, TradeAction::Buy); preference as usize]);
+        timeline.add(
+            &vec![(company_id, TradeAction::Buy); preference as usize],
+            self.size_limit,
+        );
         Ok(())
     }
     pub fn sub(
@@ -212,10 +257,13 @@ impl Preferences {
         company_id: u64,
         preference: u64,
     ) -> Result<(), SimulationError> {
-        let Some(timeline) = self.0.get_mut(agent_id as usize) else {
+        let Some(timeline) = self.timelines.get_mut(agent_id as usize) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
-        timeline.add(&vec![(company_id, TradeAction::Sell); preference as usize]);
+        timeline.add(
+            &vec![(company_id, TradeAction::Sell); preference as usize],
+            self.size_limit,
+        );
         Ok(())
     }
     pub fn get_preferred_random(
@@ -223,7 +271,7 @@ impl Preferences {
         agent_id: u64,
         rng: &mut impl Rng,
     ) -> Result<(u64, TradeAction), SimulationError> {
-        let Some(agent) = self.0.get(agent_id as usize) else {
+        let Some(agent) = self.timelines.get(agent_id as usize) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
         agent.get_rng(rng)
@@ -250,14 +298,20 @@ impl Agents {
             num_of_agents,
             balances: Balances(balances),
             holdings,
-            preferences: Preferences(preferences),
-            try_offers: HashMap::new(),
+            preferences: Preferences {
+                timelines: preferences,
+                ..Default::default()
+            },
+            try_offers: BTreeMap::new(),
+            escrow: Escrow::new(),
+            strategy_ids: Vec::new(),
+            portfolios: Portfolios::new(),
         }
     }
     pub fn save(&self) -> Result<Vec<Agent>, SimulationError> {
         let mut agents = Vec::with_capacity(self.num_of_agents as usize);
         for i in 0..self.num_of_agents {
-            let Some(preference_data) = self.preferences.0.get(i as usize) else {
+            let Some(preference_data) = self.preferences.timelines.get(i as usize) else {
                 return Err(SimulationError::NoData);
             };
             agents.push(Agent {
@@ -294,24 +348,29 @@ impl Agents {
     where
         F: FnMut(u64) -> usize,
     {
-        let Some(company_preferences) = self.preferences.0.get_mut(agent_id as usize) else {
+        let size_limit = self.preferences.size_limit;
+        let Some(company_preferences) = self.preferences.timelines.get_mut(agent_id as usize)
+        else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
         for company_id in 0..num_of_companies {
-            company_preferences.add(&vec![
-                (company_id, TradeAction::Buy);
-                preferences(company_id)
-            ]);
+            company_preferences.add(
+                &vec![(company_id, TradeAction::Buy); preferences(company_id)],
+                size_limit,
+            );
         }
         Ok(())
     }
     pub fn rand_give_preferences(
         &mut self,
-        mut rng: impl Rng + Clone,
+        mut rng: impl Rng,
         num_of_companies: u64,
     ) -> Result<(), SimulationError> {
-        let preference = move |_: u64, _: u64| rng.gen_range(0..100);
-        self.give_preferences(preference, num_of_companies)
+        // a single stream for every agent, cloning it would give them all the same preferences
+        for agent_id in 0..self.num_of_agents {
+            self.rand_set_preferences_for_all_companies(&mut rng, agent_id, num_of_companies)?;
+        }
+        Ok(())
     }
     pub fn give_preferences<F>(
         &mut self,
@@ -328,33 +387,49 @@ impl Agents {
         }
         Ok(())
     }
+    /// Gives every agent a preference drawn from the news,
+    /// returning them in order of the agents' ids
     pub fn rand_give_preferences_from_news(
         &mut self,
         rng: &mut impl Rng,
         news_dependent_company_id_probability_distribution: &[(u64, TradeAction)],
-    ) {
-        for agent_id in 0..self.num_of_agents {
-            let (company_id, action) = news_dependent_company_id_probability_distribution
-                [rng.gen_range(0..news_dependent_company_id_probability_distribution.len())];
-            self.preferences.0[agent_id as usize].add(&vec![(company_id, action)]);
+    ) -> Vec<(u64, TradeAction)> {
+        let preferences: Vec<(u64, TradeAction)> = (0..self.num_of_agents)
+            .map(|_| {
+                news_dependent_company_id_probability_distribution
+                    [rng.gen_range(0..news_dependent_company_id_probability_distribution.len())]
+            })
+            .collect();
+        self.give_preferences_from_news(&preferences);
+        preferences
+    }
+    /// Adds a preference to the timeline of every agent, in order of their ids
+    pub fn give_preferences_from_news(&mut self, preferences: &[(u64, TradeAction)]) {
+        let size_limit = self.preferences.size_limit;
+        for (timeline, preference) in self.preferences.timelines.iter_mut().zip(preferences) {
+            timeline.add(&[*preference], size_limit);
         }
     }
     pub fn rand_introduce_new_agents(
         &mut self,
-        mut rng: impl Rng + Clone,
+        mut rng: impl Rng,
         mut rng2: impl Rng,
         num_of_agents_to_introduce: u64,
         num_of_companies: u64,
     ) -> Result<(), SimulationError> {
-        let preference = move |_: u64, _: u64| rng.gen_range(0..100);
+        let first_new_agent_id = self.num_of_agents;
         self.introduce_new_agents(
-            preference,
+            |_, _| 0,
             &mut (0..num_of_agents_to_introduce)
                 .map(|_| rng2.gen_range(1000.0..1_000_000.0))
                 .collect(),
             num_of_agents_to_introduce,
             num_of_companies,
-        )
+        )?;
+        for agent_id in first_new_agent_id..self.num_of_agents {
+            self.rand_set_preferences_for_all_companies(&mut rng, agent_id, num_of_companies)?;
+        }
+        Ok(())
     }
     pub fn introduce_new_agents<F>(
         &mut self,
@@ -371,7 +446,7 @@ impl Agents {
         }
         self.balances.0.append(new_balances);
         self.preferences
-            .0
+            .timelines
             .extend((0..num_of_agents_to_introduce).map(|_| Timeline::new()));
         for i in self.num_of_agents..(self.num_of_agents + num_of_agents_to_introduce) {
             let mut pref_clone = preferences.clone();
@@ -381,10 +456,45 @@ impl Agents {
         self.num_of_agents += num_of_agents_to_introduce;
         Ok(())
     }
+    /// Splits the agents between the strategies in proportion to their shares,
+    /// in order of their ids
+    pub fn assign_strategies(&mut self, shares: &[f64]) {
+        let total_share: f64 = shares.iter().sum();
+        let num_of_agents = self.num_of_agents as f64;
+        self.strategy_ids = (0..self.num_of_agents)
+            .map(|agent_id| {
+                let position = (agent_id as f64 + 0.5) / num_of_agents * total_share;
+                let mut cumulative_share = 0.0;
+                shares
+                    .iter()
+                    .position(|share| {
+                        cumulative_share += share;
+                        position < cumulative_share
+                    })
+                    .unwrap_or(shares.len().saturating_sub(1)) as u64
+            })
+            .collect();
+    }
+    pub fn strategy_id(&self, agent_id: u64) -> u64 {
+        self.strategy_ids
+            .get(agent_id as usize)
+            .copied()
+            .unwrap_or_default()
+    }
+    pub fn set_strategy(&mut self, agent_id: u64, strategy_id: u64) -> Result<(), SimulationError> {
+        if agent_id >= self.num_of_agents {
+            return Err(SimulationError::AgentNotFound(agent_id));
+        }
+        if self.strategy_ids.len() <= agent_id as usize {
+            self.strategy_ids.resize(agent_id as usize + 1, 0);
+        }
+        self.strategy_ids[agent_id as usize] = strategy_id;
+        Ok(())
+    }
     pub fn create_agents(&mut self, num_of_agents: u64) -> Vec<u64> {
         self.balances.0.extend((0..num_of_agents).map(|_| 0.0));
         self.preferences
-            .0
+            .timelines
             .extend((0..num_of_agents).map(|_| Timeline::new()));
         self.num_of_agents += num_of_agents;
         ((self.num_of_agents - num_of_agents)..self.num_of_agents).collect()
@@ -437,31 +547,22 @@ impl Agents {
                 strike_price: price,
                 action,
                 trade: attempting_trade.clone(),
+                order_type: OrderType::Limit,
             });
         }
         Ok(())
     }
     pub fn alert_agents(
         &mut self,
-        expired_trades: &HashMap<u64, Vec<FailedOffer<Trade>>>,
-        expired_options: &HashMap<u64, Vec<FailedOffer<StockOption>>>,
+        expired_trades: &ExpiredOffers<Trade>,
+        expired_options: &ExpiredOffers<StockOption>,
     ) -> Result<(), SimulationError> {
         for (company_id, offers) in expired_trades.iter() {
             for offer in offers.iter() {
-                // refund
-                if offer.1 == TradeAction::Sell {
-                    self.holdings.push(
-                        offer.0.lifetime,
-                        *company_id,
-                        offer.0.data.number_of_shares,
-                    );
-                } else {
-                    self.balances.add(
-                        offer.0.offerer_id,
-                        offer.0.strike_price * (offer.0.data.number_of_shares as f64),
-                    )?;
+                // the other offers still have to be refunded
+                if let Err(e) = self.refund_whole_order(offer.0.id) {
+                    log!(warn "Failed to refund the expired offer {}\n{:?}", offer.0.id, e);
                 }
-
                 self.add_failed_offer(
                     *company_id,
                     offer.0.offerer_id,
@@ -472,20 +573,7 @@ impl Agents {
         }
         for (company_id, offers) in expired_options.iter() {
             for offer in offers {
-                // refund
-                if offer.1 == TradeAction::Sell {
-                    self.holdings.push(
-                        offer.0.lifetime,
-                        *company_id,
-                        offer.0.data.number_of_shares,
-                    );
-                } else {
-                    self.balances.add(
-                        offer.0.offerer_id,
-                        offer.0.strike_price * (offer.0.data.number_of_shares as f64),
-                    )?;
-                }
-
+                // nothing is put up for option offers
                 self.add_failed_offer(
                     *company_id,
                     offer.0.offerer_id,
@@ -537,35 +625,321 @@ impl Agents {
         self.holdings.push(agent_id, company_id, holding_to_add);
         Ok(())
     }
+    /// Puts up what the transaction needs in escrow, returning the id of its order
     pub fn deduct_assets_from_todotransaction(
         &mut self,
         todo_transaction: &TodoTransaction,
+    ) -> Result<u64, SimulationError> {
+        self.reserve(Reservation {
+            agent_id: todo_transaction.agent_id,
+            company_id: todo_transaction.company_id,
+            action: todo_transaction.action,
+            strike_price: todo_transaction.strike_price,
+            number_of_shares: todo_transaction.trade.number_of_shares,
+        })
+    }
+    /// Takes what the reservation covers from the agent and holds it in escrow,
+    /// returning the id of its order
+    pub fn reserve(&mut self, reservation: Reservation) -> Result<u64, SimulationError> {
+        match reservation.action {
+            TradeAction::Buy => self
+                .balances
+                .add(reservation.agent_id, -reservation.cash())?,
+            TradeAction::Sell => self.holdings.pop(
+                reservation.agent_id,
+                reservation.company_id,
+                reservation.number_of_shares,
+            )?,
+        }
+        Ok(self.escrow.reserve(reservation))
+    }
+    /// Puts up more shares for an order, at the strike price it was made with
+    pub fn top_up_order(
+        &mut self,
+        order_id: u64,
+        number_of_shares: u64,
     ) -> Result<(), SimulationError> {
-        if todo_transaction.action == TradeAction::Sell {
-            self.holdings.pop(
-                todo_transaction.agent_id,
-                todo_transaction.company_id,
-                todo_transaction.trade.number_of_shares,
-            )?;
-            return Ok(());
+        let Some(reservation) = self.escrow.get(order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        let mut top_up = reservation.clone();
+        top_up.number_of_shares = number_of_shares;
+        match top_up.action {
+            TradeAction::Buy => self.balances.add(top_up.agent_id, -top_up.cash())?,
+            TradeAction::Sell => {
+                self.holdings
+                    .pop(top_up.agent_id, top_up.company_id, number_of_shares)?
+            }
         }
-        self.balances.add(
-            todo_transaction.agent_id,
-            -(todo_transaction.strike_price * (todo_transaction.trade.number_of_shares as f64)),
-        )?;
+        self.escrow.top_up(order_id, number_of_shares)
+    }
+    /// Gives back what was put up for `number_of_shares` of an order
+    pub fn refund_order(
+        &mut self,
+        order_id: u64,
+        number_of_shares: u64,
+    ) -> Result<(), SimulationError> {
+        let refund = self.escrow.take(order_id, number_of_shares)?;
+        self.give_back(&refund)
+    }
+    /// Gives back everything that is still put up for an order
+    pub fn refund_whole_order(&mut self, order_id: u64) -> Result<(), SimulationError> {
+        let refund = self.escrow.take_all(order_id)?;
+        self.give_back(&refund)
+    }
+    /// Whether the agent could put up what the order would need at the strike price
+    /// and shares, counting what is already put up for it
+    pub fn can_amend_order(
+        &self,
+        order_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    ) -> Result<(), SimulationError> {
+        let Some(reservation) = self.escrow.get(order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        let affordable = match reservation.action {
+            TradeAction::Buy => {
+                self.balances.get(reservation.agent_id)? + reservation.cash()
+                    >= strike_price * number_of_shares as f64
+            }
+            TradeAction::Sell => {
+                self.holdings
+                    .get(reservation.agent_id, reservation.company_id)
+                    + reservation.number_of_shares
+                    >= number_of_shares
+            }
+        };
+        if !affordable {
+            return Err(SimulationError::Unspendable);
+        }
+        Ok(())
+    }
+    /// Changes the strike price and shares of an order,
+    /// putting up the difference or giving it back
+    pub fn amend_order(
+        &mut self,
+        order_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    ) -> Result<(), SimulationError> {
+        let Some(reservation) = self.escrow.get(order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        let mut amended = reservation.clone();
+        amended.strike_price = strike_price;
+        amended.number_of_shares = number_of_shares;
+        match amended.action {
+            TradeAction::Buy => self
+                .balances
+                .add(amended.agent_id, reservation.cash() - amended.cash())?,
+            TradeAction::Sell if number_of_shares > reservation.number_of_shares => {
+                self.holdings.pop(
+                    amended.agent_id,
+                    amended.company_id,
+                    number_of_shares - reservation.number_of_shares,
+                )?
+            }
+            TradeAction::Sell => self.holdings.push(
+                amended.agent_id,
+                amended.company_id,
+                reservation.number_of_shares - number_of_shares,
+            ),
+        }
+        self.escrow
+            .replace(order_id, strike_price, number_of_shares)?;
         Ok(())
     }
+    /// Brings what is put up for a buy order down to the strike price it waits
+    /// in the order book at, giving back the difference
+    pub fn reprice_order(
+        &mut self,
+        order_id: u64,
+        strike_price: f64,
+    ) -> Result<(), SimulationError> {
+        let Some(reservation) = self.escrow.get(order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        if reservation.action != TradeAction::Buy || reservation.strike_price == strike_price {
+            return Ok(());
+        }
+        let number_of_shares = reservation.number_of_shares;
+        self.amend_order(order_id, strike_price, number_of_shares)
+    }
+    /// Gives the shares bought with a reservation to its agent,
+    /// returning the money that was paid for them
+    pub fn settle_order(
+        &mut self,
+        order_id: u64,
+        number_of_shares: u64,
+    ) -> Result<f64, SimulationError> {
+        let bought = self.escrow.take(order_id, number_of_shares)?;
+        self.holdings
+            .push(bought.agent_id, bought.company_id, bought.number_of_shares);
+        self.portfolios.buy(
+            bought.agent_id,
+            bought.company_id,
+            bought.number_of_shares,
+            bought.strike_price,
+        );
+        Ok(bought.cash())
+    }
+    fn give_back(&mut self, reservation: &Reservation) -> Result<(), SimulationError> {
+        match reservation.action {
+            TradeAction::Buy => self.balances.add(reservation.agent_id, reservation.cash()),
+            TradeAction::Sell => {
+                self.holdings.push(
+                    reservation.agent_id,
+                    reservation.company_id,
+                    reservation.number_of_shares,
+                );
+                Ok(())
+            }
+        }
+    }
+    /// Settles a transaction between two orders out of their reservations.
+    /// The buyer gets back the difference if the shares were cheaper than its strike price.
+    /// Both reservations are checked before either is touched, and shares priced
+    /// above what the buyer put up for them are `Unspendable`
     pub fn exchange_assets_from_transaction(
         &mut self,
         transaction: &Transaction,
     ) -> Result<(), SimulationError> {
-        // seller's holdings and buyer's money are resolved at the time of offering
+        let covered = |order_id: u64| {
+            let reservation = self
+                .escrow
+                .get(order_id)
+                .ok_or(SimulationError::OfferNotFound(order_id))?;
+            if reservation.number_of_shares < transaction.number_of_shares {
+                return Err(SimulationError::Unspendable);
+            }
+            Ok(reservation)
+        };
+        if covered(transaction.buyer_order_id)?.strike_price < transaction.strike_price {
+            return Err(SimulationError::Unspendable);
+        }
+        covered(transaction.seller_order_id)?;
+        self.balances.get(transaction.buyer_id)?;
+
+        let bought = self
+            .escrow
+            .take(transaction.buyer_order_id, transaction.number_of_shares)?;
+        let sold = self
+            .escrow
+            .take(transaction.seller_order_id, transaction.number_of_shares)?;
+        let price = transaction.strike_price * (transaction.number_of_shares as f64);
         self.holdings
             .push_from_txn(transaction.buyer_id, transaction);
-        self.balances.add(
-            transaction.seller_id,
-            transaction.strike_price * (transaction.number_of_shares as f64),
-        )?;
+        self.balances
+            .add(transaction.buyer_id, bought.cash() - price)?;
+        self.balances.add(sold.agent_id, price)?;
+        self.portfolios.buy(
+            transaction.buyer_id,
+            transaction.company_id,
+            transaction.number_of_shares,
+            transaction.strike_price,
+        );
+        self.portfolios.sell(
+            sold.agent_id,
+            transaction.company_id,
+            transaction.number_of_shares,
+            transaction.strike_price,
+        );
         Ok(())
     }
+    /// The shares of every company every agent owns, including the ones put up for its orders
+    fn owned_shares(&self) -> BTreeMap<(u64, u64), u64> {
+        let mut owned_shares = BTreeMap::new();
+        for (agent_id, company_id, number_of_shares) in self.holdings.iter() {
+            *owned_shares.entry((agent_id, company_id)).or_default() += number_of_shares;
+        }
+        for (_, reservation) in self.escrow.iter() {
+            *owned_shares
+                .entry((reservation.agent_id, reservation.company_id))
+                .or_default() += reservation.shares();
+        }
+        owned_shares
+    }
+    /// Opens positions for the shares agents own but never bought, like the ones they
+    /// were given or had before positions were kept, at the current price of their company
+    pub fn open_positions(&mut self, companies: &Companies) {
+        for ((agent_id, company_id), number_of_shares) in self.owned_shares() {
+            let number_of_shares_in_position = self
+                .portfolios
+                .position(agent_id, company_id)
+                .map(Position::number_of_shares)
+                .unwrap_or_default();
+            if number_of_shares > number_of_shares_in_position {
+                self.portfolios.buy(
+                    agent_id,
+                    company_id,
+                    number_of_shares - number_of_shares_in_position,
+                    companies.get_current_price(company_id).unwrap_or_default(),
+                );
+            }
+        }
+    }
+    pub fn position(&self, agent_id: u64, company_id: u64) -> Option<&Position> {
+        self.portfolios.position(agent_id, company_id)
+    }
+    /// Profit the agent made on every share it sold
+    pub fn realized_profit(&self, agent_id: u64) -> f64 {
+        self.portfolios
+            .positions(agent_id)
+            .map(|(_, position)| position.realized_profit)
+            .sum()
+    }
+    /// Profit the agent would make selling every share it owns at the current prices
+    pub fn unrealized_profit(&self, agent_id: u64, companies: &Companies) -> f64 {
+        self.portfolios
+            .positions(agent_id)
+            .map(|(company_id, position)| {
+                position
+                    .unrealized_profit(companies.get_current_price(company_id).unwrap_or_default())
+            })
+            .sum()
+    }
+    /// What the agent's money and shares are worth at the current prices,
+    /// including what is put up for its orders
+    pub fn equity(&self, agent_id: u64, companies: &Companies) -> Result<f64, SimulationError> {
+        let price = |company_id| companies.get_current_price(company_id).unwrap_or_default();
+        let mut equity = self.balances.get(agent_id)?;
+        for (company_id, number_of_shares) in self.holdings.of_agent(agent_id) {
+            equity += price(company_id) * number_of_shares as f64;
+        }
+        for (_, reservation) in self.escrow.iter() {
+            if reservation.agent_id == agent_id {
+                equity += reservation.cash()
+                    + price(reservation.company_id) * reservation.shares() as f64;
+            }
+        }
+        Ok(equity)
+    }
+    /// The equity of every agent, by id
+    pub fn equities(&self, companies: &Companies) -> Vec<f64> {
+        let mut equities = self.balances.0.clone();
+        for (_, reservation) in self.escrow.iter() {
+            if let Some(equity) = equities.get_mut(reservation.agent_id as usize) {
+                *equity += reservation.cash();
+            }
+        }
+        for ((agent_id, company_id), number_of_shares) in self.owned_shares() {
+            if let Some(equity) = equities.get_mut(agent_id as usize) {
+                *equity += companies.get_current_price(company_id).unwrap_or_default()
+                    * number_of_shares as f64;
+            }
+        }
+        equities
+    }
+    pub fn record_equity(&mut self, tick: u64, companies: &Companies) {
+        let equities = self.equities(companies);
+        self.portfolios.record_equity(tick, equities);
+    }
+    /// The equity of the agent at every tick it was recorded at, oldest first
+    pub fn equity_history(&self, agent_id: u64) -> impl Iterator<Item = (u64, f64)> + '_ {
+        self.portfolios
+            .equity_history()
+            .iter()
+            .filter_map(move |record| Some((record.tick, *record.equities.get(agent_id as usize)?)))
+    }
 }
@@ -1,11 +1,16 @@
 use crate::{
-    entities::agents::Agents, log, logger::Log, trade_house::TradeAction,
-    transaction::TodoTransaction, SimulationError,
+    config::CompaniesConfig,
+    entities::{agents::Agents, escrow::Reservation},
+    log,
+    logger::Log,
+    trade_house::TradeAction,
+    transaction::{CompanyTransaction, TodoTransaction},
+    SimulationError,
 };
 use rand::Rng;
 use rand_distr::{Distribution, Normal};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct MarketValue {
@@ -22,8 +27,10 @@ pub struct Lots {
     pub strike_price: f64,
     pub number_of_lots: u64,
     pub lot_size: u64,
-    pub bets: HashMap<u64, u64>,
+    pub bets: BTreeMap<u64, u64>,
     pub total_num_of_bets: u64,
+    /// The order every bettor's money is put up for
+    pub bet_order_ids: BTreeMap<u64, u64>,
 }
 
 pub const SYMBOL_LENGTH: usize = 4;
@@ -31,7 +38,18 @@ pub const MAX_NUM_OF_HYPE_COMPANIES: usize = 2;
 pub const MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION: f64 = 70.0;
 pub const MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION: f64 = -30.0;
 
-#[derive(Default)]
+/// What came out of releasing the news of every company
+#[derive(Debug, Default)]
+pub struct NewsRelease {
+    /// The shares the companies gave out from their lots
+    pub company_transactions: Vec<CompanyTransaction>,
+    /// What the companies made (or lost) in total
+    pub profit: f64,
+    /// The companies that put up new lots
+    pub lots_released: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
 pub struct Companies {
     pub num_of_companies: u64,
     pub market_values: Vec<MarketValue>,
@@ -101,8 +119,9 @@ impl Lots {
             strike_price,
             number_of_lots,
             lot_size,
-            bets: HashMap::new(),
+            bets: BTreeMap::new(),
             total_num_of_bets: 0,
+            bet_order_ids: BTreeMap::new(),
         }
     }
     pub fn is_blank(&self) -> bool {
@@ -121,8 +140,9 @@ impl Lots {
             number_of_lots: rng.gen_range(1..1_000) * 100, // keep it a multiple of 100,
             lot_size: rng.gen_range(1..10) * 10,           // keep it a multiple of 10
             total_num_of_bets: 0,
-            bets: HashMap::new(), // no random bets because agents might not have the money for the bet
-                                  // or be uninterested
+            bets: BTreeMap::new(), // no random bets because agents might not have the money for the bet
+            // or be uninterested
+            bet_order_ids: BTreeMap::new(),
         }
     }
     pub fn rng_reset(&mut self, rng: &mut impl Rng, appox_price: f64) {
@@ -139,22 +159,31 @@ impl Lots {
         self.lot_size = rng.gen_range(1..10) * 10;
         self.total_num_of_bets = 0;
     }
+    /// Puts up the money for the lots in escrow and places the bet
     pub fn add_bet_and_update_agent(
         &mut self,
+        company_id: u64,
         agents: &mut Agents,
         agent_id: u64,
         number_of_lots: u64,
     ) -> Result<(), SimulationError> {
-        if self.is_blank() {
+        if self.is_blank() || number_of_lots == 0 {
             return Ok(());
         }
-        if self.lot_size == 0 {
-            return Ok(());
+        let number_of_shares = self.lot_size * number_of_lots;
+        match self.bet_order_ids.get(&agent_id) {
+            Some(&order_id) => agents.top_up_order(order_id, number_of_shares)?,
+            None => {
+                let order_id = agents.reserve(Reservation {
+                    agent_id,
+                    company_id,
+                    action: TradeAction::Buy,
+                    strike_price: self.strike_price,
+                    number_of_shares,
+                })?;
+                self.bet_order_ids.insert(agent_id, order_id);
+            }
         }
-        agents.balances.add(
-            agent_id,
-            -(self.strike_price * (self.lot_size * number_of_lots) as f64),
-        )?;
         self.bets
             .entry(agent_id)
             .and_modify(|bet| *bet += number_of_lots)
@@ -162,22 +191,11 @@ impl Lots {
         self.total_num_of_bets += number_of_lots;
         Ok(())
     }
-    pub fn add_bet(&mut self, agent_id: u64, number_of_lots: u64) {
-        if self.is_blank() || number_of_lots == 0 {
-            return;
-        }
-        if self.lot_size == 0 {
-            return;
-        }
-        self.bets
-            .entry(agent_id)
-            .and_modify(|bet| *bet += number_of_lots)
-            .or_insert(number_of_lots);
-        self.total_num_of_bets += number_of_lots;
-    }
-    pub fn fits_agent_price(&self, strike_price: f64, acceptable_deviation: f64) -> bool {
-        (self.strike_price - strike_price).abs() < acceptable_deviation
+    /// Whether a buyer willing to pay up to the strike price can afford the lots
+    pub fn fits_agent_price(&self, strike_price: f64) -> bool {
+        self.strike_price <= strike_price
     }
+    /// Takes back part of a bet, refunding what was put up for it
     pub fn remove_bet_and_update_agent(
         &mut self,
         agents: &mut Agents,
@@ -187,86 +205,101 @@ impl Lots {
         if self.is_blank() || number_of_lots == 0 {
             return Ok(());
         }
-        let Some(bet) = self.bets.get_mut(&agent_id) else {
+        let (Some(bet), Some(&order_id)) = (
+            self.bets.get_mut(&agent_id),
+            self.bet_order_ids.get(&agent_id),
+        ) else {
             return Err(SimulationError::AgentNotFound(agent_id));
         };
         if *bet < number_of_lots {
             return Err(SimulationError::Unspendable);
         }
-        agents.balances.add(
-            agent_id,
-            self.strike_price * (self.lot_size * number_of_lots) as f64,
-        )?;
+        agents.refund_order(order_id, self.lot_size * number_of_lots)?;
 
         *bet -= number_of_lots;
         if *bet == 0 {
             self.bets.remove(&agent_id);
+            self.bet_order_ids.remove(&agent_id);
         }
         self.total_num_of_bets -= number_of_lots;
         Ok(())
     }
-    pub fn remove_bet(&mut self, bet: &mut u64, agent_id: u64, number_of_lots: u64) {
-        if self.is_blank() {
-            return;
-        }
-        *bet -= number_of_lots;
-        if *bet == 0 {
-            self.bets.remove(&agent_id);
-        }
-        self.total_num_of_bets -= number_of_lots;
-    }
     pub fn get_bet(&self, agent_id: u64) -> u64 {
-        self.bets.get(&agent_id).unwrap_or(&0).clone()
+        *self.bets.get(&agent_id).unwrap_or(&0)
     }
-    pub fn distribute_shares(&mut self, company_id: u64, agents: &mut Agents) {
-        if self.is_blank() {
-            return;
+    /// Gives out the lots to the biggest bets first, as long as there are lots left.
+    /// The bets that can't be filled get their money back.
+    pub fn distribute_shares(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+    ) -> Result<Vec<CompanyTransaction>, SimulationError> {
+        if self.bets.is_empty() {
+            return Ok(Vec::new());
         }
-        let mut bets = self.bets.iter().collect::<Vec<_>>();
-        bets.sort_by(|a, b| b.1.cmp(a.1));
+        let mut bets = std::mem::take(&mut self.bets)
+            .into_iter()
+            .collect::<Vec<_>>();
+        bets.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
         log!(info "Lot distribution: company_id: {} strike_price: {}", company_id,  self.strike_price);
-        for (&agent_id, &number_of_lots) in bets.iter() {
+        let mut transactions = Vec::new();
+        for (agent_id, number_of_lots) in bets {
+            let Some(order_id) = self.bet_order_ids.remove(&agent_id) else {
+                continue;
+            };
             let number_of_shares = number_of_lots * self.lot_size;
-            print!("({}[{}]), ", agent_id, number_of_shares);
+            log!(info "Lot bet: agent_id: {} number_of_shares: {}", agent_id, number_of_shares);
             if self.number_of_lots < number_of_lots {
+                agents.refund_whole_order(order_id)?;
                 continue;
             }
-            agents
-                .holdings
-                .insert(agent_id, company_id, number_of_shares);
+            agents.settle_order(order_id, number_of_shares)?;
             self.number_of_lots -= number_of_lots;
+            transactions.push(CompanyTransaction::new(
+                agent_id,
+                company_id,
+                number_of_shares,
+                self.strike_price,
+            ));
         }
-        println!();
-        self.bets.clear();
+        self.total_num_of_bets = 0;
+        Ok(transactions)
     }
     pub fn compress_lot_size(&mut self, compress_ratio: f64) -> u64 {
-        let new_lot_size = (self.lot_size as f64 * compress_ratio).round() as u64;
+        let new_lot_size = (self.lot_size as f64 * compress_ratio).floor() as u64;
 
         let refund_difference = self.lot_size - new_lot_size;
         self.lot_size = new_lot_size;
         refund_difference
     }
+    /// When more lots are bet on than there are, the lots are made smaller
+    /// so that every bet can be filled, and the bettors get back
+    /// what they put up for the shares they won't get
     pub fn compress_shares(&mut self, agents: &mut Agents) -> Result<(), SimulationError> {
-        if self.is_blank() {
+        if self.is_blank() || self.total_num_of_bets <= self.number_of_lots {
             return Ok(());
         }
-        let compress_ratio = self.total_num_of_bets as f64 / self.number_of_lots as f64;
-        if compress_ratio.fract() != 0.0 {
+        let compress_ratio = self.number_of_lots as f64 / self.total_num_of_bets as f64;
+        if (self.lot_size as f64 * compress_ratio).floor() == 0.0 {
             return Err(SimulationError::UnDoable);
         }
         let refund_difference = self.compress_lot_size(compress_ratio);
         for (bettor, &number_of_lots) in self.bets.iter() {
-            agents.balances.add(
-                *bettor,
-                (refund_difference * number_of_lots) as f64 * self.strike_price,
-            )?;
+            let Some(&order_id) = self.bet_order_ids.get(bettor) else {
+                continue;
+            };
+            agents.refund_order(order_id, refund_difference * number_of_lots)?;
         }
-        self.strike_price *= compress_ratio;
+        self.number_of_lots = self.total_num_of_bets;
         Ok(())
     }
-    pub fn finalize(&mut self, company_id: u64, agents: &mut Agents) {
+    pub fn finalize(
+        &mut self,
+        company_id: u64,
+        agents: &mut Agents,
+    ) -> Result<Vec<CompanyTransaction>, SimulationError> {
         _ = self.compress_shares(agents); // compress if you can
-        self.distribute_shares(company_id, agents);
+        self.distribute_shares(company_id, agents)
     }
 }
 
@@ -372,40 +405,64 @@ impl Companies {
     pub fn rand_company_id(&self, rng: &mut impl Rng) -> u64 {
         rng.gen_range(0..self.num_of_companies)
     }
-    pub fn rand_release_news(&mut self, agents: &mut Agents, rng: &mut impl Rng) {
+    pub fn rand_release_news(
+        &mut self,
+        agents: &mut Agents,
+        rng: &mut impl Rng,
+        config: &CompaniesConfig,
+    ) -> Result<NewsRelease, SimulationError> {
         let mut hypeable_companies = Vec::new();
+        let mut company_transactions = Vec::new();
+        let mut profit = 0.0;
+        let mut lots_released = Vec::new();
         for id in 0..self.num_of_companies {
             // for now, we distribute shares after news update
-            self.lots[id as usize].finalize(id, agents);
-            if rng.gen_ratio(1, 10) {
-                // 10% chance of re-releasing shares
+            for transaction in self.lots[id as usize].finalize(id, agents)? {
+                self.balances[id as usize] +=
+                    transaction.strike_price * transaction.number_of_shares as f64;
+                company_transactions.push(transaction);
+            }
+            if rng.gen_bool(config.lot_release_probability) {
                 let failable_value = rng.gen_range(10.0..2_000.0);
                 let current_price = self.get_current_price(id).unwrap_or(failable_value);
                 self.lots[id as usize].rng_reset_exact_price(rng, current_price);
+                lots_released.push(id);
             }
 
             let expected_profit = self.expected_profits[id as usize];
             let Ok(normal) = Normal::new(0.0, 100.0 / expected_profit) else {
                 // If the normal distribution fails, we just add the expected profit
                 self.balances[id as usize] += expected_profit;
+                profit += expected_profit;
                 continue;
             };
             let deviation: f64 = normal.sample(rng);
-            let Some(hypeable_news) = self.release_news(id, deviation) else {
+            profit += expected_profit * deviation;
+            let Some(hypeable_news) = self.release_news(id, deviation, config) else {
                 continue;
             };
             hypeable_companies.push((id, hypeable_news));
         }
         self.send_hype(&mut hypeable_companies);
+        Ok(NewsRelease {
+            company_transactions,
+            profit,
+            lots_released,
+        })
     }
-    pub fn release_news(&mut self, company_id: u64, deviation: f64) -> Option<f64> {
+    pub fn release_news(
+        &mut self,
+        company_id: u64,
+        deviation: f64,
+        config: &CompaniesConfig,
+    ) -> Option<f64> {
         let id = company_id as usize;
         let balance = &mut self.balances[id];
         let news = deviation * 100.0;
         *balance += self.expected_profits[id] * deviation;
         self.news[id] = news;
-        if (MAX_PROFIT_PERCENT_FOR_NEGATIVE_HYPE_CONSIDERATION
-            ..=MIN_PROFIT_PERCENT_FOR_POSITIVE_HYPE_CONSIDERATION)
+        if (config.max_profit_percent_for_negative_hype
+            ..=config.min_profit_percent_for_positive_hype)
             .contains(&news)
         {
             return None;
@@ -459,17 +516,33 @@ impl Companies {
         // Ya, this is the way it happens in real life, idk why
         self.lots[company_id as usize].number_of_lots != 0
     }
+    /// Only buyers can bet on the lots of a company
     pub fn check_lots_from_todotransaction(&self, todo_transaction: &TodoTransaction) -> bool {
-        self.check_lot(todo_transaction.company_id)
+        todo_transaction.action == TradeAction::Buy && self.check_lot(todo_transaction.company_id)
     }
-    pub fn add_bet_from_todotransaction(&mut self, todo_transaction: &TodoTransaction) {
+    /// Bets on as many lots as the transaction has shares, at the strike price of the lots.
+    /// Returns the number of lots bet on, 0 if no bet was placed,
+    /// like when the lots cost more than the strike price of the transaction.
+    pub fn add_bet_from_todotransaction(
+        &mut self,
+        todo_transaction: &TodoTransaction,
+        agents: &mut Agents,
+    ) -> Result<u64, SimulationError> {
         let lot = &mut self.lots[todo_transaction.company_id as usize];
-        if lot.lot_size == 0 {
-            return;
+        if lot.is_blank() || !lot.fits_agent_price(todo_transaction.strike_price) {
+            return Ok(0);
+        }
+        let number_of_lots =
+            (todo_transaction.trade.number_of_shares as f64 / lot.lot_size as f64).round() as u64;
+        if number_of_lots == 0 {
+            return Ok(0);
         }
-        lot.add_bet(
+        lot.add_bet_and_update_agent(
+            todo_transaction.company_id,
+            agents,
             todo_transaction.agent_id,
-            (todo_transaction.trade.number_of_shares as f64 / lot.lot_size as f64).round() as u64,
-        );
+            number_of_lots,
+        )?;
+        Ok(number_of_lots)
     }
 }
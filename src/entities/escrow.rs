@@ -0,0 +1,152 @@
+use crate::{trade_house::TradeAction, SimulationError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// What an agent put up for an order, held until the order is resolved.
+/// A buyer puts up `strike_price` for every share, a seller the shares themselves.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Reservation {
+    pub agent_id: u64,
+    pub company_id: u64,
+    pub action: TradeAction,
+    pub strike_price: f64,
+    /// The shares still covered by the reservation
+    pub number_of_shares: u64,
+}
+
+impl Reservation {
+    /// The money held for the reservation
+    pub fn cash(&self) -> f64 {
+        match self.action {
+            TradeAction::Buy => self.strike_price * (self.number_of_shares as f64),
+            TradeAction::Sell => 0.0,
+        }
+    }
+
+    /// The shares held for the reservation
+    pub fn shares(&self) -> u64 {
+        match self.action {
+            TradeAction::Buy => 0,
+            TradeAction::Sell => self.number_of_shares,
+        }
+    }
+}
+
+/// Keeps track of everything agents have put up for their orders, by order id.
+/// Only the bookkeeping is done here, the assets are moved by [`Agents`](super::agents::Agents).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Escrow {
+    reservations: BTreeMap<u64, Reservation>,
+    next_order_id: u64,
+}
+
+impl Default for Escrow {
+    fn default() -> Self {
+        Self {
+            reservations: BTreeMap::new(),
+            // 0 is never an order id
+            next_order_id: 1,
+        }
+    }
+}
+
+impl Escrow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the id of the order the reservation is made for
+    pub fn reserve(&mut self, reservation: Reservation) -> u64 {
+        let order_id = self.next_order_id;
+        self.next_order_id += 1;
+        self.reservations.insert(order_id, reservation);
+        order_id
+    }
+
+    pub fn get(&self, order_id: u64) -> Option<&Reservation> {
+        self.reservations.get(&order_id)
+    }
+
+    /// Adds shares to an existing reservation, at its strike price
+    pub fn top_up(&mut self, order_id: u64, number_of_shares: u64) -> Result<(), SimulationError> {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        reservation.number_of_shares += number_of_shares;
+        Ok(())
+    }
+
+    /// Takes `number_of_shares` out of the reservation, which is dropped once nothing is left.
+    /// Returns the part that was taken out.
+    pub fn take(
+        &mut self,
+        order_id: u64,
+        number_of_shares: u64,
+    ) -> Result<Reservation, SimulationError> {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        if reservation.number_of_shares < number_of_shares {
+            return Err(SimulationError::Unspendable);
+        }
+        reservation.number_of_shares -= number_of_shares;
+        let mut taken = reservation.clone();
+        taken.number_of_shares = number_of_shares;
+        if reservation.number_of_shares == 0 {
+            self.reservations.remove(&order_id);
+        }
+        Ok(taken)
+    }
+
+    /// Takes out everything that is left of the reservation
+    pub fn take_all(&mut self, order_id: u64) -> Result<Reservation, SimulationError> {
+        self.reservations
+            .remove(&order_id)
+            .ok_or(SimulationError::OfferNotFound(order_id))
+    }
+
+    /// Replaces what the reservation covers, returning what it covered before
+    pub fn replace(
+        &mut self,
+        order_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    ) -> Result<Reservation, SimulationError> {
+        let Some(reservation) = self.reservations.get_mut(&order_id) else {
+            return Err(SimulationError::OfferNotFound(order_id));
+        };
+        let previous = reservation.clone();
+        reservation.strike_price = strike_price;
+        reservation.number_of_shares = number_of_shares;
+        if number_of_shares == 0 {
+            self.reservations.remove(&order_id);
+        }
+        Ok(previous)
+    }
+
+    pub fn len(&self) -> usize {
+        self.reservations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reservations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&u64, &Reservation)> {
+        self.reservations.iter()
+    }
+
+    /// The money held for every order
+    pub fn total_cash(&self) -> f64 {
+        self.reservations.values().map(Reservation::cash).sum()
+    }
+
+    /// The shares of a company held for every order
+    pub fn total_shares(&self, company_id: u64) -> u64 {
+        self.reservations
+            .values()
+            .filter(|reservation| reservation.company_id == company_id)
+            .map(Reservation::shares)
+            .sum()
+    }
+}
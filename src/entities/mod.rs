@@ -1,9 +1,12 @@
 use crate::SimulationError;
+use serde::{Deserialize, Serialize};
 
 pub mod agents;
 pub mod companies;
+pub mod escrow;
+pub mod portfolio;
 
-#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Balances(pub Vec<f64>);
 
 impl Balances {
@@ -0,0 +1,157 @@
+use crate::EQUITY_HISTORY_SIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// Shares of a company bought together, at the same price
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PurchaseLot {
+    pub number_of_shares: u64,
+    pub price: f64,
+}
+
+/// What an agent paid for the shares of a company it owns, and what it made selling them
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Position {
+    /// The shares still owned, oldest purchase first. They're sold in that order (FIFO)
+    pub lots: VecDeque<PurchaseLot>,
+    /// Profit made on the shares sold so far, against what was paid for them
+    pub realized_profit: f64,
+}
+
+impl Position {
+    pub fn number_of_shares(&self) -> u64 {
+        self.lots.iter().map(|lot| lot.number_of_shares).sum()
+    }
+
+    /// What was paid for the shares still owned
+    pub fn cost_basis(&self) -> f64 {
+        self.lots
+            .iter()
+            .map(|lot| lot.price * lot.number_of_shares as f64)
+            .sum()
+    }
+
+    /// What was paid for a share still owned on average, none once every share is sold
+    pub fn average_cost(&self) -> Option<f64> {
+        let number_of_shares = self.number_of_shares();
+        (number_of_shares > 0).then(|| self.cost_basis() / number_of_shares as f64)
+    }
+
+    /// What the shares still owned would make if they were sold at the price
+    pub fn unrealized_profit(&self, price: f64) -> f64 {
+        price * self.number_of_shares() as f64 - self.cost_basis()
+    }
+
+    pub fn buy(&mut self, number_of_shares: u64, price: f64) {
+        if number_of_shares == 0 {
+            return;
+        }
+        self.lots.push_back(PurchaseLot {
+            number_of_shares,
+            price,
+        });
+    }
+
+    /// Sells the oldest shares first, returning the profit made.
+    /// Shares sold past the ones in the position are taken as costing the price
+    pub fn sell(&mut self, number_of_shares: u64, price: f64) -> f64 {
+        let mut number_of_shares_left = number_of_shares;
+        let mut profit = 0.0;
+        while number_of_shares_left > 0 {
+            let Some(lot) = self.lots.front_mut() else {
+                break;
+            };
+            let number_of_shares_sold = number_of_shares_left.min(lot.number_of_shares);
+            profit += (price - lot.price) * number_of_shares_sold as f64;
+            lot.number_of_shares -= number_of_shares_sold;
+            number_of_shares_left -= number_of_shares_sold;
+            if lot.number_of_shares == 0 {
+                self.lots.pop_front();
+            }
+        }
+        self.realized_profit += profit;
+        profit
+    }
+}
+
+/// What every agent's money and shares were worth at a tick, by agent id
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct EquityRecord {
+    pub tick: u64,
+    pub equities: Vec<f64>,
+}
+
+/// The positions of every agent and how what they own was worth over time.
+/// They're saved in a snapshot section of their own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Portfolios {
+    /// By agent and company id, positions are kept after every share is sold
+    positions: BTreeMap<(u64, u64), Position>,
+    equity_history: VecDeque<EquityRecord>,
+    /// Most equity records kept, the oldest ones get forgotten
+    pub history_size: usize,
+}
+
+impl Default for Portfolios {
+    fn default() -> Self {
+        Self {
+            positions: BTreeMap::new(),
+            equity_history: VecDeque::new(),
+            history_size: EQUITY_HISTORY_SIZE,
+        }
+    }
+}
+
+impl Portfolios {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn position(&self, agent_id: u64, company_id: u64) -> Option<&Position> {
+        self.positions.get(&(agent_id, company_id))
+    }
+
+    /// Every position of the agent with the id of its company
+    pub fn positions(&self, agent_id: u64) -> impl Iterator<Item = (u64, &Position)> {
+        self.positions
+            .range((agent_id, 0)..=(agent_id, u64::MAX))
+            .map(|((_, company_id), position)| (*company_id, position))
+    }
+
+    pub fn buy(&mut self, agent_id: u64, company_id: u64, number_of_shares: u64, price: f64) {
+        self.positions
+            .entry((agent_id, company_id))
+            .or_default()
+            .buy(number_of_shares, price);
+    }
+
+    /// Returns the profit made
+    pub fn sell(
+        &mut self,
+        agent_id: u64,
+        company_id: u64,
+        number_of_shares: u64,
+        price: f64,
+    ) -> f64 {
+        self.positions
+            .entry((agent_id, company_id))
+            .or_default()
+            .sell(number_of_shares, price)
+    }
+
+    pub fn record_equity(&mut self, tick: u64, equities: Vec<f64>) {
+        if self.history_size == 0 {
+            return;
+        }
+        while self.equity_history.len() >= self.history_size {
+            self.equity_history.pop_front();
+        }
+        self.equity_history
+            .push_back(EquityRecord { tick, equities });
+    }
+
+    /// Every equity record kept, oldest first
+    pub fn equity_history(&self) -> &VecDeque<EquityRecord> {
+        &self.equity_history
+    }
+}
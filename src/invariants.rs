@@ -0,0 +1,188 @@
+use crate::{
+    entities::{agents::Agents, companies::Companies},
+    market::Market,
+    trade_house::TradeAction,
+    transaction::CompanyTransaction,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The money and shares in the simulation at some point.
+///
+/// Shares still in the lots of a company don't count, they are only
+/// issued once they are given out to the bettors
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Totals {
+    /// Agent balances, money held in escrow and company balances
+    pub cash: f64,
+    /// The issued shares of every company, held by agents or in escrow
+    pub shares: BTreeMap<u64, u64>,
+}
+
+impl Totals {
+    pub fn measure(agents: &Agents, companies: &Companies) -> Self {
+        let cash = agents.balances.0.iter().sum::<f64>()
+            + agents.escrow.total_cash()
+            + companies.balances.iter().sum::<f64>();
+        let mut shares = BTreeMap::new();
+        for (_, company_id, number_of_shares) in agents.holdings.iter() {
+            *shares.entry(company_id).or_default() += number_of_shares;
+        }
+        for (_, reservation) in agents.escrow.iter() {
+            *shares.entry(reservation.company_id).or_default() += reservation.shares();
+        }
+        shares.retain(|_, number_of_shares| *number_of_shares != 0);
+        Self { cash, shares }
+    }
+}
+
+/// How the simulation stopped adding up
+#[derive(Debug, Clone, PartialEq)]
+pub enum Divergence {
+    Cash {
+        expected: f64,
+        actual: f64,
+    },
+    Shares {
+        company_id: u64,
+        expected: u64,
+        actual: u64,
+    },
+    /// A resting offer that isn't backed by what its offerer put up in escrow
+    UncoveredOffer {
+        offer_id: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub tick: u64,
+    /// What happened right before the totals were checked
+    pub event: String,
+    pub divergence: Divergence,
+}
+
+/// Checks that money and shares are only created or destroyed where they are expected to be.
+///
+/// Known sources and sinks, like the profits of companies or the shares given out
+/// from lots, have to be told to the checker before the next check
+#[derive(Debug, Clone)]
+pub struct InvariantChecker {
+    expected: Totals,
+    /// How far apart the cash totals can be, relative to the expected total
+    pub tolerance: f64,
+    first_violation: Option<Violation>,
+}
+
+impl InvariantChecker {
+    pub fn new(agents: &Agents, companies: &Companies) -> Self {
+        Self {
+            expected: Totals::measure(agents, companies),
+            tolerance: 1e-9,
+            first_violation: None,
+        }
+    }
+
+    pub fn expected(&self) -> &Totals {
+        &self.expected
+    }
+
+    /// The first violation found, if any
+    pub fn first_violation(&self) -> Option<&Violation> {
+        self.first_violation.as_ref()
+    }
+
+    /// Money that is added to (or taken out of) the simulation on purpose
+    pub fn expect_cash(&mut self, amount: f64) {
+        self.expected.cash += amount;
+    }
+
+    /// Shares of a company that are issued on purpose
+    pub fn expect_shares(&mut self, company_id: u64, number_of_shares: u64) {
+        *self.expected.shares.entry(company_id).or_default() += number_of_shares;
+    }
+
+    /// Shares given out from the lots of companies
+    pub fn expect_company_transactions(&mut self, company_transactions: &[CompanyTransaction]) {
+        for transaction in company_transactions {
+            self.expect_shares(transaction.seller_company_id, transaction.number_of_shares);
+        }
+    }
+
+    /// Compares the simulation with what is expected after `event`.
+    /// The expected totals are moved to the measured ones either way, so that
+    /// a single divergence isn't reported again by every later check.
+    pub fn check(
+        &mut self,
+        tick: u64,
+        event: &str,
+        agents: &Agents,
+        companies: &Companies,
+        market: &Market,
+    ) -> Result<(), Violation> {
+        let actual = Totals::measure(agents, companies);
+        let divergence = self
+            .diverges(&actual)
+            .or_else(|| uncovered_offer(agents, market));
+        self.expected = actual;
+        let Some(divergence) = divergence else {
+            return Ok(());
+        };
+        let violation = Violation {
+            tick,
+            event: event.to_string(),
+            divergence,
+        };
+        if self.first_violation.is_none() {
+            self.first_violation = Some(violation.clone());
+        }
+        Err(violation)
+    }
+
+    fn diverges(&self, actual: &Totals) -> Option<Divergence> {
+        let allowed = self.tolerance * self.expected.cash.abs().max(1.0);
+        if (self.expected.cash - actual.cash).abs() > allowed {
+            return Some(Divergence::Cash {
+                expected: self.expected.cash,
+                actual: actual.cash,
+            });
+        }
+        let company_ids: BTreeSet<u64> = self
+            .expected
+            .shares
+            .keys()
+            .chain(actual.shares.keys())
+            .copied()
+            .collect();
+        company_ids.into_iter().find_map(|company_id| {
+            let expected = self.expected.shares.get(&company_id).copied().unwrap_or(0);
+            let actual = actual.shares.get(&company_id).copied().unwrap_or(0);
+            (expected != actual).then_some(Divergence::Shares {
+                company_id,
+                expected,
+                actual,
+            })
+        })
+    }
+}
+
+/// Finds a resting trade offer that doesn't have a matching reservation in escrow
+fn uncovered_offer(agents: &Agents, market: &Market) -> Option<Divergence> {
+    for (_, offers) in market.house.trade_offers() {
+        for (action, side) in [
+            (TradeAction::Buy, &offers.buyer_offers),
+            (TradeAction::Sell, &offers.seller_offers),
+        ] {
+            for offer in side.iter() {
+                let covered = agents.escrow.get(offer.id).is_some_and(|reservation| {
+                    reservation.agent_id == offer.offerer_id
+                        && reservation.action == action
+                        && reservation.number_of_shares >= offer.data.number_of_shares
+                });
+                if !covered {
+                    return Some(Divergence::UncoveredOffer { offer_id: offer.id });
+                }
+            }
+        }
+    }
+    None
+}
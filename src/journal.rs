@@ -0,0 +1,493 @@
+use crate::{
+    entities::{
+        agents::Agents,
+        companies::{Companies, Lots, MAX_NUM_OF_HYPE_COMPANIES},
+        escrow::Reservation,
+    },
+    market::Market,
+    snapshot::Snapshot,
+    trade_house::{ExpiredOffers, TradeAction},
+    transaction::{TodoTransaction, Transaction},
+    trigger_book::StopOrder,
+    DeserializationError, SerializationError, SimulationError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+};
+
+/// A change to the world, recorded as the simulation makes it.
+/// Enough is recorded to make the same change again without any randomness
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Event {
+    /// A new tick started, the offers agents would have retried are forgotten
+    /// and the ones good till it expired
+    TickStarted,
+    /// The market values were taken from the candles and the resting offers aged
+    MarketTicked,
+    /// What the order needs was put up in escrow
+    OrderSubmitted {
+        order_id: u64,
+        reservation: Reservation,
+    },
+    /// What was left of the order was put in the order book at its strike price
+    OrderRested {
+        order_id: u64,
+        todo_transaction: TodoTransaction,
+    },
+    /// The order was filled against the best resting offer within `limit_price`
+    OrderFilled {
+        transaction: Transaction,
+        aggressor: TradeAction,
+        limit_price: f64,
+    },
+    /// What was left of an order that can't rest was refunded
+    OrderCancelled {
+        order_id: u64,
+    },
+    /// An order ran into a resting offer of its own agent,
+    /// so shares of either or both were cancelled and refunded
+    SelfTradePrevented {
+        company_id: u64,
+        resting_action: TradeAction,
+        resting_number_of_shares: u64,
+        order_id: u64,
+        number_of_shares: u64,
+    },
+    /// A resting offer ran out of lifetime and what was put up for it was refunded.
+    /// Nothing is put up for option offers, so they have no order
+    OfferExpired {
+        company_id: u64,
+        agent_id: u64,
+        action: TradeAction,
+        strike_price: f64,
+        order_id: Option<u64>,
+    },
+    StopOrderAdded {
+        stop_order: StopOrder,
+    },
+    StopOrderCancelled {
+        stop_order_id: u64,
+    },
+    /// The oldest triggered stop order was sent to the market
+    StopOrderSent,
+    LotBet {
+        company_id: u64,
+        agent_id: u64,
+        number_of_lots: u64,
+    },
+    /// The bets on the lots of the company were settled
+    LotsDistributed {
+        company_id: u64,
+    },
+    /// The company put up new lots
+    LotsReleased {
+        company_id: u64,
+        lots: Lots,
+    },
+    NewsReleased {
+        company_id: u64,
+        news: f64,
+        balance: f64,
+    },
+    HypeSet {
+        hype: [Option<(u64, f64)>; MAX_NUM_OF_HYPE_COMPANIES],
+    },
+    /// Every agent got a preference from the news, in order of their ids
+    PreferencesAdded {
+        preferences: Vec<(u64, TradeAction)>,
+    },
+    /// A resting offer was taken out of the order book and refunded
+    OfferCancelled {
+        offer_id: u64,
+    },
+    /// A resting offer was moved to a new price and size without being matched
+    OfferAmended {
+        offer_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JournalEntry {
+    pub tick: u64,
+    pub event: Event,
+}
+
+/// Append-only record of every change made to the world.
+///
+/// Nothing is recorded unless the journal is enabled. When opened from a file,
+/// the entries are appended to it on every [`Journal::save`] and forgotten,
+/// one bincode encoded entry after the other
+#[derive(Debug, Default)]
+pub struct Journal {
+    entries: Vec<JournalEntry>,
+    enabled: bool,
+    file_path: Option<String>,
+}
+
+fn read_entries(file_path: &str) -> Result<Vec<JournalEntry>, DeserializationError> {
+    let Ok(file) = File::open(file_path) else {
+        return Err(DeserializationError::FileNotFound);
+    };
+    let mut reader = BufReader::new(file);
+    let mut entries = Vec::new();
+    loop {
+        let Ok(remaining) = reader.fill_buf() else {
+            return Err(DeserializationError::FailedToReadFile);
+        };
+        if remaining.is_empty() {
+            return Ok(entries);
+        }
+        let Ok(entry) = bincode::deserialize_from(&mut reader) else {
+            return Err(DeserializationError::FailedToSerialize);
+        };
+        entries.push(entry);
+    }
+}
+
+impl Journal {
+    /// A journal that is only kept in memory
+    pub fn new() -> Self {
+        Self {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    /// New entries get appended to the file, which is made if there is none
+    pub fn open(file_path: &str) -> Result<Self, DeserializationError> {
+        if let Err(e) = File::open(file_path) {
+            if e.kind() != ErrorKind::NotFound {
+                return Err(DeserializationError::FailedToReadFile);
+            }
+            if File::create(file_path).is_err() {
+                return Err(DeserializationError::FileNotFound);
+            }
+        }
+        Ok(Self {
+            entries: Vec::new(),
+            enabled: true,
+            file_path: Some(file_path.to_string()),
+        })
+    }
+
+    /// Every entry saved in the file
+    pub fn read(file_path: &str) -> Result<Vec<JournalEntry>, DeserializationError> {
+        read_entries(file_path)
+    }
+
+    /// Forgets the entries in the file after the tick, used when the world
+    /// goes back to an older snapshot
+    pub fn discard_after(file_path: &str, tick: u64) -> Result<(), SerializationError> {
+        let Ok(entries) = read_entries(file_path) else {
+            return Err(SerializationError::FailedToSerialize);
+        };
+        if entries.last().is_none_or(|entry| entry.tick <= tick) {
+            return Ok(());
+        }
+        let Ok(file) = File::create(file_path) else {
+            return Err(SerializationError::FailedToCreateFile);
+        };
+        let mut writer = BufWriter::new(file);
+        for entry in entries.iter().take_while(|entry| entry.tick <= tick) {
+            if bincode::serialize_into(&mut writer, entry).is_err() {
+                return Err(SerializationError::FailedToSerialize);
+            }
+        }
+        writer
+            .flush()
+            .map_err(|_| SerializationError::FailedToWrite)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn record(&mut self, tick: u64, event: Event) {
+        if self.enabled {
+            self.entries.push(JournalEntry { tick, event });
+        }
+    }
+
+    /// The entries that aren't saved in a file
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Appends the entries to the file, if the journal has one
+    pub fn save(&mut self) -> Result<(), SerializationError> {
+        let Some(file_path) = self.file_path.as_ref() else {
+            return Ok(());
+        };
+        if self.entries.is_empty() {
+            return Ok(());
+        }
+        let Ok(file) = OpenOptions::new().append(true).open(file_path) else {
+            return Err(SerializationError::FailedToCreateFile);
+        };
+        let mut writer = BufWriter::new(file);
+        for entry in self.entries.iter() {
+            if bincode::serialize_into(&mut writer, entry).is_err() {
+                return Err(SerializationError::FailedToSerialize);
+            }
+        }
+        if writer.flush().is_err() {
+            return Err(SerializationError::FailedToWrite);
+        }
+        self.entries.clear();
+        Ok(())
+    }
+}
+
+/// Rebuilds the world from a snapshot by making the changes in the journal again,
+/// checking along the way that they turn out as they were recorded
+pub struct Replayer {
+    agents: Agents,
+    companies: Companies,
+    market: Market,
+    tick: u64,
+}
+
+impl Replayer {
+    pub fn new(snapshot: Snapshot) -> Self {
+        let mut market = snapshot.market;
+        market.current_tick = snapshot.header.tick;
+        Self {
+            agents: snapshot.agents,
+            companies: snapshot.companies,
+            market,
+            tick: snapshot.header.tick,
+        }
+    }
+
+    pub fn agents(&self) -> &Agents {
+        &self.agents
+    }
+
+    pub fn companies(&self) -> &Companies {
+        &self.companies
+    }
+
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    /// The tick of the last entry replayed, or of the snapshot if there was none
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn into_world(self) -> (Agents, Companies, Market) {
+        (self.agents, self.companies, self.market)
+    }
+
+    /// Replays the entries up to and including `last_tick`, skipping the ones
+    /// the snapshot already has
+    pub fn replay<'a>(
+        &mut self,
+        entries: impl IntoIterator<Item = &'a JournalEntry>,
+        last_tick: Option<u64>,
+    ) -> Result<(), SimulationError> {
+        let start_tick = self.tick;
+        for entry in entries.into_iter().filter(|entry| entry.tick > start_tick) {
+            if last_tick.is_some_and(|last_tick| entry.tick > last_tick) {
+                break;
+            }
+            self.apply(entry)?;
+        }
+        Ok(())
+    }
+
+    fn check(&self, holds: bool) -> Result<(), SimulationError> {
+        if holds {
+            Ok(())
+        } else {
+            Err(SimulationError::Diverged(self.tick))
+        }
+    }
+
+    pub fn apply(&mut self, entry: &JournalEntry) -> Result<(), SimulationError> {
+        self.tick = entry.tick;
+        self.market.current_tick = entry.tick;
+        match &entry.event {
+            Event::TickStarted => {
+                self.agents.try_offers.clear();
+                // the refunds are replayed from their own entries
+                self.market
+                    .expire_offers(&mut ExpiredOffers::new(), &mut ExpiredOffers::new());
+            }
+            Event::MarketTicked => {
+                for (company_id, market_value) in
+                    self.companies.market_values.iter_mut().enumerate()
+                {
+                    self.market
+                        .tick_individual_company(company_id as u64, market_value);
+                }
+                // the refunds are replayed from their own entries
+                self.market
+                    .tick_failures(&mut ExpiredOffers::new(), &mut ExpiredOffers::new());
+                self.agents.record_equity(self.tick, &self.companies);
+            }
+            Event::OrderSubmitted {
+                order_id,
+                reservation,
+            } => {
+                let reserved_order_id = self.agents.reserve(reservation.clone())?;
+                self.check(reserved_order_id == *order_id)?;
+            }
+            Event::OrderRested {
+                order_id,
+                todo_transaction,
+            } => {
+                self.agents
+                    .reprice_order(*order_id, todo_transaction.strike_price)?;
+                self.market
+                    .house
+                    .add_trade_offer_from_todo_transaction(*order_id, todo_transaction);
+            }
+            Event::OrderFilled {
+                transaction,
+                aggressor,
+                limit_price,
+            } => {
+                let resting_order_id = match aggressor {
+                    TradeAction::Buy => transaction.seller_order_id,
+                    TradeAction::Sell => transaction.buyer_order_id,
+                };
+                let fill = self.market.house.fill_best_matching_trade_offer(
+                    transaction.company_id,
+                    *aggressor,
+                    *limit_price,
+                    transaction.number_of_shares,
+                );
+                self.check(fill.is_some_and(|(offer, number_of_shares)| {
+                    offer.id == resting_order_id && number_of_shares == transaction.number_of_shares
+                }))?;
+                self.market.add_transaction(
+                    transaction.company_id,
+                    transaction.strike_price,
+                    transaction.number_of_shares,
+                );
+                self.market
+                    .ledger
+                    .record_transaction(self.tick, transaction, *aggressor);
+                self.agents.exchange_assets_from_transaction(transaction)?;
+            }
+            Event::OrderCancelled { order_id } => self.agents.refund_whole_order(*order_id)?,
+            Event::SelfTradePrevented {
+                company_id,
+                resting_action,
+                resting_number_of_shares,
+                order_id,
+                number_of_shares,
+            } => {
+                self.market.self_trades_prevented += 1;
+                if *resting_number_of_shares > 0 {
+                    let Some(offer) = self.market.house.reduce_best_trade_offer(
+                        *company_id,
+                        *resting_action,
+                        *resting_number_of_shares,
+                    ) else {
+                        return Err(SimulationError::Diverged(self.tick));
+                    };
+                    self.agents
+                        .refund_order(offer.id, *resting_number_of_shares)?;
+                }
+                if *number_of_shares > 0 {
+                    self.agents.refund_order(*order_id, *number_of_shares)?;
+                }
+            }
+            Event::OfferExpired {
+                company_id,
+                agent_id,
+                action,
+                strike_price,
+                order_id,
+            } => {
+                if let Some(order_id) = order_id {
+                    self.agents.refund_whole_order(*order_id)?;
+                }
+                self.agents
+                    .add_failed_offer(*company_id, *agent_id, *strike_price, action);
+            }
+            Event::StopOrderAdded { stop_order } => {
+                self.market.add_stop_order(stop_order.clone());
+            }
+            Event::StopOrderCancelled { stop_order_id } => {
+                self.market.cancel_stop_order(*stop_order_id)?;
+            }
+            Event::StopOrderSent => {
+                let sent = self.market.pop_triggered_order();
+                self.check(sent.is_some())?;
+            }
+            Event::LotBet {
+                company_id,
+                agent_id,
+                number_of_lots,
+            } => {
+                let Some(lots) = self.companies.lots.get_mut(*company_id as usize) else {
+                    return Err(SimulationError::Diverged(self.tick));
+                };
+                lots.add_bet_and_update_agent(
+                    *company_id,
+                    &mut self.agents,
+                    *agent_id,
+                    *number_of_lots,
+                )?;
+            }
+            Event::LotsDistributed { company_id } => {
+                let id = *company_id as usize;
+                let Some(lots) = self.companies.lots.get_mut(id) else {
+                    return Err(SimulationError::Diverged(self.tick));
+                };
+                let company_transactions = lots.finalize(*company_id, &mut self.agents)?;
+                for transaction in company_transactions.iter() {
+                    self.companies.balances[id] +=
+                        transaction.strike_price * transaction.number_of_shares as f64;
+                }
+                self.market
+                    .ledger
+                    .record_company_transactions(self.tick, &company_transactions);
+            }
+            Event::LotsReleased { company_id, lots } => {
+                let Some(company_lots) = self.companies.lots.get_mut(*company_id as usize) else {
+                    return Err(SimulationError::Diverged(self.tick));
+                };
+                *company_lots = lots.clone();
+            }
+            Event::NewsReleased {
+                company_id,
+                news,
+                balance,
+            } => {
+                let id = *company_id as usize;
+                self.check(id < self.companies.news.len())?;
+                self.companies.news[id] = *news;
+                self.companies.balances[id] = *balance;
+            }
+            Event::HypeSet { hype } => self.companies.hype = *hype,
+            Event::PreferencesAdded { preferences } => {
+                self.agents.give_preferences_from_news(preferences);
+            }
+            Event::OfferCancelled { offer_id } => {
+                self.market
+                    .house
+                    .cancel_trade_offer(&mut self.agents, *offer_id)?;
+            }
+            Event::OfferAmended {
+                offer_id,
+                strike_price,
+                number_of_shares,
+            } => self.market.house.amend_trade_offer(
+                &mut self.agents,
+                *offer_id,
+                *strike_price,
+                *number_of_shares,
+            )?,
+        }
+        Ok(())
+    }
+}
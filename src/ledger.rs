@@ -0,0 +1,272 @@
+use crate::{
+    log,
+    logger::Log,
+    trade_house::TradeAction,
+    transaction::{CompanyTransaction, Transaction},
+    DeserializationError, SerializationError,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, ErrorKind, Write},
+    ops::RangeBounds,
+};
+
+/// A single entry of the trade ledger
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum LedgerRecord {
+    Trade {
+        tick: u64,
+        transaction: Transaction,
+        /// The side of the order that came in and was matched with a resting offer
+        aggressor: TradeAction,
+    },
+    /// Shares bought from the lots of a company
+    CompanyTrade {
+        tick: u64,
+        transaction: CompanyTransaction,
+    },
+}
+
+impl LedgerRecord {
+    pub fn tick(&self) -> u64 {
+        match self {
+            Self::Trade { tick, .. } | Self::CompanyTrade { tick, .. } => *tick,
+        }
+    }
+
+    pub fn company_id(&self) -> u64 {
+        match self {
+            Self::Trade { transaction, .. } => transaction.company_id,
+            Self::CompanyTrade { transaction, .. } => transaction.seller_company_id,
+        }
+    }
+
+    pub fn number_of_shares(&self) -> u64 {
+        match self {
+            Self::Trade { transaction, .. } => transaction.number_of_shares,
+            Self::CompanyTrade { transaction, .. } => transaction.number_of_shares,
+        }
+    }
+
+    pub fn strike_price(&self) -> f64 {
+        match self {
+            Self::Trade { transaction, .. } => transaction.strike_price,
+            Self::CompanyTrade { transaction, .. } => transaction.strike_price,
+        }
+    }
+
+    /// Whether the agent bought or sold anything in the record
+    pub fn involves_agent(&self, agent_id: u64) -> bool {
+        match self {
+            Self::Trade { transaction, .. } => {
+                transaction.buyer_id == agent_id || transaction.seller_id == agent_id
+            }
+            Self::CompanyTrade { transaction, .. } => transaction.buyer_agent_id == agent_id,
+        }
+    }
+}
+
+impl fmt::Display for LedgerRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Trade {
+                tick, transaction, ..
+            } => write!(
+                f,
+                "tick {}: agent {} bought {} shares of company {} from agent {} at {}",
+                tick,
+                transaction.buyer_id,
+                transaction.number_of_shares,
+                transaction.company_id,
+                transaction.seller_id,
+                transaction.strike_price
+            ),
+            Self::CompanyTrade { tick, transaction } => write!(
+                f,
+                "tick {}: agent {} bought {} shares of company {} from its lots at {}",
+                tick,
+                transaction.buyer_agent_id,
+                transaction.number_of_shares,
+                transaction.seller_company_id,
+                transaction.strike_price
+            ),
+        }
+    }
+}
+
+/// Append-only tape of every trade done in the market.
+///
+/// When opened from a file, new records are appended to it on every [`Ledger::save`],
+/// one bincode encoded record after the other
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Ledger {
+    records: Vec<LedgerRecord>,
+    #[serde(skip)]
+    file_path: Option<String>,
+    /// Number of records that are already in the file
+    #[serde(skip)]
+    number_of_saved_records: usize,
+}
+
+impl Ledger {
+    /// A ledger that is only kept in memory
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads every record already in the file, new ones get appended to it.
+    /// The file is made if there is none. A record left partly written at the end
+    /// of the file, like a crash while appending leaves behind, is cut off
+    pub fn open(file_path: &str) -> Result<Self, DeserializationError> {
+        let mut records = Vec::new();
+        match fs::read(file_path) {
+            Ok(bytes) => {
+                let mut remaining = bytes.as_slice();
+                let mut complete_len = 0;
+                while !remaining.is_empty() {
+                    let Ok(record) = bincode::deserialize_from(&mut remaining) else {
+                        break;
+                    };
+                    records.push(record);
+                    complete_len = bytes.len() - remaining.len();
+                }
+                if complete_len < bytes.len() {
+                    log!(warn "Cut off the {} bytes of the partly written record at the end of {}", bytes.len() - complete_len, file_path);
+                    let Ok(file) = OpenOptions::new().write(true).open(file_path) else {
+                        return Err(DeserializationError::FailedToReadFile);
+                    };
+                    if file.set_len(complete_len as u64).is_err() {
+                        return Err(DeserializationError::FailedToReadFile);
+                    }
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                // made right away, so that a path that can't be written to is found out early
+                if File::create(file_path).is_err() {
+                    return Err(DeserializationError::FileNotFound);
+                }
+            }
+            Err(_) => return Err(DeserializationError::FailedToReadFile),
+        }
+        Ok(Self {
+            number_of_saved_records: records.len(),
+            records,
+            file_path: Some(file_path.to_string()),
+        })
+    }
+
+    /// Appends the records that aren't in the file yet, if the ledger has one
+    pub fn save(&mut self) -> Result<(), SerializationError> {
+        let Some(file_path) = self.file_path.as_ref() else {
+            return Ok(());
+        };
+        if self.number_of_saved_records == self.records.len() {
+            return Ok(());
+        }
+        let Ok(file) = OpenOptions::new().create(true).append(true).open(file_path) else {
+            return Err(SerializationError::FailedToCreateFile);
+        };
+        let mut writer = BufWriter::new(file);
+        for record in self.records[self.number_of_saved_records..].iter() {
+            if bincode::serialize_into(&mut writer, record).is_err() {
+                return Err(SerializationError::FailedToSerialize);
+            }
+        }
+        if writer.flush().is_err() {
+            return Err(SerializationError::FailedToWrite);
+        }
+        self.number_of_saved_records = self.records.len();
+        Ok(())
+    }
+
+    /// Forgets the records after the tick, rewriting the file if the ledger has one.
+    /// Used when the world goes back to an older snapshot
+    pub fn discard_after(&mut self, tick: u64) -> Result<(), SerializationError> {
+        let kept = self.records.partition_point(|record| record.tick() <= tick);
+        if kept == self.records.len() {
+            return Ok(());
+        }
+        self.records.truncate(kept);
+        let Some(file_path) = self.file_path.as_ref() else {
+            return Ok(());
+        };
+        if File::create(file_path).is_err() {
+            return Err(SerializationError::FailedToCreateFile);
+        }
+        self.number_of_saved_records = 0;
+        self.save()
+    }
+
+    pub fn record_transaction(
+        &mut self,
+        tick: u64,
+        transaction: &Transaction,
+        aggressor: TradeAction,
+    ) {
+        self.records.push(LedgerRecord::Trade {
+            tick,
+            transaction: transaction.clone(),
+            aggressor,
+        });
+    }
+
+    pub fn record_company_transactions(
+        &mut self,
+        tick: u64,
+        company_transactions: &[CompanyTransaction],
+    ) {
+        self.records
+            .extend(
+                company_transactions
+                    .iter()
+                    .map(|transaction| LedgerRecord::CompanyTrade {
+                        tick,
+                        transaction: transaction.clone(),
+                    }),
+            );
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Every record, oldest first
+    pub fn records(&self) -> &[LedgerRecord] {
+        &self.records
+    }
+
+    pub fn by_company(&self, company_id: u64) -> impl Iterator<Item = &LedgerRecord> {
+        self.records
+            .iter()
+            .filter(move |record| record.company_id() == company_id)
+    }
+
+    pub fn by_agent(&self, agent_id: u64) -> impl Iterator<Item = &LedgerRecord> {
+        self.records
+            .iter()
+            .filter(move |record| record.involves_agent(agent_id))
+    }
+
+    /// The records up to and including the tick, only kept in memory
+    pub fn until(&self, last_tick: u64) -> Self {
+        Self {
+            records: self.by_tick_range(..=last_tick).cloned().collect(),
+            ..Default::default()
+        }
+    }
+
+    pub fn by_tick_range<R: RangeBounds<u64>>(
+        &self,
+        ticks: R,
+    ) -> impl Iterator<Item = &LedgerRecord> {
+        self.records
+            .iter()
+            .filter(move |record| ticks.contains(&record.tick()))
+    }
+}
@@ -5,21 +5,44 @@ use std::{
 
 use serde::{de::DeserializeOwned, Serialize};
 
+pub mod candles;
+pub mod checkpoints;
+pub mod cli;
+pub mod config;
 pub mod entities;
+pub mod invariants;
+pub mod journal;
+pub mod ledger;
 pub mod logger;
 pub mod market;
+pub mod rng;
+pub mod simulation;
+pub mod snapshot;
+pub mod statistics;
+pub mod strategies;
 pub mod trade_house;
 pub mod transaction;
+pub mod trigger_book;
 
 pub static NUM_OF_AGENTS: u64 = 10_000;
 pub static NUM_OF_COMPANIES: u64 = 100;
 
-pub static AGENTS_DATA_FILENAME: &str = "data/agents.bin";
-pub static COMPANIES_DATA_FILENAME: &str = "data/companies.bin";
+pub static DATA_DIR: &str = "data";
+pub static AGENTS_DATA_FILENAME: &str = "agents.bin";
+pub static COMPANIES_DATA_FILENAME: &str = "companies.bin";
+pub static LEDGER_DATA_FILENAME: &str = "ledger.bin";
+pub static CONFIG_FILENAME: &str = "config.yaml";
+pub static SNAPSHOT_DATA_FILENAME: &str = "world.snapshot";
+pub static JOURNAL_DATA_FILENAME: &str = "journal.bin";
+pub static JOURNAL_SNAPSHOT_FILENAME: &str = "journal.snapshot";
+pub static CHECKPOINTS_DIR: &str = "checkpoints";
+pub static CHECKPOINT_EVERY: u64 = 1000;
+pub static CHECKPOINTS_KEPT: u64 = 3;
 
 pub static MIN_STRIKE_PRICE: f64 = 5.0;
 pub static OFFER_LIFETIME: u64 = 10;
 pub static TIMELINE_SIZE_LIMIT: usize = 1000;
+pub static EQUITY_HISTORY_SIZE: usize = 100;
 
 #[derive(Debug)]
 pub enum SerializationError {
@@ -33,14 +56,25 @@ pub enum DeserializationError {
     FileNotFound,
     FailedToSerialize,
     FailedToReadFile,
+    /// The config can't be parsed or the simulation can't run with it
+    InvalidConfig(String),
+    /// The file doesn't start with the snapshot magic bytes
+    NotASnapshot,
+    /// The snapshot was written in a version that can't be read
+    UnsupportedVersion(u32),
+    /// A checksum of the snapshot doesn't match, or it ends too early
+    CorruptedSnapshot,
 }
 
 #[derive(Debug)]
 pub enum SimulationError {
     AgentNotFound(u64),
+    OfferNotFound(u64),
     Unspendable,
     NoData,
     UnDoable,
+    /// Replaying the journal didn't do what was recorded at the tick
+    Diverged(u64),
 }
 
 pub fn save<T: Serialize>(data: T, file_path: &str) -> Result<(), SerializationError> {
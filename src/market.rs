@@ -1,30 +1,91 @@
 use crate::{
+    candles::Candles,
+    config::MarketConfig,
     entities::{agents::Agents, companies::Companies, companies::MarketValue},
-    max, min,
-    trade_house::{FailedOffer, Offer, StockOption, Trade, TradeAction, TradeHouse},
+    journal::{Event, Journal},
+    ledger::Ledger,
+    max,
+    statistics::MarketStatistics,
+    trade_house::{
+        ExpiredOffers, FailedOffer, Offer, OrderType, StockOption, Trade, TradeAction, TradeHouse,
+    },
     transaction::{TodoTransaction, Transaction},
+    trigger_book::{StopOrder, TriggerBook},
     SimulationError,
 };
 use rand::Rng;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::VecDeque;
+
+/// Length in ticks of the candles market values are taken from
+pub const MARKET_VALUE_RESOLUTION: u64 = 5;
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct Market {
-    /// If the interval is set for 5 seconds
-    /// Have a function, called something like tick().
-    /// When it is called, the maximum and minimum values in the vec are stored in
-    /// `highest_price` and `lowest_price`. And the average is set at `current_price`
-    /// Also calculate the standard deviation and store it in `standard_deviation`
-    recent_transactions: HashMap<u64, Vec<f64>>,
+    /// Price bars of every company, built from the trades done
+    pub candles: Candles,
+    /// Rolling statistics of the recent trades of every company
+    pub statistics: MarketStatistics,
     pub house: TradeHouse,
+    pub triggers: TriggerBook,
+    /// Stop orders that were triggered but haven't been sent to the market yet
+    triggered_orders: VecDeque<TodoTransaction>,
+    pub self_trade_prevention: SelfTradePrevention,
+    /// Number of times an agent's transaction ran into its own offer
+    pub self_trades_prevented: u64,
+    /// Every trade done in the market, it's saved in its own file
+    #[serde(skip)]
+    pub ledger: Ledger,
+    /// Every change made to the market and the agents trading in it, when enabled
+    #[serde(skip)]
+    pub journal: Journal,
+    /// The tick trades are recorded at
+    pub current_tick: u64,
+}
+
+/// What happens when an agent's transaction would be matched with its own resting offer.
+/// Whatever gets cancelled is refunded
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum SelfTradePrevention {
+    /// The rest of the incoming transaction is cancelled
+    #[default]
+    CancelNewest,
+    /// The resting offer is cancelled and matching goes on
+    CancelOldest,
+    /// Both the rest of the incoming transaction and the resting offer are cancelled
+    CancelBoth,
+    /// The smaller of the two is cancelled, and the larger one is reduced by as many shares
+    Decrement,
 }
 
 #[derive(Debug)]
 pub enum ActionState {
-    AddedToOffers,
-    InstantlyResolved(Transaction),
-    PartiallyResolved(Transaction),
+    AddedToLots,
+    /// Nothing was resolved, the offer with the given id is in the order book
+    AddedToOffers(u64),
+    /// Every share was resolved by the listed fills
+    InstantlyResolved(Vec<Transaction>),
+    /// Some shares were resolved by the listed fills,
+    /// the rest is in the order book as the offer with the given id
+    PartiallyResolved(Vec<Transaction>, u64),
+    /// The listed fills are all that could be resolved, the rest was refunded
+    Cancelled(Vec<Transaction>),
+    /// Not every share could be resolved, so nothing was
+    Killed,
+}
+
+/// A change an agent makes to one of its resting offers
+#[derive(Debug, Clone, PartialEq)]
+pub enum OfferChange {
+    Cancel {
+        offer_id: u64,
+    },
+    /// See [`Market::replace_offer`]
+    Replace {
+        offer_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    },
 }
 
 impl Market {
@@ -32,203 +93,511 @@ impl Market {
         Self::default()
     }
 
+    pub fn from_config(config: &MarketConfig) -> Self {
+        let mut market = Self {
+            candles: Candles::new(&config.candle_resolutions, config.candle_history_size),
+            statistics: MarketStatistics::new(config.statistics_window_size),
+            ..Default::default()
+        };
+        market.house.offer_lifetime = config.offer_lifetime;
+        market
+    }
+
     pub fn rand_do_trade(
         &mut self,
         rng: &mut impl Rng,
         agents: &mut Agents,
         companies: &mut Companies,
         transactions: &mut [TodoTransaction],
+        config: &MarketConfig,
     ) -> Result<(), SimulationError> {
         for todo_transaction in transactions.iter() {
-            let Ok(Some(possible_offers)) = self.trade(
-                rng.gen_ratio(6, 10),
+            // a failed trade of one agent shouldn't stop the others from trading
+            _ = self.trade(
+                rng.gen_bool(config.lot_acceptance_probability),
                 todo_transaction,
                 agents,
                 companies,
-                5.0,
-            ) else {
-                continue;
-            };
-
-            if rng.gen_ratio(7, 10) {
-                continue;
-            }
-
-            let offer_idx = rng.gen_range(0..possible_offers.len());
-            let all_offers = self.house.get_mut_trade_offers(todo_transaction.company_id);
-            let target_offers = match todo_transaction.action {
-                TradeAction::Buy => &mut all_offers.seller_offers,
-                TradeAction::Sell => &mut all_offers.buyer_offers,
-            };
-            let offer = target_offers.remove(offer_idx);
-
-            let transaction = self
-                .convert_trade_offer_and_todo_transaction_to_transaction(&offer, todo_transaction);
-            self.add_transaction(todo_transaction.company_id, transaction.strike_price);
-            agents.exchange_assets_from_transaction(&transaction)?;
+                config.acceptable_strike_price_deviation,
+            );
+            self.trade_triggered_orders(agents, companies);
         }
         Ok(())
     }
-    pub fn trade(
+
+    /// Sends the order exactly as it is: it's only matched within its strike price,
+    /// rests at it, and is never diverted to the lots of the company
+    pub fn submit(
         &mut self,
-        willing_to_accept_company_shares_if_they_are_present: bool,
         todo_transaction: &TodoTransaction,
         agents: &mut Agents,
         companies: &mut Companies,
-        acceptable_strike_price_deviation: f64,
-    ) -> Result<Option<Vec<Offer<Trade>>>, SimulationError> {
-        agents.deduct_assets_from_todotransaction(todo_transaction)?;
+    ) -> Result<ActionState, SimulationError> {
+        let action_state = self.trade(false, todo_transaction, agents, companies, 0.0);
+        self.trade_triggered_orders(agents, companies);
+        action_state
+    }
 
-        //
-        if companies.check_lots_from_todotransaction(todo_transaction)
-            && willing_to_accept_company_shares_if_they_are_present
-        {
-            companies.add_bet_from_todotransaction(todo_transaction);
-            return Ok(None);
-        }
+    /// Stop orders are kept aside until a trade of the company happens at their stop price,
+    /// nothing is put up for them until then.
+    ///
+    /// Returns the id of the stop order
+    pub fn add_stop_order(&mut self, stop_order: StopOrder) -> u64 {
+        self.journal.record(
+            self.current_tick,
+            Event::StopOrderAdded {
+                stop_order: stop_order.clone(),
+            },
+        );
+        self.triggers.add_stop_order(stop_order)
+    }
 
-        // Check if there is an appropriate trade offer
-        let appropriate_trade_offer = self.house.get_appropriate_trade_offer(
-            todo_transaction.company_id,
-            todo_transaction.strike_price,
-            acceptable_strike_price_deviation,
-            todo_transaction.action.complement(),
+    pub fn cancel_stop_order(&mut self, stop_order_id: u64) -> Result<StopOrder, SimulationError> {
+        let stop_order = self
+            .triggers
+            .remove_stop_order(stop_order_id)
+            .ok_or(SimulationError::OfferNotFound(stop_order_id))?;
+        self.journal.record(
+            self.current_tick,
+            Event::StopOrderCancelled { stop_order_id },
         );
+        Ok(stop_order)
+    }
 
-        let Some(offer_idxs) = appropriate_trade_offer else {
-            // If none, then add trade to the house
-            self.house
-                .add_trade_offer_from_todo_transaction(todo_transaction);
-            return Ok(None);
+    /// The resting offer, as long as it belongs to the agent
+    fn find_offer_of(
+        &self,
+        agent_id: u64,
+        offer_id: u64,
+    ) -> Result<(u64, TradeAction, &Offer<Trade>), SimulationError> {
+        self.house
+            .find_trade_offer(offer_id)
+            .filter(|(_, _, offer)| offer.offerer_id == agent_id)
+            .ok_or(SimulationError::OfferNotFound(offer_id))
+    }
+
+    /// Takes the agent's resting offer out of the order book
+    /// and gives back what was put up for it
+    pub fn cancel_offer(
+        &mut self,
+        agents: &mut Agents,
+        agent_id: u64,
+        offer_id: u64,
+    ) -> Result<FailedOffer<Trade>, SimulationError> {
+        self.find_offer_of(agent_id, offer_id)?;
+        let cancelled = self.house.cancel_trade_offer(agents, offer_id)?;
+        self.journal
+            .record(self.current_tick, Event::OfferCancelled { offer_id });
+        Ok(cancelled)
+    }
+
+    /// Moves the agent's resting offer to a new strike price and number of shares.
+    /// It's amended in place when it wouldn't be matched at the new price, otherwise
+    /// it's cancelled and sent to the market again as a new order of the same type.
+    /// An offer the agent couldn't afford at the new price and shares is left as it is
+    pub fn replace_offer(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        agent_id: u64,
+        offer_id: u64,
+        strike_price: f64,
+        number_of_shares: u64,
+    ) -> Result<ActionState, SimulationError> {
+        let (company_id, offer_ask, offer) = self.find_offer_of(agent_id, offer_id)?;
+        let todo_transaction = TodoTransaction {
+            agent_id,
+            company_id,
+            strike_price,
+            action: offer_ask,
+            trade: offer.data.resized(number_of_shares),
+            order_type: offer.order_type,
         };
-        if offer_idxs.is_empty() {
-            // If none, then add trade to the house
-            self.house
-                .add_trade_offer_from_todo_transaction(todo_transaction);
-            return Ok(None);
+        match self
+            .house
+            .amend_trade_offer(agents, offer_id, strike_price, number_of_shares)
+        {
+            Ok(()) => {
+                self.journal.record(
+                    self.current_tick,
+                    Event::OfferAmended {
+                        offer_id,
+                        strike_price,
+                        number_of_shares,
+                    },
+                );
+                return Ok(match number_of_shares {
+                    0 => ActionState::Cancelled(Vec::new()),
+                    _ => ActionState::AddedToOffers(offer_id),
+                });
+            }
+            Err(SimulationError::UnDoable) => {}
+            Err(error) => return Err(error),
         }
-        let all_offers = self.house.get_mut_trade_offers(todo_transaction.company_id);
-        let target_offers = match todo_transaction.action {
-            TradeAction::Buy => &mut all_offers.seller_offers,
-            TradeAction::Sell => &mut all_offers.buyer_offers,
-        };
+        agents.can_amend_order(offer_id, strike_price, number_of_shares)?;
+        self.cancel_offer(agents, agent_id, offer_id)?;
+        self.submit(&todo_transaction, agents, companies)
+    }
 
-        for offer_idx in offer_idxs.iter() {
-            let offer = target_offers[*offer_idx].clone();
-            // Don't autoresolve if it can be slightly worse for us
-            if offer.strike_price > todo_transaction.strike_price {
-                continue;
+    pub fn change_offer(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+        agent_id: u64,
+        offer_change: &OfferChange,
+    ) -> Result<ActionState, SimulationError> {
+        match *offer_change {
+            OfferChange::Cancel { offer_id } => {
+                self.cancel_offer(agents, agent_id, offer_id)?;
+                Ok(ActionState::Cancelled(Vec::new()))
             }
+            OfferChange::Replace {
+                offer_id,
+                strike_price,
+                number_of_shares,
+            } => self.replace_offer(
+                agents,
+                companies,
+                agent_id,
+                offer_id,
+                strike_price,
+                number_of_shares,
+            ),
+        }
+    }
 
-            // Resolve the offer
-            target_offers.remove(*offer_idx);
+    /// Takes the oldest triggered stop order out of the queue
+    pub(crate) fn pop_triggered_order(&mut self) -> Option<TodoTransaction> {
+        let todo_transaction = self.triggered_orders.pop_front()?;
+        self.journal.record(self.current_tick, Event::StopOrderSent);
+        Some(todo_transaction)
+    }
 
-            let transaction = self
-                .convert_trade_offer_and_todo_transaction_to_transaction(&offer, todo_transaction);
-            self.add_transaction(todo_transaction.company_id, transaction.strike_price);
-            agents.exchange_assets_from_transaction(&transaction)?;
-            return Ok(None);
+    /// Sends every triggered stop order to the market, including the ones
+    /// triggered by the trades of other triggered stop orders
+    pub fn trade_triggered_orders(
+        &mut self,
+        agents: &mut Agents,
+        companies: &mut Companies,
+    ) -> Vec<Result<ActionState, SimulationError>> {
+        let mut action_states = Vec::new();
+        while let Some(todo_transaction) = self.pop_triggered_order() {
+            action_states.push(self.trade(false, &todo_transaction, agents, companies, 0.0));
         }
-
-        return Ok(Some(
-            offer_idxs
-                .iter()
-                .map(|idx| target_offers[*idx].clone())
-                .collect(),
-        ));
+        action_states
     }
 
-    pub fn convert_trade_offer_and_todo_transaction_to_transaction(
+    /// Walks the opposite side of the order book, filling against the best resting offers
+    /// until either the transaction is resolved or no offer is within its strike price.
+    /// What happens with the unresolved shares depends on the order type.
+    ///
+    /// `acceptable_strike_price_deviation` is how much worse than its strike price
+    /// the agent is willing to trade, so the limit price it's matched within gets
+    /// widened by it. Whatever rests in the order book does so at the strike price itself
+    pub fn trade(
         &mut self,
-        offer: &Offer<Trade>,
+        willing_to_accept_company_shares_if_they_are_present: bool,
         todo_transaction: &TodoTransaction,
-    ) -> Transaction {
-        let (buyer_id, seller_id) = match todo_transaction.action {
-            TradeAction::Buy => (todo_transaction.agent_id, offer.offerer_id),
-            TradeAction::Sell => (offer.offerer_id, todo_transaction.agent_id),
+        agents: &mut Agents,
+        companies: &mut Companies,
+        acceptable_strike_price_deviation: f64,
+    ) -> Result<ActionState, SimulationError> {
+        let mut todo_transaction = todo_transaction.clone();
+        let limit_price = match (todo_transaction.order_type, todo_transaction.action) {
+            (OrderType::Market, TradeAction::Buy) => f64::INFINITY,
+            (OrderType::Market, TradeAction::Sell) => 0.0,
+            (_, TradeAction::Buy) => {
+                todo_transaction.strike_price + acceptable_strike_price_deviation
+            }
+            (_, TradeAction::Sell) => max(
+                0.0,
+                todo_transaction.strike_price - acceptable_strike_price_deviation,
+            ),
         };
 
-        if offer.data.number_of_shares == todo_transaction.trade.number_of_shares {
-            self.house
-                .remove_trade_offer(todo_transaction.company_id, offer.clone());
-            return Transaction::new(
-                buyer_id,
-                seller_id,
-                todo_transaction.company_id,
+        let number_of_shares_requested = todo_transaction.trade.number_of_shares;
+        let (available_shares, number_of_shares_used, worst_price) = self
+            .house
+            .get_mut_trade_offers(todo_transaction.company_id)
+            .liquidity(
+                todo_transaction.agent_id,
+                todo_transaction.action,
+                limit_price,
                 todo_transaction.trade.number_of_shares,
-                offer.strike_price,
+                self.self_trade_prevention,
             );
+        match (todo_transaction.order_type, todo_transaction.action) {
+            (OrderType::FillOrKill, _)
+                if available_shares < todo_transaction.trade.number_of_shares =>
+            {
+                return Ok(ActionState::Killed);
+            }
+            (OrderType::Market, TradeAction::Buy) => {
+                // The price isn't known up front, so what is available is put up for
+                // at the worst price it can be bought at, and the difference is given back.
+                // The shares decremented against the agent's own offers are left in
+                // for the sweep to cancel
+                if available_shares == 0 {
+                    return Ok(ActionState::Cancelled(Vec::new()));
+                }
+                todo_transaction.trade.number_of_shares = number_of_shares_used;
+                todo_transaction.strike_price = worst_price;
+            }
+            _ => {}
         }
 
-        if offer.data.number_of_shares > todo_transaction.trade.number_of_shares {
-            self.house.add_trade_offer(
-                offer.offerer_id,
-                todo_transaction.company_id,
-                offer.strike_price,
-                Trade::new(offer.data.number_of_shares - todo_transaction.trade.number_of_shares),
-                todo_transaction.action.complement(),
-            );
-            return Transaction::new(
+        if todo_transaction.order_type.rests()
+            && willing_to_accept_company_shares_if_they_are_present
+            && companies.check_lots_from_todotransaction(&todo_transaction)
+        {
+            let number_of_lots =
+                companies.add_bet_from_todotransaction(&todo_transaction, agents)?;
+            if number_of_lots > 0 {
+                self.journal.record(
+                    self.current_tick,
+                    Event::LotBet {
+                        company_id: todo_transaction.company_id,
+                        agent_id: todo_transaction.agent_id,
+                        number_of_lots,
+                    },
+                );
+                return Ok(ActionState::AddedToLots);
+            }
+        }
+        // A buyer puts up what it may pay while matching, and gets the difference
+        // back if the rest of the order waits in the order book at its strike price
+        let mut reserved_transaction = todo_transaction.clone();
+        if todo_transaction.action == TradeAction::Buy && limit_price.is_finite() {
+            reserved_transaction.strike_price = limit_price;
+        }
+        let order_id = agents.deduct_assets_from_todotransaction(&reserved_transaction)?;
+        if self.journal.is_enabled() {
+            if let Some(reservation) = agents.escrow.get(order_id) {
+                self.journal.record(
+                    self.current_tick,
+                    Event::OrderSubmitted {
+                        order_id,
+                        reservation: reservation.clone(),
+                    },
+                );
+            }
+        }
+
+        let (transactions, number_of_shares_cancelled) =
+            self.sweep(order_id, &todo_transaction, limit_price, agents)?;
+        let number_of_shares_resolved: u64 = transactions
+            .iter()
+            .map(|transaction| transaction.number_of_shares)
+            .sum();
+        let number_of_shares_left = todo_transaction.trade.number_of_shares
+            - number_of_shares_resolved
+            - number_of_shares_cancelled;
+
+        if number_of_shares_resolved == number_of_shares_requested {
+            return Ok(ActionState::InstantlyResolved(transactions));
+        }
+        if number_of_shares_left == 0 {
+            return Ok(ActionState::Cancelled(transactions));
+        }
+        if !todo_transaction.order_type.rests() {
+            agents.refund_whole_order(order_id)?;
+            self.journal
+                .record(self.current_tick, Event::OrderCancelled { order_id });
+            return Ok(ActionState::Cancelled(transactions));
+        }
+        todo_transaction.trade = todo_transaction.trade.resized(number_of_shares_left);
+        agents.reprice_order(order_id, todo_transaction.strike_price)?;
+        self.journal.record(
+            self.current_tick,
+            Event::OrderRested {
+                order_id,
+                todo_transaction: todo_transaction.clone(),
+            },
+        );
+        let offer_id = self
+            .house
+            .add_trade_offer_from_todo_transaction(order_id, &todo_transaction);
+        if transactions.is_empty() {
+            return Ok(ActionState::AddedToOffers(offer_id));
+        }
+        Ok(ActionState::PartiallyResolved(transactions, offer_id))
+    }
+
+    /// Fills the transaction against as many resting offers as its strike price allows,
+    /// best price first and then by arrival time.
+    ///
+    /// Returns the fills, and the number of shares of the transaction that were
+    /// cancelled (and refunded) to prevent it from being matched with the agent's own offers
+    fn sweep(
+        &mut self,
+        order_id: u64,
+        todo_transaction: &TodoTransaction,
+        limit_price: f64,
+        agents: &mut Agents,
+    ) -> Result<(Vec<Transaction>, u64), SimulationError> {
+        let company_id = todo_transaction.company_id;
+        let resting_action = todo_transaction.action.complement();
+        let mut transactions = Vec::new();
+        let mut number_of_shares_left = todo_transaction.trade.number_of_shares;
+        let mut number_of_shares_cancelled = 0;
+        while number_of_shares_left > 0 {
+            let offers = self.house.get_mut_trade_offers(company_id);
+            let Some(offer) = offers.best_matching_offer(todo_transaction.action, limit_price)
+            else {
+                break;
+            };
+            if offer.offerer_id == todo_transaction.agent_id {
+                self.self_trades_prevented += 1;
+                let number_of_shares = match self.self_trade_prevention {
+                    SelfTradePrevention::CancelNewest => number_of_shares_left,
+                    SelfTradePrevention::CancelOldest => 0,
+                    SelfTradePrevention::CancelBoth => number_of_shares_left,
+                    SelfTradePrevention::Decrement => {
+                        number_of_shares_left.min(offer.data.number_of_shares)
+                    }
+                };
+                let number_of_resting_shares = match self.self_trade_prevention {
+                    SelfTradePrevention::CancelNewest => 0,
+                    SelfTradePrevention::CancelOldest | SelfTradePrevention::CancelBoth => {
+                        offer.data.number_of_shares
+                    }
+                    SelfTradePrevention::Decrement => number_of_shares,
+                };
+                if number_of_resting_shares > 0 {
+                    if let Some(offer) = self.house.reduce_best_trade_offer(
+                        company_id,
+                        resting_action,
+                        number_of_resting_shares,
+                    ) {
+                        agents.refund_order(offer.id, number_of_resting_shares)?;
+                    }
+                }
+                if number_of_shares > 0 {
+                    agents.refund_order(order_id, number_of_shares)?;
+                }
+                self.journal.record(
+                    self.current_tick,
+                    Event::SelfTradePrevented {
+                        company_id,
+                        resting_action,
+                        resting_number_of_shares: number_of_resting_shares,
+                        order_id,
+                        number_of_shares,
+                    },
+                );
+                number_of_shares_left -= number_of_shares;
+                number_of_shares_cancelled += number_of_shares;
+                continue;
+            }
+
+            let Some((offer, number_of_shares)) = self.house.fill_best_matching_trade_offer(
+                company_id,
+                todo_transaction.action,
+                limit_price,
+                number_of_shares_left,
+            ) else {
+                break;
+            };
+            number_of_shares_left -= number_of_shares;
+
+            let ((buyer_id, buyer_order_id), (seller_id, seller_order_id)) =
+                match todo_transaction.action {
+                    TradeAction::Buy => (
+                        (todo_transaction.agent_id, order_id),
+                        (offer.offerer_id, offer.id),
+                    ),
+                    TradeAction::Sell => (
+                        (offer.offerer_id, offer.id),
+                        (todo_transaction.agent_id, order_id),
+                    ),
+                };
+            // Resting offers always get the price they asked for
+            let transaction = Transaction::new(
                 buyer_id,
                 seller_id,
-                todo_transaction.company_id,
-                todo_transaction.trade.number_of_shares,
+                company_id,
+                number_of_shares,
                 offer.strike_price,
+            )
+            .with_order_ids(buyer_order_id, seller_order_id);
+            self.add_transaction(company_id, transaction.strike_price, number_of_shares);
+            self.ledger.record_transaction(
+                self.current_tick,
+                &transaction,
+                todo_transaction.action,
+            );
+            agents.exchange_assets_from_transaction(&transaction)?;
+            self.journal.record(
+                self.current_tick,
+                Event::OrderFilled {
+                    transaction: transaction.clone(),
+                    aggressor: todo_transaction.action,
+                    limit_price,
+                },
             );
+            transactions.push(transaction);
         }
-        self.house.add_trade_offer(
-            todo_transaction.agent_id,
-            todo_transaction.company_id,
-            todo_transaction.strike_price,
-            Trade::new(todo_transaction.trade.number_of_shares - offer.data.number_of_shares),
-            todo_transaction.action,
-        );
-        Transaction::new(
-            buyer_id,
-            seller_id,
-            todo_transaction.company_id,
-            offer.data.number_of_shares,
-            offer.strike_price,
-        )
+        Ok((transactions, number_of_shares_cancelled))
     }
 
-    pub fn add_transaction(&mut self, company_id: u64, price: f64) {
-        let tracker = self.recent_transactions.entry(company_id).or_default();
-        tracker.push(price);
+    pub fn add_transaction(&mut self, company_id: u64, price: f64, number_of_shares: u64) {
+        self.candles
+            .add_trade(company_id, self.current_tick, price, number_of_shares);
+        self.statistics
+            .add_trade(company_id, price, number_of_shares);
+        self.triggered_orders.extend(
+            self.triggers
+                .trigger(company_id, price)
+                .iter()
+                .map(StopOrder::to_todo_transaction),
+        );
     }
+    /// Sets the market value of the company from its candle of the last
+    /// `MARKET_VALUE_RESOLUTION` ticks, if it had any trades in them
     pub fn tick_individual_company(&mut self, company_id: u64, market_value: &mut MarketValue) {
-        let recent_transactions = self.recent_transactions.entry(company_id).or_default();
-        if recent_transactions.is_empty() {
+        let Some(candle) = self
+            .candles
+            .latest(company_id, MARKET_VALUE_RESOLUTION)
+            .filter(|candle| candle.start_tick + MARKET_VALUE_RESOLUTION >= self.current_tick)
+        else {
             market_value.highest_price = market_value.current_price;
             market_value.lowest_price = market_value.current_price;
             return;
-        }
-        let max: f64 = recent_transactions
-            .iter()
-            .fold(recent_transactions[0], |a, &b| max(a, b));
-        let min: f64 = recent_transactions
-            .iter()
-            .fold(recent_transactions[0], |a, &b| min(a, b));
-        let sum: f64 = recent_transactions.iter().sum();
-        let avg = sum / (recent_transactions.len() as f64);
-
-        market_value.highest_price = max;
-        market_value.lowest_price = min;
+        };
+        market_value.highest_price = candle.high;
+        market_value.lowest_price = candle.low;
         market_value.overall_movement_start = market_value.overall_movement_end;
-        market_value.current_price = avg;
-        market_value.overall_movement_end = *recent_transactions.last().unwrap();
-
-        self.recent_transactions.clear();
+        market_value.current_price = candle.vwap();
+        market_value.overall_movement_end = candle.close;
     }
-
     pub fn tick_failures(
         &mut self,
-        expired_trades: &mut HashMap<u64, Vec<FailedOffer<Trade>>>,
-        expired_options: &mut HashMap<u64, Vec<FailedOffer<StockOption>>>,
+        expired_trades: &mut ExpiredOffers<Trade>,
+        expired_options: &mut ExpiredOffers<StockOption>,
     ) {
         let house_tick_data = self.house.tick();
-        expired_trades.extend(house_tick_data.0);
-        expired_options.extend(house_tick_data.1);
+        merge_expired_offers(expired_trades, house_tick_data.0);
+        merge_expired_offers(expired_options, house_tick_data.1);
+    }
+
+    /// Takes out the offers good till the current tick, see `OrderType::GoodTillTick`
+    pub fn expire_offers(
+        &mut self,
+        expired_trades: &mut ExpiredOffers<Trade>,
+        expired_options: &mut ExpiredOffers<StockOption>,
+    ) {
+        let (trades, options) = self.house.expire(self.current_tick);
+        merge_expired_offers(expired_trades, trades);
+        merge_expired_offers(expired_options, options);
+    }
+}
+
+fn merge_expired_offers<T: Clone + Default>(
+    expired_offers: &mut ExpiredOffers<T>,
+    other: ExpiredOffers<T>,
+) {
+    for (company_id, offers) in other {
+        expired_offers.entry(company_id).or_default().extend(offers);
     }
 }
 
@@ -0,0 +1,59 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+/// The generator behind every stream. It's the one `StdRng` uses, but unlike it
+/// it can be saved, so a restored simulation goes on drawing the same numbers
+pub type StreamRng = ChaCha12Rng;
+
+/// Hands out an independent random number stream to every subsystem, all derived
+/// from a single master seed. Drawing more numbers in one subsystem doesn't change
+/// what the others get, so two runs with the same seed do exactly the same thing.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RngStreams {
+    pub master_seed: u64,
+}
+
+impl RngStreams {
+    pub fn new(master_seed: u64) -> Self {
+        Self { master_seed }
+    }
+
+    /// The stream of the subsystem called `name`
+    pub fn stream(&self, name: &str) -> StreamRng {
+        StreamRng::seed_from_u64(split_mix(self.master_seed ^ fnv1a(name.as_bytes())))
+    }
+}
+
+/// The streams a running simulation draws from
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SimulationRngs {
+    pub companies: StreamRng,
+    pub agents: StreamRng,
+    pub market: StreamRng,
+}
+
+impl SimulationRngs {
+    pub fn new(rng_streams: &RngStreams) -> Self {
+        Self {
+            companies: rng_streams.stream("companies"),
+            agents: rng_streams.stream("agents"),
+            market: rng_streams.stream("market"),
+        }
+    }
+}
+
+/// FNV-1a hash, which unlike the std hashers is the same on every platform and version
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Spreads seeds that are close to each other all over the seed space
+fn split_mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,446 @@
+use crate::{
+    config::Config,
+    entities::{agents::Agents, companies::Companies},
+    invariants::{InvariantChecker, Violation},
+    journal::{Event, Journal},
+    log,
+    logger::Log,
+    market::Market,
+    rng::{RngStreams, SimulationRngs},
+    snapshot::{write_atomically, Snapshot},
+    strategies::{MarketView, Strategies, Strategy},
+    trade_house::{ExpiredOffers, StockOption, Trade},
+    transaction::TodoTransaction,
+    SerializationError, SimulationError,
+};
+use serde::{Deserialize, Serialize};
+
+/// How often the periodic events of the simulation happen, in ticks.
+/// A cadence of 0 turns the event off.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cadences {
+    /// Updating the market values of the companies and expiring offers
+    pub market_values: u64,
+    /// Companies releasing news and handing out their lots
+    pub news: u64,
+}
+
+impl Default for Cadences {
+    fn default() -> Self {
+        Self {
+            market_values: 5,
+            news: 20,
+        }
+    }
+}
+
+impl Cadences {
+    pub fn is_due(cadence: u64, tick: u64) -> bool {
+        cadence != 0 && tick.is_multiple_of(cadence)
+    }
+}
+
+/// The whole simulated world, moved forward one tick at a time
+pub struct Simulation {
+    agents: Agents,
+    companies: Companies,
+    market: Market,
+    config: Config,
+    rngs: SimulationRngs,
+    strategies: Strategies,
+    tick: u64,
+    todo_transactions: Vec<TodoTransaction>,
+    expired_trades: ExpiredOffers<Trade>,
+    expired_options: ExpiredOffers<StockOption>,
+    invariants: Option<InvariantChecker>,
+}
+
+impl Simulation {
+    pub fn new(
+        mut config: Config,
+        mut agents: Agents,
+        companies: Companies,
+        market: Market,
+    ) -> Self {
+        let rng_streams = RngStreams::new(config.resolve_seed());
+        agents.preferences.size_limit = config.agents.timeline_size_limit;
+        agents.portfolios.history_size = config.agents.equity_history_size;
+        agents.open_positions(&companies);
+        if agents.strategy_ids.is_empty() {
+            let shares: Vec<f64> = config
+                .agents
+                .strategies
+                .iter()
+                .map(|strategy_share| strategy_share.share)
+                .collect();
+            agents.assign_strategies(&shares);
+        }
+        Self {
+            agents,
+            companies,
+            market,
+            rngs: SimulationRngs::new(&rng_streams),
+            strategies: Strategies::from_config(&config),
+            config,
+            tick: 0,
+            todo_transactions: Vec::new(),
+            expired_trades: ExpiredOffers::new(),
+            expired_options: ExpiredOffers::new(),
+            invariants: None,
+        }
+    }
+
+    /// A freshly generated world of the size in the config
+    pub fn rand(mut config: Config) -> Result<Self, SimulationError> {
+        let seed = config.resolve_seed();
+        let companies = Self::rand_companies(seed, config.world.num_of_companies);
+        let agents =
+            Self::rand_agents(seed, config.world.num_of_agents, companies.num_of_companies)?;
+        let market = Market::from_config(&config.market);
+        Ok(Self::new(config, agents, companies, market))
+    }
+
+    /// Picks the world up where the snapshot left it
+    pub fn from_snapshot(config: Config, snapshot: Snapshot) -> Self {
+        let mut market = snapshot.market;
+        market.current_tick = snapshot.header.tick;
+        market.house.offer_lifetime = config.market.offer_lifetime;
+        let mut simulation = Self::new(config, snapshot.agents, snapshot.companies, market);
+        simulation.tick = snapshot.header.tick;
+        if let Some(rngs) = snapshot.rngs {
+            simulation.rngs = rngs;
+        }
+        simulation
+    }
+
+    /// Writes the whole world as a snapshot, tagged with the hash of the config
+    pub fn save_snapshot(&self, file_path: &str) -> Result<(), SerializationError> {
+        write_atomically(file_path, |writer| {
+            Snapshot::write_world(
+                writer,
+                self.config.hash(),
+                self.tick,
+                &self.agents,
+                &self.companies,
+                &self.market,
+                Some(&self.rngs),
+            )
+        })
+    }
+
+    pub fn rand_companies(seed: u64, num_of_companies: u64) -> Companies {
+        let mut rng = RngStreams::new(seed).stream("company generation");
+        Companies::rand(num_of_companies as usize, 0, &mut rng)
+    }
+
+    pub fn rand_agents(
+        seed: u64,
+        num_of_agents: u64,
+        num_of_companies: u64,
+    ) -> Result<Agents, SimulationError> {
+        let rng_streams = RngStreams::new(seed);
+        let mut agents = Agents::new();
+        agents.rand_introduce_new_agents(
+            rng_streams.stream("agent preferences"),
+            rng_streams.stream("agent balances"),
+            num_of_agents,
+            num_of_companies,
+        )?;
+        Ok(agents)
+    }
+
+    pub fn agents(&self) -> &Agents {
+        &self.agents
+    }
+
+    pub fn companies(&self) -> &Companies {
+        &self.companies
+    }
+
+    pub fn market(&self) -> &Market {
+        &self.market
+    }
+
+    /// The last tick that was simulated, 0 before the first step
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The config the simulation runs with, its seed is always set
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.config.seed.unwrap_or_default()
+    }
+
+    /// Transactions waiting to be traded loosely in the next step, see `Strategy::loose_orders`
+    pub fn pending_transactions(&self) -> &[TodoTransaction] {
+        &self.todo_transactions
+    }
+
+    /// Records every change made to the world in the journal from now on
+    pub fn record_journal(&mut self, journal: Journal) {
+        self.market.journal = journal;
+    }
+
+    /// Makes the strategy available to agents, returning its id
+    pub fn add_strategy(&mut self, strategy: Box<dyn Strategy>) -> u64 {
+        self.strategies.add(strategy)
+    }
+
+    pub fn assign_strategy(
+        &mut self,
+        agent_id: u64,
+        strategy_id: u64,
+    ) -> Result<(), SimulationError> {
+        self.agents.set_strategy(agent_id, strategy_id)
+    }
+
+    /// Checks after every event that no money or shares appear out of nowhere
+    pub fn check_invariants(&mut self) {
+        self.invariants = Some(InvariantChecker::new(&self.agents, &self.companies));
+    }
+
+    pub fn first_violation(&self) -> Option<&Violation> {
+        self.invariants
+            .as_ref()
+            .and_then(InvariantChecker::first_violation)
+    }
+
+    /// Logs where the simulation stopped adding up, if it is being checked
+    fn check(&mut self, event: &str) {
+        let Some(invariants) = self.invariants.as_mut() else {
+            return;
+        };
+        if let Err(violation) = invariants.check(
+            self.tick,
+            event,
+            &self.agents,
+            &self.companies,
+            &self.market,
+        ) {
+            log!(warn "Invariant violated at tick {} after {}: {:?}", violation.tick, violation.event, violation.divergence);
+        }
+    }
+
+    /// Simulates a single tick
+    pub fn step(&mut self) -> Result<(), SimulationError> {
+        self.tick += 1;
+        self.market.current_tick = self.tick;
+        self.market.journal.record(self.tick, Event::TickStarted);
+        self.agents.try_offers.clear();
+        self.market
+            .expire_offers(&mut self.expired_trades, &mut self.expired_options);
+        if Cadences::is_due(self.config.cadences.market_values, self.tick) {
+            for company_id in self.companies.iter() {
+                let Some(market_value) = self.companies.market_values.get_mut(company_id as usize)
+                else {
+                    continue;
+                };
+                self.market
+                    .tick_individual_company(company_id, market_value);
+            }
+            self.market
+                .tick_failures(&mut self.expired_trades, &mut self.expired_options);
+            self.agents.record_equity(self.tick, &self.companies);
+            self.market.journal.record(self.tick, Event::MarketTicked);
+            self.check("expiring offers");
+        }
+        if Cadences::is_due(self.config.cadences.news, self.tick) {
+            let news_release = self.companies.rand_release_news(
+                &mut self.agents,
+                &mut self.rngs.companies,
+                &self.config.companies,
+            )?;
+            self.market
+                .ledger
+                .record_company_transactions(self.tick, &news_release.company_transactions);
+            self.record_news_release(&news_release.lots_released);
+            if let Some(invariants) = self.invariants.as_mut() {
+                invariants.expect_cash(news_release.profit);
+                invariants.expect_company_transactions(&news_release.company_transactions);
+            }
+            self.check("releasing news");
+        }
+        self.record_expired_offers();
+        self.agents
+            .alert_agents(&self.expired_trades, &self.expired_options)?;
+        self.check("refunding expired offers");
+        self.expired_trades.clear();
+        self.expired_options.clear();
+
+        self.add_agent_transactions();
+        let news_probability_distribution = &self
+            .companies
+            .generate_preferences_from_news(&mut self.rngs.companies);
+        let preferences = self
+            .agents
+            .rand_give_preferences_from_news(&mut self.rngs.agents, news_probability_distribution);
+        self.market
+            .journal
+            .record(self.tick, Event::PreferencesAdded { preferences });
+        let trade_result = self.market.rand_do_trade(
+            &mut self.rngs.market,
+            &mut self.agents,
+            &mut self.companies,
+            &mut self.todo_transactions,
+            &self.config.market,
+        );
+        self.todo_transactions.clear();
+        self.check("trading");
+        if let Err(e) = self.market.ledger.save() {
+            log!(warn "Failed to save the ledger\n{:?}", e);
+        }
+        if let Err(e) = self.market.journal.save() {
+            log!(warn "Failed to save the journal\n{:?}", e);
+        }
+        trade_result
+    }
+
+    /// Journals what every company did in the news release, in the order they did it
+    fn record_news_release(&mut self, lots_released: &[u64]) {
+        if !self.market.journal.is_enabled() {
+            return;
+        }
+        for company_id in self.companies.iter() {
+            let id = company_id as usize;
+            self.market
+                .journal
+                .record(self.tick, Event::LotsDistributed { company_id });
+            if lots_released.contains(&company_id) {
+                let lots = self.companies.lots[id].clone();
+                self.market
+                    .journal
+                    .record(self.tick, Event::LotsReleased { company_id, lots });
+            }
+            self.market.journal.record(
+                self.tick,
+                Event::NewsReleased {
+                    company_id,
+                    news: self.companies.news[id],
+                    balance: self.companies.balances[id],
+                },
+            );
+        }
+        self.market.journal.record(
+            self.tick,
+            Event::HypeSet {
+                hype: self.companies.hype,
+            },
+        );
+    }
+
+    /// Journals the offers that are about to be refunded and retried
+    fn record_expired_offers(&mut self) {
+        if !self.market.journal.is_enabled() {
+            return;
+        }
+        for (company_id, offers) in self.expired_trades.iter() {
+            for offer in offers.iter() {
+                self.market.journal.record(
+                    self.tick,
+                    Event::OfferExpired {
+                        company_id: *company_id,
+                        agent_id: offer.0.offerer_id,
+                        action: offer.1,
+                        strike_price: offer.0.strike_price,
+                        order_id: Some(offer.0.id),
+                    },
+                );
+            }
+        }
+        for (company_id, offers) in self.expired_options.iter() {
+            for offer in offers.iter() {
+                self.market.journal.record(
+                    self.tick,
+                    Event::OfferExpired {
+                        company_id: *company_id,
+                        agent_id: offer.0.offerer_id,
+                        action: offer.1,
+                        strike_price: offer.0.strike_price,
+                        order_id: None,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Every agent decides on its orders with the strategy it's assigned.
+    /// Agents assigned a strategy that isn't there don't trade.
+    ///
+    /// Orders of strategies that don't send loose orders are sent right away,
+    /// the others wait with the pending transactions to be traded loosely
+    fn add_agent_transactions(&mut self) {
+        let view = MarketView::new(self.tick, &self.agents, &self.companies, &self.market);
+        let mut offer_changes = Vec::new();
+        let mut orders = Vec::new();
+        for agent_id in self.agents.iter() {
+            let Some(strategy) = self.strategies.get(self.agents.strategy_id(agent_id)) else {
+                continue;
+            };
+            // an agent whose strategy fails sits the tick out, the others still trade
+            let decision = strategy
+                .change_offers(agent_id, &view, &mut self.rngs.agents)
+                .and_then(|changes| {
+                    let todo_transactions =
+                        strategy.decide(agent_id, &view, &mut self.rngs.agents)?;
+                    Ok((changes, todo_transactions))
+                });
+            let (changes, todo_transactions) = match decision {
+                Ok(decision) => decision,
+                Err(e) => {
+                    log!(warn "Agent {} couldn't decide what to trade\n{:?}", agent_id, e);
+                    continue;
+                }
+            };
+            offer_changes.extend(
+                changes
+                    .into_iter()
+                    .map(|offer_change| (agent_id, offer_change)),
+            );
+            if strategy.loose_orders() {
+                self.todo_transactions.extend(todo_transactions);
+            } else {
+                orders.extend(todo_transactions);
+            }
+        }
+        for (agent_id, offer_change) in offer_changes.iter() {
+            // the offer may have been filled or expired since the strategy saw it
+            _ = self.market.change_offer(
+                &mut self.agents,
+                &mut self.companies,
+                *agent_id,
+                offer_change,
+            );
+        }
+        for todo_transaction in orders.iter() {
+            // a failed order of one agent shouldn't stop the others from trading
+            _ = self
+                .market
+                .submit(todo_transaction, &mut self.agents, &mut self.companies);
+        }
+    }
+
+    /// Simulates `number_of_ticks` ticks, stopping at the first error
+    pub fn run_for(&mut self, number_of_ticks: u64) -> Result<(), SimulationError> {
+        for _ in 0..number_of_ticks {
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    /// Simulates ticks until the predicate holds, checking it before every step.
+    /// Stops at the first error.
+    pub fn run_until(
+        &mut self,
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Result<(), SimulationError> {
+        while !predicate(self) {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
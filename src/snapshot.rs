@@ -0,0 +1,382 @@
+use crate::{
+    config::MarketConfig,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company, Lots, MarketValue},
+        escrow::Reservation,
+    },
+    market::Market,
+    rng::{fnv1a, SimulationRngs},
+    trade_house::TradeAction,
+    DeserializationError, SerializationError,
+};
+use bincode::Options;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{BufReader, BufWriter, Read, Write},
+};
+
+/// Every snapshot file starts with these
+pub const SNAPSHOT_MAGIC: [u8; 8] = *b"STOCKSIM";
+/// Version of the snapshot format written.
+/// Version 0 is the headerless pair of agent and company files saved before snapshots,
+/// version 1 only kept what was saved in them besides the market, without open orders
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+const AGENTS_SECTION: u32 = 1;
+const COMPANIES_SECTION: u32 = 2;
+const MARKET_SECTION: u32 = 3;
+const RNGS_SECTION: u32 = 4;
+const STRATEGIES_SECTION: u32 = 5;
+const PORTFOLIOS_SECTION: u32 = 6;
+
+/// What a snapshot says about itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    pub version: u32,
+    /// Hash of the config the world was simulated with, 0 if it's unknown
+    pub config_hash: u64,
+    /// The last tick that was simulated
+    pub tick: u64,
+}
+
+/// The saved state of the world.
+///
+/// On disk it is the magic bytes, the header and its checksum, followed by
+/// the sections of the world, each tagged with its kind, length and checksum.
+/// Sections of unknown kinds are skipped, so newer versions can add some
+pub struct Snapshot {
+    pub header: SnapshotHeader,
+    pub agents: Agents,
+    pub companies: Companies,
+    pub market: Market,
+    /// Where the random number streams were, none if the snapshot was migrated
+    /// from a version that didn't save them
+    pub rngs: Option<SimulationRngs>,
+}
+
+fn encode<T: Serialize + ?Sized>(data: &T) -> Result<Vec<u8>, SerializationError> {
+    bincode::serialize(data).map_err(|_| SerializationError::FailedToSerialize)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, DeserializationError> {
+    bincode::deserialize(bytes).map_err(|_| DeserializationError::FailedToSerialize)
+}
+
+/// Decodes a whole file, bytes left over mean it was saved in another layout
+fn decode_file<T: DeserializeOwned>(file_path: &str) -> Result<T, DeserializationError> {
+    let Ok(bytes) = fs::read(file_path) else {
+        return Err(DeserializationError::FileNotFound);
+    };
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+        .deserialize(&bytes)
+        .map_err(|_| DeserializationError::FailedToSerialize)
+}
+
+/// Lots as they were saved before snapshots. Bets were paid for
+/// straight from the balances of the bettors, without orders
+#[derive(Deserialize)]
+struct LegacyLots {
+    strike_price: f64,
+    number_of_lots: u64,
+    lot_size: u64,
+    bets: HashMap<u64, u64>,
+    total_num_of_bets: u64,
+}
+
+/// A company as it was saved before snapshots.
+/// Bincode isn't self-describing, so this has to keep that layout as it was
+#[derive(Deserialize)]
+struct LegacyCompany {
+    id: u64,
+    market_value: MarketValue,
+    balance: f64,
+    expected_profit: f64,
+    news: f64,
+    lots: LegacyLots,
+    lot_finalization_time: u64,
+}
+
+impl LegacyCompany {
+    /// Puts what was paid for the bets in escrow, as it is now while they're open
+    fn migrate(self, agents: &mut Agents) -> Company {
+        let mut lots = Lots::new(
+            self.lots.strike_price,
+            self.lots.number_of_lots,
+            self.lots.lot_size,
+        );
+        lots.total_num_of_bets = self.lots.total_num_of_bets;
+        lots.bets = self.lots.bets.into_iter().collect();
+        for (&agent_id, &number_of_lots) in lots.bets.iter() {
+            let order_id = agents.escrow.reserve(Reservation {
+                agent_id,
+                company_id: self.id,
+                action: TradeAction::Buy,
+                strike_price: lots.strike_price,
+                number_of_shares: lots.lot_size * number_of_lots,
+            });
+            lots.bet_order_ids.insert(agent_id, order_id);
+        }
+        Company {
+            id: self.id,
+            market_value: self.market_value,
+            balance: self.balance,
+            expected_profit: self.expected_profit,
+            news: self.news,
+            lots,
+            lot_finalization_time: self.lot_finalization_time,
+        }
+    }
+}
+
+/// Writes to a temporary file next to the target and moves it in place once it's
+/// complete, so that a crash never leaves a half written file behind
+pub(crate) fn write_atomically(
+    file_path: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), SerializationError>,
+) -> Result<(), SerializationError> {
+    let temp_file_path = format!("{}.tmp", file_path);
+    let Ok(file) = File::create(&temp_file_path) else {
+        return Err(SerializationError::FailedToCreateFile);
+    };
+    let mut writer = BufWriter::new(file);
+    write(&mut writer)?;
+    let Ok(file) = writer.into_inner() else {
+        return Err(SerializationError::FailedToWrite);
+    };
+    if file.sync_all().is_err() || fs::rename(&temp_file_path, file_path).is_err() {
+        return Err(SerializationError::FailedToWrite);
+    }
+    Ok(())
+}
+
+fn read_bytes<const N: usize>(reader: &mut impl Read) -> Result<[u8; N], DeserializationError> {
+    let mut bytes = [0; N];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|_| DeserializationError::CorruptedSnapshot)?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32, DeserializationError> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u64(reader: &mut impl Read) -> Result<u64, DeserializationError> {
+    Ok(u64::from_le_bytes(read_bytes(reader)?))
+}
+
+impl Snapshot {
+    pub fn new(
+        config_hash: u64,
+        tick: u64,
+        agents: Agents,
+        companies: Companies,
+        market: Market,
+        rngs: Option<SimulationRngs>,
+    ) -> Self {
+        Self {
+            header: SnapshotHeader {
+                version: SNAPSHOT_VERSION,
+                config_hash,
+                tick,
+            },
+            agents,
+            companies,
+            market,
+            rngs,
+        }
+    }
+
+    /// Writes the world in the current version, without having to own it
+    pub fn write_world(
+        writer: &mut impl Write,
+        config_hash: u64,
+        tick: u64,
+        agents: &Agents,
+        companies: &Companies,
+        market: &Market,
+        rngs: Option<&SimulationRngs>,
+    ) -> Result<(), SerializationError> {
+        let mut sections = vec![
+            (AGENTS_SECTION, encode(agents)?),
+            (COMPANIES_SECTION, encode(companies)?),
+            (MARKET_SECTION, encode(market)?),
+            (STRATEGIES_SECTION, encode(&agents.strategy_ids)?),
+            (PORTFOLIOS_SECTION, encode(&agents.portfolios)?),
+        ];
+        if let Some(rngs) = rngs {
+            sections.push((RNGS_SECTION, encode(rngs)?));
+        }
+        let mut header = Vec::new();
+        header.extend_from_slice(&SNAPSHOT_MAGIC);
+        header.extend_from_slice(&SNAPSHOT_VERSION.to_le_bytes());
+        header.extend_from_slice(&config_hash.to_le_bytes());
+        header.extend_from_slice(&tick.to_le_bytes());
+        header.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        header.extend_from_slice(&fnv1a(&header).to_le_bytes());
+
+        let mut write = |bytes: &[u8]| {
+            writer
+                .write_all(bytes)
+                .map_err(|_| SerializationError::FailedToWrite)
+        };
+        write(&header)?;
+        for (kind, bytes) in sections.iter() {
+            write(&kind.to_le_bytes())?;
+            write(&(bytes.len() as u64).to_le_bytes())?;
+            write(&fnv1a(bytes).to_le_bytes())?;
+            write(bytes)?;
+        }
+        writer
+            .flush()
+            .map_err(|_| SerializationError::FailedToWrite)
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> Result<(), SerializationError> {
+        Self::write_world(
+            writer,
+            self.header.config_hash,
+            self.header.tick,
+            &self.agents,
+            &self.companies,
+            &self.market,
+            self.rngs.as_ref(),
+        )
+    }
+
+    /// Reads just the header, checking its checksum
+    pub fn read_header(reader: &mut impl Read) -> Result<SnapshotHeader, DeserializationError> {
+        Ok(Self::read_header_and_number_of_sections(reader)?.0)
+    }
+
+    fn read_header_and_number_of_sections(
+        reader: &mut impl Read,
+    ) -> Result<(SnapshotHeader, u32), DeserializationError> {
+        let Ok(magic) = read_bytes::<8>(reader) else {
+            return Err(DeserializationError::NotASnapshot);
+        };
+        if magic != SNAPSHOT_MAGIC {
+            return Err(DeserializationError::NotASnapshot);
+        }
+        let version = read_u32(reader)?;
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(DeserializationError::UnsupportedVersion(version));
+        }
+        let config_hash = read_u64(reader)?;
+        let tick = read_u64(reader)?;
+        let number_of_sections = read_u32(reader)?;
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&magic);
+        header.extend_from_slice(&version.to_le_bytes());
+        header.extend_from_slice(&config_hash.to_le_bytes());
+        header.extend_from_slice(&tick.to_le_bytes());
+        header.extend_from_slice(&number_of_sections.to_le_bytes());
+        if read_u64(reader)? != fnv1a(&header) {
+            return Err(DeserializationError::CorruptedSnapshot);
+        }
+        let snapshot_header = SnapshotHeader {
+            version,
+            config_hash,
+            tick,
+        };
+        Ok((snapshot_header, number_of_sections))
+    }
+
+    pub fn read(reader: &mut impl Read) -> Result<Self, DeserializationError> {
+        let (header, number_of_sections) = Self::read_header_and_number_of_sections(reader)?;
+        let mut sections = BTreeMap::new();
+        for _ in 0..number_of_sections {
+            let kind = read_u32(reader)?;
+            let length = read_u64(reader)?;
+            let checksum = read_u64(reader)?;
+            let mut bytes = Vec::new();
+            reader
+                .take(length)
+                .read_to_end(&mut bytes)
+                .map_err(|_| DeserializationError::CorruptedSnapshot)?;
+            if bytes.len() as u64 != length || fnv1a(&bytes) != checksum {
+                return Err(DeserializationError::CorruptedSnapshot);
+            }
+            sections.insert(kind, bytes);
+        }
+        let section = |kind: u32| {
+            sections
+                .get(&kind)
+                .ok_or(DeserializationError::CorruptedSnapshot)
+        };
+        let mut market: Market = decode(section(MARKET_SECTION)?)?;
+        market.house.reindex();
+        if header.version == 1 {
+            let agents = decode::<Vec<Agent>>(section(AGENTS_SECTION)?)?;
+            let companies = decode::<Vec<Company>>(section(COMPANIES_SECTION)?)?;
+            return Ok(Self {
+                header,
+                agents: Agents::load(&agents),
+                companies: Companies::load(&companies),
+                market,
+                rngs: None,
+            });
+        }
+        let mut agents: Agents = decode(section(AGENTS_SECTION)?)?;
+        // snapshots saved before agents had strategies don't have them
+        if let Some(bytes) = sections.get(&STRATEGIES_SECTION) {
+            agents.strategy_ids = decode(bytes)?;
+        }
+        // nor the ones saved before positions were kept, they're opened when simulated
+        if let Some(bytes) = sections.get(&PORTFOLIOS_SECTION) {
+            agents.portfolios = decode(bytes)?;
+        }
+        Ok(Self {
+            header,
+            agents,
+            companies: decode(section(COMPANIES_SECTION)?)?,
+            market,
+            rngs: sections
+                .get(&RNGS_SECTION)
+                .map(|bytes| decode(bytes))
+                .transpose()?,
+        })
+    }
+
+    pub fn save(&self, file_path: &str) -> Result<(), SerializationError> {
+        write_atomically(file_path, |writer| self.write(writer))
+    }
+
+    pub fn load(file_path: &str) -> Result<Self, DeserializationError> {
+        let Ok(file) = File::open(file_path) else {
+            return Err(DeserializationError::FileNotFound);
+        };
+        Self::read(&mut BufReader::new(file))
+    }
+
+    /// Migrates the agent and company files saved before snapshots (version 0).
+    /// Their market was never saved and their config is unknown,
+    /// so an empty market is set up from the given config.
+    /// What was paid for open lot bets is moved to escrow
+    pub fn from_legacy_files(
+        agents_file_path: &str,
+        companies_file_path: &str,
+        market_config: &MarketConfig,
+    ) -> Result<Self, DeserializationError> {
+        let mut agents = Agents::load(&decode_file::<Vec<Agent>>(agents_file_path)?);
+        let companies: Vec<Company> = decode_file::<Vec<LegacyCompany>>(companies_file_path)?
+            .into_iter()
+            .map(|company| company.migrate(&mut agents))
+            .collect();
+        let market = Market::from_config(market_config);
+        Ok(Self::new(
+            0,
+            0,
+            agents,
+            Companies::load(&companies),
+            market,
+            None,
+        ))
+    }
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+
+/// The most recent trades of a single company
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct RollingWindow {
+    /// Price and number of shares of every trade, oldest first
+    trades: VecDeque<(f64, u64)>,
+}
+
+impl RollingWindow {
+    fn add_trade(&mut self, price: f64, number_of_shares: u64, window_size: usize) {
+        self.trades.push_back((price, number_of_shares));
+        while self.trades.len() > window_size {
+            self.trades.pop_front();
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    pub fn prices(&self) -> impl Iterator<Item = f64> + '_ {
+        self.trades.iter().map(|(price, _)| *price)
+    }
+
+    pub fn last_price(&self) -> Option<f64> {
+        self.trades.back().map(|(price, _)| *price)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.prices().sum::<f64>() / self.len() as f64)
+    }
+
+    /// Population standard deviation of the prices
+    pub fn standard_deviation(&self) -> Option<f64> {
+        let mean = self.mean()?;
+        let variance = self
+            .prices()
+            .map(|price| (price - mean).powi(2))
+            .sum::<f64>()
+            / self.len() as f64;
+        Some(variance.sqrt())
+    }
+
+    /// Simple returns from one trade to the next
+    pub fn returns(&self) -> Vec<f64> {
+        self.prices()
+            .zip(self.prices().skip(1))
+            .filter(|(previous, _)| *previous != 0.0)
+            .map(|(previous, price)| price / previous - 1.0)
+            .collect()
+    }
+
+    /// Square root of the sum of squared log returns
+    pub fn realized_volatility(&self) -> Option<f64> {
+        if self.len() < 2 {
+            return None;
+        }
+        let sum_of_squares: f64 = self
+            .prices()
+            .zip(self.prices().skip(1))
+            .filter(|(previous, price)| *previous > 0.0 && *price > 0.0)
+            .map(|(previous, price)| (price / previous).ln().powi(2))
+            .sum();
+        Some(sum_of_squares.sqrt())
+    }
+
+    /// Number of shares traded
+    pub fn volume(&self) -> u64 {
+        self.trades
+            .iter()
+            .map(|(_, number_of_shares)| number_of_shares)
+            .sum()
+    }
+
+    /// Money that changed hands
+    pub fn turnover(&self) -> f64 {
+        self.trades
+            .iter()
+            .map(|(price, number_of_shares)| price * *number_of_shares as f64)
+            .sum()
+    }
+}
+
+/// Rolling statistics of every company over its last `window_size` trades.
+/// Every company only ever sees its own trades.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MarketStatistics {
+    pub window_size: usize,
+    windows: BTreeMap<u64, RollingWindow>,
+}
+
+impl Default for MarketStatistics {
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl MarketStatistics {
+    pub fn new(window_size: usize) -> Self {
+        Self {
+            window_size,
+            windows: BTreeMap::new(),
+        }
+    }
+
+    pub fn add_trade(&mut self, company_id: u64, price: f64, number_of_shares: u64) {
+        self.windows.entry(company_id).or_default().add_trade(
+            price,
+            number_of_shares,
+            self.window_size,
+        );
+    }
+
+    /// The trades of the company in the window, if it had any
+    pub fn get(&self, company_id: u64) -> Option<&RollingWindow> {
+        self.windows.get(&company_id)
+    }
+}
@@ -0,0 +1,187 @@
+use crate::{
+    max,
+    rng::RngStreams,
+    strategies::{MarketView, Strategy},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    DeserializationError, SimulationError,
+};
+use rand::{Rng, RngCore};
+use rand_distr::{Distribution, Normal};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeMap};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FundamentalConfig {
+    /// Number of news releases worth of expected profit a company is valued at, besides its balance
+    pub earnings_multiple: f64,
+    /// Standard deviation of every agent's error in its estimate, as a portion of the fair value
+    pub estimate_noise: f64,
+    /// How far from the estimate the price has to be before agents trade,
+    /// as a portion of the estimate
+    pub threshold: f64,
+    /// Portion of its money an agent buys an undervalued company with,
+    /// and portion of its shares it sells of an overvalued one
+    pub position_size: f64,
+    /// How much above the current price agents buy and below it they sell,
+    /// as a portion of the current price. They never trade past their estimate
+    pub price_tolerance: f64,
+}
+
+impl Default for FundamentalConfig {
+    fn default() -> Self {
+        Self {
+            earnings_multiple: 10.0,
+            estimate_noise: 0.1,
+            threshold: 0.05,
+            position_size: 0.1,
+            price_tolerance: 0.01,
+        }
+    }
+}
+
+impl FundamentalConfig {
+    pub fn validate(&self) -> Result<(), DeserializationError> {
+        let invalid = |reason: &str| {
+            DeserializationError::InvalidConfig(format!("fundamental strategy: {}", reason))
+        };
+        for (name, value) in [
+            ("earnings_multiple", self.earnings_multiple),
+            ("estimate_noise", self.estimate_noise),
+            ("threshold", self.threshold),
+            ("price_tolerance", self.price_tolerance),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(invalid(&format!("{} can't be negative", name)));
+            }
+        }
+        if !(self.position_size > 0.0 && self.position_size <= 1.0) {
+            return Err(invalid("position_size has to be above 0 and at most 1"));
+        }
+        Ok(())
+    }
+}
+
+/// Estimates what a random company is worth from its balance, expected profit
+/// and issued shares, then buys it below the estimate and sells it above.
+///
+/// Every agent is off in its estimate of every company by its own error,
+/// which stays the same from tick to tick
+#[derive(Debug, Clone)]
+pub struct FundamentalStrategy {
+    pub config: FundamentalConfig,
+    pub min_strike_price: f64,
+    /// Seed the errors in the estimates are drawn from
+    pub seed: u64,
+    /// The error of every agent in its estimate of every company, by agent and company id.
+    /// They're drawn the first time they're needed
+    errors: RefCell<BTreeMap<(u64, u64), f64>>,
+}
+
+impl FundamentalStrategy {
+    pub fn new(config: FundamentalConfig, min_strike_price: f64, seed: u64) -> Self {
+        Self {
+            config,
+            min_strike_price,
+            seed,
+            errors: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    /// What a share of the company is worth, none until it has issued any
+    pub fn fair_value(&self, view: &MarketView, company_id: u64) -> Option<f64> {
+        let id = company_id as usize;
+        let companies = view.companies();
+        let (Some(balance), Some(expected_profit)) = (
+            companies.balances.get(id),
+            companies.expected_profits.get(id),
+        ) else {
+            return None;
+        };
+        let issued_shares = view.issued_shares(company_id);
+        if issued_shares == 0 {
+            return None;
+        }
+        let value = balance + expected_profit * self.config.earnings_multiple;
+        Some(max(0.0, value / issued_shares as f64))
+    }
+
+    /// The fair value as the agent sees it
+    pub fn estimate(&self, agent_id: u64, view: &MarketView, company_id: u64) -> Option<f64> {
+        let fair_value = self.fair_value(view, company_id)?;
+        Some(max(0.0, fair_value * (1.0 + self.error(agent_id, company_id))))
+    }
+
+    /// How far off the agent is in its estimate of the company, as a portion of the fair value
+    fn error(&self, agent_id: u64, company_id: u64) -> f64 {
+        *self
+            .errors
+            .borrow_mut()
+            .entry((agent_id, company_id))
+            .or_insert_with(|| {
+                let Ok(normal) = Normal::new(0.0, self.config.estimate_noise) else {
+                    return 0.0;
+                };
+                let mut rng = RngStreams::new(self.seed).stream(&format!(
+                    "fundamental estimate of agent {} for company {}",
+                    agent_id, company_id
+                ));
+                normal.sample(&mut rng)
+            })
+    }
+}
+
+impl Strategy for FundamentalStrategy {
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        let num_of_companies = view.companies().num_of_companies;
+        if num_of_companies == 0 {
+            return Ok(Vec::new());
+        }
+        let company_id = rng.gen_range(0..num_of_companies);
+        let (Some(estimate), Some(current_price)) = (
+            self.estimate(agent_id, view, company_id),
+            view.price(company_id),
+        ) else {
+            return Ok(Vec::new());
+        };
+
+        let (action, strike_price, number_of_shares) =
+            if current_price < estimate * (1.0 - self.config.threshold) {
+                let strike_price = max(
+                    self.min_strike_price,
+                    estimate.min(current_price * (1.0 + self.config.price_tolerance)),
+                );
+                let want_to_spend = view.balance(agent_id)? * self.config.position_size;
+                let number_of_shares = (want_to_spend / strike_price).floor() as u64;
+                (TradeAction::Buy, strike_price, number_of_shares)
+            } else if current_price > estimate * (1.0 + self.config.threshold) {
+                let strike_price = max(
+                    self.min_strike_price,
+                    estimate.max(current_price * (1.0 - self.config.price_tolerance)),
+                );
+                let holding = view.holding(agent_id, company_id);
+                let number_of_shares = (holding as f64 * self.config.position_size).ceil() as u64;
+                (TradeAction::Sell, strike_price, number_of_shares)
+            } else {
+                return Ok(Vec::new());
+            };
+        if number_of_shares == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id,
+            strike_price,
+            action,
+            trade: Trade::new(number_of_shares),
+            order_type: OrderType::Limit,
+        }])
+    }
+}
@@ -0,0 +1,223 @@
+use crate::{
+    market::OfferChange,
+    max,
+    strategies::{MarketView, Strategy},
+    trade_house::{Offer, OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    DeserializationError, SimulationError,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarketMakerConfig {
+    /// Distance between the bid and the ask, as a portion of the reference price
+    pub spread: f64,
+    /// Number of shares quoted on each side
+    pub quote_size: u64,
+    /// Number of shares agents try to hold, their quotes lean towards getting back to it
+    pub target_inventory: u64,
+    /// Number of shares agents never buy past
+    pub max_inventory: u64,
+    /// How far both quotes move when agents hold `max_inventory` shares
+    /// more than their target, as a portion of the reference price
+    pub skew: f64,
+}
+
+impl Default for MarketMakerConfig {
+    fn default() -> Self {
+        Self {
+            spread: 0.02,
+            quote_size: 10,
+            target_inventory: 50,
+            max_inventory: 100,
+            skew: 0.01,
+        }
+    }
+}
+
+impl MarketMakerConfig {
+    pub fn validate(&self) -> Result<(), DeserializationError> {
+        let invalid = |reason: &str| {
+            DeserializationError::InvalidConfig(format!("market maker strategy: {}", reason))
+        };
+        if !(self.spread.is_finite() && self.spread > 0.0 && self.spread < 2.0) {
+            return Err(invalid("spread has to be above 0 and below 2"));
+        }
+        if !(self.skew.is_finite() && self.skew >= 0.0) {
+            return Err(invalid("skew can't be negative"));
+        }
+        if self.quote_size == 0 {
+            return Err(invalid("quote_size has to be at least 1"));
+        }
+        if self.max_inventory == 0 || self.target_inventory > self.max_inventory {
+            return Err(invalid(
+                "max_inventory has to be at least 1 and at least target_inventory",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The strike price and number of shares of a quote, no shares means no quote
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quote {
+    pub strike_price: f64,
+    pub number_of_shares: u64,
+}
+
+/// Quotes both sides of a company around its last price, and requotes them every tick.
+///
+/// Every agent makes the market of the company its id falls on, wrapping around
+/// the companies. Its quotes lean down while it holds more shares than its target
+/// and up while it holds fewer, and it stops buying once it holds `max_inventory`
+#[derive(Debug, Clone)]
+pub struct MarketMakerStrategy {
+    pub config: MarketMakerConfig,
+    pub min_strike_price: f64,
+}
+
+impl MarketMakerStrategy {
+    /// The company the agent makes the market of
+    pub fn company_id(&self, agent_id: u64, view: &MarketView) -> Option<u64> {
+        let num_of_companies = view.companies().num_of_companies;
+        (num_of_companies > 0).then(|| agent_id % num_of_companies)
+    }
+
+    /// The price quotes are put around: the last trade of the company,
+    /// or its market value until it has traded
+    pub fn reference_price(&self, view: &MarketView, company_id: u64) -> Option<f64> {
+        view.statistics(company_id)
+            .and_then(|statistics| statistics.last_price())
+            .or_else(|| view.price(company_id))
+            .filter(|price| *price > 0.0)
+    }
+
+    /// The bid and the ask the agent wants resting this tick, counting
+    /// what is already put up for its resting offers as its own
+    pub fn quotes(&self, agent_id: u64, view: &MarketView) -> Option<(Quote, Quote)> {
+        let company_id = self.company_id(agent_id, view)?;
+        let reference_price = self.reference_price(view, company_id)?;
+        let resting_offers = view.resting_offers(agent_id, company_id);
+        let put_up = |offer_ask: TradeAction| {
+            resting_offers
+                .iter()
+                .filter(move |(action, _)| *action == offer_ask)
+                .map(|(_, offer)| offer)
+        };
+        let inventory = view.holding(agent_id, company_id)
+            + put_up(TradeAction::Sell)
+                .map(|offer| offer.data.number_of_shares)
+                .sum::<u64>();
+        let money = view.balance(agent_id).ok()?
+            + put_up(TradeAction::Buy)
+                .map(|offer| offer.strike_price * offer.data.number_of_shares as f64)
+                .sum::<f64>();
+
+        let imbalance = (inventory as f64 - self.config.target_inventory as f64)
+            / self.config.max_inventory as f64;
+        let center = reference_price * (1.0 - self.config.skew * imbalance);
+        let bid_price = center * (1.0 - self.config.spread / 2.0);
+        let ask_price = max(
+            self.min_strike_price,
+            center * (1.0 + self.config.spread / 2.0),
+        );
+        // below the lowest strike price the bid could cross the agent's own ask, so it's dropped
+        let bid_size = if bid_price >= self.min_strike_price {
+            self.config
+                .quote_size
+                .min(self.config.max_inventory.saturating_sub(inventory))
+                .min((money / bid_price).floor() as u64)
+        } else {
+            0
+        };
+        let bid = Quote {
+            strike_price: bid_price,
+            number_of_shares: bid_size,
+        };
+        let ask = Quote {
+            strike_price: ask_price,
+            number_of_shares: self.config.quote_size.min(inventory),
+        };
+        Some((bid, ask))
+    }
+
+    /// The agent's resting offers of the company it makes the market of
+    fn resting_offers<'a>(
+        &self,
+        agent_id: u64,
+        view: &MarketView<'a>,
+    ) -> Vec<(TradeAction, &'a Offer<Trade>)> {
+        self.company_id(agent_id, view)
+            .map(|company_id| view.resting_offers(agent_id, company_id))
+            .unwrap_or_default()
+    }
+}
+
+impl Strategy for MarketMakerStrategy {
+    /// New quotes for the sides the agent has no resting offer on
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        let (Some(company_id), Some((bid, ask))) =
+            (self.company_id(agent_id, view), self.quotes(agent_id, view))
+        else {
+            return Ok(Vec::new());
+        };
+        let resting_offers = self.resting_offers(agent_id, view);
+        Ok([(TradeAction::Buy, bid), (TradeAction::Sell, ask)]
+            .into_iter()
+            .filter(|(action, quote)| {
+                quote.number_of_shares > 0
+                    && !resting_offers
+                        .iter()
+                        .any(|(offer_ask, _)| offer_ask == action)
+            })
+            .map(|(action, quote)| TodoTransaction {
+                agent_id,
+                company_id,
+                strike_price: quote.strike_price,
+                action,
+                trade: Trade::new(quote.number_of_shares),
+                order_type: OrderType::GoodTillCancelled,
+            })
+            .collect())
+    }
+
+    /// Moves the resting quotes to this tick's prices and sizes,
+    /// and cancels any other resting offer of the agent
+    fn change_offers(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<OfferChange>, SimulationError> {
+        let quotes = self.quotes(agent_id, view);
+        let mut requoted = Vec::new();
+        Ok(self
+            .resting_offers(agent_id, view)
+            .into_iter()
+            .map(|(offer_ask, offer)| {
+                let quote = quotes.map(|(bid, ask)| match offer_ask {
+                    TradeAction::Buy => bid,
+                    TradeAction::Sell => ask,
+                });
+                match quote {
+                    Some(quote) if !requoted.contains(&offer_ask) => {
+                        requoted.push(offer_ask);
+                        OfferChange::Replace {
+                            offer_id: offer.id,
+                            strike_price: quote.strike_price,
+                            number_of_shares: quote.number_of_shares,
+                        }
+                    }
+                    _ => OfferChange::Cancel { offer_id: offer.id },
+                }
+            })
+            .collect())
+    }
+}
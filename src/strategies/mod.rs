@@ -0,0 +1,271 @@
+use crate::{
+    candles::CandleSeries,
+    config::Config,
+    entities::{
+        agents::{Agents, Preferences},
+        companies::{Companies, MarketValue},
+    },
+    invariants::Totals,
+    market::{Market, OfferChange},
+    statistics::RollingWindow,
+    trade_house::{Offer, Trade, TradeAction},
+    transaction::TodoTransaction,
+    DeserializationError, SimulationError,
+};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::cell::OnceCell;
+
+pub mod fundamental;
+pub mod market_maker;
+pub mod momentum;
+pub mod preferences;
+
+use fundamental::{FundamentalConfig, FundamentalStrategy};
+use market_maker::{MarketMakerConfig, MarketMakerStrategy};
+use momentum::{MomentumConfig, MomentumStrategy};
+use preferences::PreferencesStrategy;
+
+/// Decides what an agent trades every tick.
+///
+/// A strategy is shared by every agent assigned to it, so whatever
+/// is particular to an agent has to come from its id or the view
+pub trait Strategy {
+    /// The orders the agent sends to the market this tick
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError>;
+
+    /// The changes the agent makes to its resting offers this tick,
+    /// they are made before any of the orders are sent
+    fn change_offers(
+        &self,
+        _agent_id: u64,
+        _view: &MarketView,
+        _rng: &mut dyn RngCore,
+    ) -> Result<Vec<OfferChange>, SimulationError> {
+        Ok(Vec::new())
+    }
+
+    /// Whether the orders are matched up to the acceptable strike price deviation
+    /// past their strike price, and may be diverted to the lots of the company.
+    /// Otherwise they're sent exactly as they are, see `Market::submit`
+    fn loose_orders(&self) -> bool {
+        false
+    }
+}
+
+/// What strategies get to see of the world, it can't be changed through it
+pub struct MarketView<'a> {
+    tick: u64,
+    agents: &'a Agents,
+    companies: &'a Companies,
+    market: &'a Market,
+    /// Only measured once a strategy asks for it
+    totals: OnceCell<Totals>,
+}
+
+impl<'a> MarketView<'a> {
+    pub fn new(
+        tick: u64,
+        agents: &'a Agents,
+        companies: &'a Companies,
+        market: &'a Market,
+    ) -> Self {
+        Self {
+            tick,
+            agents,
+            companies,
+            market,
+            totals: OnceCell::new(),
+        }
+    }
+
+    /// The tick being simulated
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn companies(&self) -> &'a Companies {
+        self.companies
+    }
+
+    pub fn market_value(&self, company_id: u64) -> Option<&'a MarketValue> {
+        self.companies.market_values.get(company_id as usize)
+    }
+
+    pub fn price(&self, company_id: u64) -> Option<f64> {
+        self.companies.get_current_price(company_id)
+    }
+
+    pub fn candles(&self, company_id: u64, resolution: u64) -> Option<&'a CandleSeries> {
+        self.market.candles.get(company_id, resolution)
+    }
+
+    /// The shares of the company held by agents or put up for their orders
+    pub fn issued_shares(&self, company_id: u64) -> u64 {
+        let totals = self
+            .totals
+            .get_or_init(|| Totals::measure(self.agents, self.companies));
+        totals.shares.get(&company_id).copied().unwrap_or_default()
+    }
+
+    /// Rolling statistics of the recent trades of the company
+    pub fn statistics(&self, company_id: u64) -> Option<&'a RollingWindow> {
+        self.market.statistics.get(company_id)
+    }
+
+    /// The highest price a resting offer buys the company at
+    pub fn best_bid(&self, company_id: u64) -> Option<f64> {
+        let offers = self.market.house.get_trade_offers(company_id)?;
+        Some(offers.best_buyer_offer()?.strike_price)
+    }
+
+    /// The lowest price a resting offer sells the company at
+    pub fn best_ask(&self, company_id: u64) -> Option<f64> {
+        let offers = self.market.house.get_trade_offers(company_id)?;
+        Some(offers.best_seller_offer()?.strike_price)
+    }
+
+    /// The money the agent can spend, not counting what is put up for its orders
+    pub fn balance(&self, agent_id: u64) -> Result<f64, SimulationError> {
+        self.agents.balances.get(agent_id)
+    }
+
+    /// The shares of the company the agent can sell,
+    /// not counting the ones put up for its orders
+    pub fn holding(&self, agent_id: u64, company_id: u64) -> u64 {
+        self.agents.holdings.get(agent_id, company_id)
+    }
+
+    /// The agent's resting offers of the company, best price first and then by arrival time
+    pub fn resting_offers(
+        &self,
+        agent_id: u64,
+        company_id: u64,
+    ) -> Vec<(TradeAction, &'a Offer<Trade>)> {
+        let Some(offers) = self.market.house.get_trade_offers(company_id) else {
+            return Vec::new();
+        };
+        [TradeAction::Buy, TradeAction::Sell]
+            .into_iter()
+            .flat_map(|offer_ask| {
+                offers
+                    .iter_by_priority(offer_ask)
+                    .filter(|offer| offer.offerer_id == agent_id)
+                    .map(move |offer| (offer_ask, offer))
+            })
+            .collect()
+    }
+
+    pub fn preferences(&self) -> &'a Preferences {
+        &self.agents.preferences
+    }
+}
+
+/// The built-in strategies, as they are set up in the config
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum StrategyConfig {
+    /// Trades a company picked from the agent's preference timeline around its current price
+    Preferences,
+    /// Buys companies that are rising and sells them once they turn
+    Momentum(MomentumConfig),
+    /// Buys companies below what they're worth and sells them above it
+    Fundamental(FundamentalConfig),
+    /// Quotes both sides of a company and requotes them every tick
+    MarketMaker(MarketMakerConfig),
+}
+
+/// A strategy and the share of the agents that trade with it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct StrategyShare {
+    pub strategy: StrategyConfig,
+    pub share: f64,
+}
+
+impl Default for StrategyShare {
+    fn default() -> Self {
+        Self {
+            strategy: StrategyConfig::Preferences,
+            share: 1.0,
+        }
+    }
+}
+
+impl StrategyConfig {
+    pub fn validate(&self, config: &Config) -> Result<(), DeserializationError> {
+        match self {
+            Self::Preferences => Ok(()),
+            Self::Momentum(momentum) => momentum.validate(&config.market.candle_resolutions),
+            Self::Fundamental(fundamental) => fundamental.validate(),
+            Self::MarketMaker(market_maker) => market_maker.validate(),
+        }
+    }
+
+    pub fn build(&self, config: &Config) -> Box<dyn Strategy> {
+        match self {
+            Self::Preferences => Box::new(PreferencesStrategy {
+                contrarian_probability: config.agents.contrarian_probability,
+                min_strike_price: config.market.min_strike_price,
+            }),
+            Self::Momentum(momentum) => Box::new(MomentumStrategy {
+                config: momentum.clone(),
+                min_strike_price: config.market.min_strike_price,
+            }),
+            Self::Fundamental(fundamental) => Box::new(FundamentalStrategy::new(
+                fundamental.clone(),
+                config.market.min_strike_price,
+                config.seed.unwrap_or_default(),
+            )),
+            Self::MarketMaker(market_maker) => Box::new(MarketMakerStrategy {
+                config: market_maker.clone(),
+                min_strike_price: config.market.min_strike_price,
+            }),
+        }
+    }
+}
+
+/// Every strategy agents can be assigned, by id
+#[derive(Default)]
+pub struct Strategies(Vec<Box<dyn Strategy>>);
+
+impl Strategies {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The strategies in the config, their ids are their positions in it
+    pub fn from_config(config: &Config) -> Self {
+        Self(
+            config
+                .agents
+                .strategies
+                .iter()
+                .map(|strategy_share| strategy_share.strategy.build(config))
+                .collect(),
+        )
+    }
+
+    /// Returns the id of the strategy
+    pub fn add(&mut self, strategy: Box<dyn Strategy>) -> u64 {
+        self.0.push(strategy);
+        self.0.len() as u64 - 1
+    }
+
+    pub fn get(&self, strategy_id: u64) -> Option<&dyn Strategy> {
+        self.0.get(strategy_id as usize).map(Box::as_ref)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{
+    candles::CandleSeries,
+    max,
+    strategies::{MarketView, Strategy},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    DeserializationError, SimulationError,
+};
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+/// How a trend is read from the closes of the candles
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum TrendSignal {
+    /// The moving average of the last `short_lookback` closes against
+    /// the one of the last `long_lookback` closes
+    #[default]
+    Crossover,
+    /// The return since the close `long_lookback` candles ago
+    Return,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct MomentumConfig {
+    pub signal: TrendSignal,
+    /// Number of candles the short moving average is taken over
+    pub short_lookback: usize,
+    /// Number of candles the long moving average, or the return, is taken over
+    pub long_lookback: usize,
+    /// Length in ticks of the candles the trend is read from
+    pub resolution: u64,
+    /// Portion of its money an agent buys into a rising company with
+    pub position_size: f64,
+    /// How much above the last close agents buy and below it they sell,
+    /// as a portion of the last close
+    pub price_tolerance: f64,
+}
+
+impl Default for MomentumConfig {
+    fn default() -> Self {
+        Self {
+            signal: TrendSignal::Crossover,
+            short_lookback: 5,
+            long_lookback: 20,
+            resolution: 1,
+            position_size: 0.1,
+            price_tolerance: 0.01,
+        }
+    }
+}
+
+impl MomentumConfig {
+    pub fn validate(&self, candle_resolutions: &[u64]) -> Result<(), DeserializationError> {
+        let invalid = |reason: &str| {
+            DeserializationError::InvalidConfig(format!("momentum strategy: {}", reason))
+        };
+        if !candle_resolutions.contains(&self.resolution) {
+            return Err(invalid(
+                "resolution has to be one of market.candle_resolutions",
+            ));
+        }
+        if self.short_lookback == 0 || self.long_lookback == 0 {
+            return Err(invalid("lookbacks have to be at least 1"));
+        }
+        if self.signal == TrendSignal::Crossover && self.short_lookback >= self.long_lookback {
+            return Err(invalid("short_lookback has to be below long_lookback"));
+        }
+        if !(self.position_size > 0.0 && self.position_size <= 1.0) {
+            return Err(invalid("position_size has to be above 0 and at most 1"));
+        }
+        if !(self.price_tolerance.is_finite() && self.price_tolerance >= 0.0) {
+            return Err(invalid("price_tolerance can't be negative"));
+        }
+        Ok(())
+    }
+}
+
+/// Follows the trend of a random company: buys into it while it rises
+/// and sells everything it holds of it once it turns
+#[derive(Debug, Clone)]
+pub struct MomentumStrategy {
+    pub config: MomentumConfig,
+    pub min_strike_price: f64,
+}
+
+fn mean(prices: &[f64]) -> f64 {
+    prices.iter().sum::<f64>() / prices.len() as f64
+}
+
+impl MomentumStrategy {
+    /// Positive when the company is rising, negative when it's falling.
+    /// None until there are enough candles to tell
+    pub fn trend(&self, series: &CandleSeries) -> Option<f64> {
+        let lookback = match self.config.signal {
+            TrendSignal::Crossover => self.config.long_lookback,
+            TrendSignal::Return => self.config.long_lookback + 1,
+        };
+        if series.candles.len() < lookback {
+            return None;
+        }
+        let closes: Vec<f64> = series
+            .candles
+            .iter()
+            .skip(series.candles.len() - lookback)
+            .map(|candle| candle.close)
+            .collect();
+        match self.config.signal {
+            TrendSignal::Crossover => {
+                let short = &closes[closes.len() - self.config.short_lookback..];
+                Some(mean(short) - mean(&closes))
+            }
+            TrendSignal::Return if closes[0] > 0.0 => Some(closes[lookback - 1] / closes[0] - 1.0),
+            TrendSignal::Return => None,
+        }
+    }
+}
+
+impl Strategy for MomentumStrategy {
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        rng: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        let num_of_companies = view.companies().num_of_companies;
+        if num_of_companies == 0 {
+            return Ok(Vec::new());
+        }
+        let company_id = rng.gen_range(0..num_of_companies);
+        let Some(series) = view.candles(company_id, self.config.resolution) else {
+            return Ok(Vec::new());
+        };
+        let (Some(trend), Some(last_candle)) = (self.trend(series), series.candles.back()) else {
+            return Ok(Vec::new());
+        };
+
+        let (action, strike_price, number_of_shares) = if trend > 0.0 {
+            let strike_price = max(
+                self.min_strike_price,
+                last_candle.close * (1.0 + self.config.price_tolerance),
+            );
+            let want_to_spend = view.balance(agent_id)? * self.config.position_size;
+            let number_of_shares = (want_to_spend / strike_price).floor() as u64;
+            (TradeAction::Buy, strike_price, number_of_shares)
+        } else if trend < 0.0 {
+            let strike_price = max(
+                self.min_strike_price,
+                last_candle.close * (1.0 - self.config.price_tolerance),
+            );
+            let number_of_shares = view.holding(agent_id, company_id);
+            (TradeAction::Sell, strike_price, number_of_shares)
+        } else {
+            return Ok(Vec::new());
+        };
+        if number_of_shares == 0 {
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id,
+            strike_price,
+            action,
+            trade: Trade::new(number_of_shares),
+            order_type: OrderType::Limit,
+        }])
+    }
+}
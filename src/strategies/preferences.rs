@@ -0,0 +1,78 @@
+use crate::{
+    max,
+    strategies::{MarketView, Strategy},
+    trade_house::{OrderType, Trade},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+use rand::{Rng, RngCore};
+use rand_distr::{Distribution, Normal};
+
+fn spend_function(x: f64) -> f64 {
+    // went off feeling
+    0.99 * (1.0 - (-0.01 * x * x).exp()) + 0.01
+}
+
+pub(crate) fn rand_spend_portion_wealth(rng: &mut impl Rng) -> f64 {
+    let Ok(normal) = Normal::new(0.0, 1.0) else {
+        // If the normal distribution fails, fuck it then
+        return 0.01;
+    };
+    spend_function(normal.sample(rng))
+}
+
+/// Picks a company from the agent's preference timeline and trades it
+/// around its current price, with a random portion of the agent's money
+#[derive(Debug, Clone)]
+pub struct PreferencesStrategy {
+    /// Chance of selling what the agent would like to buy and the other way around
+    pub contrarian_probability: f64,
+    pub min_strike_price: f64,
+}
+
+impl Strategy for PreferencesStrategy {
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        mut rng: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        let (company_id, mut action) = view
+            .preferences()
+            .get_preferred_random(agent_id, &mut rng)?;
+
+        // small portion of people who sell low and buy high, because .... IDK WHY
+        if rng.gen_bool(self.contrarian_probability) {
+            action = action.complement();
+        }
+
+        // preferred companies always have a price, so the fallback is never stored
+        // as the market value of one. Writing the price back left it as it was
+        let failable_value = rng.gen_range(10.0..2_000.0);
+        let current_price = view.price(company_id).unwrap_or(failable_value);
+        let strike_price = max(
+            self.min_strike_price,
+            current_price + rng.gen_range(-10.0..10.0),
+        );
+        let want_to_spend = view.balance(agent_id)? * rand_spend_portion_wealth(&mut rng);
+        let rough_amount_of_stocks = (want_to_spend / strike_price).floor() as u64;
+        if rough_amount_of_stocks == 0 {
+            // bruh, just don't trade anything
+            return Ok(Vec::new());
+        }
+
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id,
+            strike_price,
+            action,
+            trade: Trade::new(rough_amount_of_stocks),
+            order_type: OrderType::Limit,
+        }])
+    }
+
+    /// The agents haggle a little past their strike price, and take lots when offered
+    fn loose_orders(&self) -> bool {
+        true
+    }
+}
@@ -1,30 +1,67 @@
-use crate::{transaction::TodoTransaction, OFFER_LIFETIME};
-use rand::random;
+use crate::{
+    entities::agents::Agents, market::SelfTradePrevention, transaction::TodoTransaction,
+    SimulationError, OFFER_LIFETIME,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+};
+
+/// Offers that ran out of lifetime, grouped by company id
+pub type ExpiredOffers<T> = BTreeMap<u64, Vec<FailedOffer<T>>>;
 
 /// Basically stores all the requested trades that weren't immediately resolved
-#[derive(Serialize, Deserialize, Debug, Default)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct TradeHouse {
-    trade_offers: HashMap<u64, Offers<Trade>>,
-    option_offers: HashMap<u64, Offers<StockOption>>,
+    trade_offers: BTreeMap<u64, Offers<Trade>>,
+    option_offers: BTreeMap<u64, Offers<StockOption>>,
+    /// Number of times the trade house has ticked
+    current_tick: u64,
+    /// Trade offers take the id of their order, option offers are numbered here
+    next_option_offer_id: u64,
+    /// Number of ticks limit offers rest in the order book
+    pub offer_lifetime: u64,
+    /// The company of every resting trade offer, by offer id.
+    /// It isn't saved, `reindex` builds it back from the order books
+    #[serde(skip)]
+    offer_companies: BTreeMap<u64, u64>,
 }
 
-/// All the offers of the certain company
+impl Default for TradeHouse {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The order book of a certain company
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Offers<T>
 where
     T: Clone + Default,
 {
-    pub seller_offers: Vec<Offer<T>>,
-    pub buyer_offers: Vec<Offer<T>>,
-    pub lowest_strike_price: f64,
-    pub highest_strike_price: f64,
+    pub seller_offers: BookSide<T>,
+    pub buyer_offers: BookSide<T>,
 }
 
+/// One side of an order book.
+/// Offers are grouped into price levels, and every level is a FIFO queue,
+/// so the front of a level is always the offer that arrived first.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BookSide<T>
+where
+    T: Clone + Default,
+{
+    levels: BTreeMap<Price, VecDeque<Offer<T>>>,
+    /// The price level of every offer on this side, by offer id
+    index: BTreeMap<u64, Price>,
+}
+
+/// A strike price that can be used as a key of a price level
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct Price(pub f64);
+
 /// A specific offer
-///
-///todo Allow users to cancel their offers (to get their holdings back)
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Offer<T>
 where
@@ -35,15 +72,118 @@ where
     pub strike_price: f64,
     pub data: T,
     pub lifetime: u64,
+    pub order_type: OrderType,
+}
+
+/// How an order is resolved and how long whatever remains of it rests in the order book
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum OrderType {
+    /// Rests in the order book for the offer lifetime of the trade house, in its ticks
+    #[default]
+    Limit,
+    /// Takes whatever is in the order book regardless of the strike price,
+    /// whatever couldn't be resolved is refunded
+    Market,
+    /// Resolves what it can within the strike price, the rest is refunded
+    ImmediateOrCancel,
+    /// Resolves every share within the strike price, or nothing at all
+    FillOrKill,
+    /// Rests in the order book until it's resolved or cancelled
+    GoodTillCancelled,
+    /// Rests in the order book until the given tick of the simulation starts
+    GoodTillTick(u64),
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Trade {
+    /// Every share of the trade, including the ones that aren't shown
     pub number_of_shares: u64,
+    pub visibility: Visibility,
+}
+
+/// How much of a resting trade offer is shown in the order book
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+pub enum Visibility {
+    #[default]
+    Visible,
+    /// Only `shown` shares (at most `peak`) are shown and can be matched at a time.
+    /// Once they are resolved, the next `peak` shares are shown and the offer goes
+    /// to the back of its price level
+    Iceberg { peak: u64, shown: u64 },
+    /// Can be matched, but is never shown in the order book
+    Hidden,
+}
+
+/// What the order book of a company shows, best price levels first
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BookSnapshot {
+    /// Price and number of shown shares of every buyer price level
+    pub buyer_levels: Vec<(f64, u64)>,
+    /// Price and number of shown shares of every seller price level
+    pub seller_levels: Vec<(f64, u64)>,
 }
+
 impl Trade {
     pub fn new(number_of_shares: u64) -> Self {
-        Self { number_of_shares }
+        Self {
+            number_of_shares,
+            visibility: Visibility::Visible,
+        }
+    }
+    pub fn iceberg(number_of_shares: u64, peak: u64) -> Self {
+        Self {
+            number_of_shares,
+            visibility: Visibility::Iceberg {
+                peak,
+                shown: peak.min(number_of_shares),
+            },
+        }
+    }
+    pub fn hidden(number_of_shares: u64) -> Self {
+        Self {
+            number_of_shares,
+            visibility: Visibility::Hidden,
+        }
+    }
+    /// The same kind of trade with a different number of shares, showing a fresh peak
+    pub fn resized(&self, number_of_shares: u64) -> Self {
+        match self.visibility {
+            Visibility::Iceberg { peak, .. } => Self::iceberg(number_of_shares, peak),
+            visibility => Self {
+                number_of_shares,
+                visibility,
+            },
+        }
+    }
+    /// Number of shares shown in the order book
+    pub fn shown_shares(&self) -> u64 {
+        match self.visibility {
+            Visibility::Visible => self.number_of_shares,
+            Visibility::Iceberg { shown, .. } => shown,
+            Visibility::Hidden => 0,
+        }
+    }
+    /// Number of shares that can be matched before the offer has to be refreshed
+    pub fn matchable_shares(&self) -> u64 {
+        match self.visibility {
+            Visibility::Iceberg { shown, .. } => shown,
+            _ => self.number_of_shares,
+        }
+    }
+    /// Takes resolved shares out of the trade.
+    ///
+    /// Returns true if an iceberg ran out of shown shares and showed its next peak
+    fn resolve(&mut self, number_of_shares: u64) -> bool {
+        self.number_of_shares -= number_of_shares;
+        let Visibility::Iceberg { peak, shown } = &mut self.visibility else {
+            return false;
+        };
+        *shown -= number_of_shares;
+        if *shown != 0 || self.number_of_shares == 0 {
+            return false;
+        }
+        *shown = (*peak).min(self.number_of_shares);
+        true
     }
 }
 
@@ -79,16 +219,81 @@ impl TradeAction {
             TradeAction::Sell => TradeAction::Buy,
         }
     }
+    /// Whether an offer of this action at `strike_price` can be matched
+    /// with a resting offer of the complementary action at `offer_price`
+    pub fn crosses(&self, strike_price: f64, offer_price: f64) -> bool {
+        match self {
+            TradeAction::Buy => offer_price <= strike_price,
+            TradeAction::Sell => offer_price >= strike_price,
+        }
+    }
+}
+
+impl OrderType {
+    /// Whether the unresolved part of the order is put in the order book
+    pub fn rests(&self) -> bool {
+        matches!(
+            self,
+            OrderType::Limit | OrderType::GoodTillCancelled | OrderType::GoodTillTick(_)
+        )
+    }
+}
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Price {}
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
 }
 
 impl TradeHouse {
     pub fn new() -> Self {
         Self {
-            trade_offers: HashMap::new(),
-            option_offers: HashMap::new(),
+            trade_offers: BTreeMap::new(),
+            option_offers: BTreeMap::new(),
+            current_tick: 0,
+            next_option_offer_id: 0,
+            offer_lifetime: OFFER_LIFETIME,
+            offer_companies: BTreeMap::new(),
         }
     }
 
+    pub fn current_tick(&self) -> u64 {
+        self.current_tick
+    }
+
+    fn new_offer<T: Clone + Default>(
+        &self,
+        offerer_id: u64,
+        strike_price: f64,
+        data: T,
+        order_type: OrderType,
+    ) -> Offer<T> {
+        let mut offer = Offer::new(offerer_id, strike_price, data);
+        offer.lifetime = self.offer_lifetime;
+        offer.order_type = order_type;
+        offer
+    }
+
+    /// The trade offers of every company that has any
+    pub fn trade_offers(&self) -> impl Iterator<Item = (&u64, &Offers<Trade>)> {
+        self.trade_offers.iter()
+    }
+
+    pub fn get_trade_offers(&self, company_id: u64) -> Option<&Offers<Trade>> {
+        self.trade_offers.get(&company_id)
+    }
+
     pub fn get_mut_trade_offers(&mut self, company_id: u64) -> &mut Offers<Trade> {
         self.trade_offers.entry(company_id).or_default()
     }
@@ -97,202 +302,240 @@ impl TradeHouse {
         self.option_offers.entry(company_id).or_default()
     }
 
-    pub fn add_trade_offer(
+    /// Puts the transaction in the order book under the id of the order
+    /// whose reservation it is resolved from, which is returned
+    pub fn add_trade_offer_from_todo_transaction(
         &mut self,
-        offerer_id: u64,
-        company_id: u64,
-        strike_price: f64,
-        trade: Trade,
-        offer_ask: TradeAction,
-    ) {
-        let company_stock_trade = self.get_mut_trade_offers(company_id);
-        (match offer_ask {
-            TradeAction::Buy => &mut company_stock_trade.buyer_offers,
-            TradeAction::Sell => &mut company_stock_trade.seller_offers,
-        })
-        .push(Offer::new(offerer_id, strike_price, trade));
-    }
-
-    pub fn add_trade_offer_from_todo_transaction(&mut self, todo_transaction: &TodoTransaction) {
-        self.add_trade_offer(
+        order_id: u64,
+        todo_transaction: &TodoTransaction,
+    ) -> u64 {
+        let mut offer = self.new_offer(
             todo_transaction.agent_id,
-            todo_transaction.company_id,
             todo_transaction.strike_price,
             todo_transaction.trade.clone(),
-            todo_transaction.action,
+            todo_transaction.order_type,
         );
+        offer.id = order_id;
+        self.get_mut_trade_offers(todo_transaction.company_id)
+            .add_offer(offer, todo_transaction.action);
+        self.offer_companies
+            .insert(order_id, todo_transaction.company_id);
+        order_id
     }
 
     pub fn remove_trade_offer(&mut self, company_id: u64, offer: Offer<Trade>) {
-        self.get_mut_trade_offers(company_id)
-            .remove_offer(offer.id as usize);
+        self.get_mut_trade_offers(company_id).remove_offer(offer.id);
+        self.offer_companies.remove(&offer.id);
     }
 
-    pub fn add_option_offer(
-        &mut self,
-        offerer_id: u64,
-        company_id: u64,
-        strike_price: f64,
-        option: StockOption,
-        offer_ask: TradeAction,
-    ) {
-        let company_stock_trade = self.get_mut_option_offers(company_id);
-        (match offer_ask {
-            TradeAction::Buy => &mut company_stock_trade.buyer_offers,
-            TradeAction::Sell => &mut company_stock_trade.seller_offers,
-        })
-        .push(Offer::new(offerer_id, strike_price, option));
+    /// Finds a resting trade offer by its id
+    pub fn find_trade_offer(&self, offer_id: u64) -> Option<(u64, TradeAction, &Offer<Trade>)> {
+        let company_id = *self.offer_companies.get(&offer_id)?;
+        let (offer_ask, offer) = self.trade_offers.get(&company_id)?.get_offer(offer_id)?;
+        Some((company_id, offer_ask, offer))
     }
 
-    pub fn remove_option_offer(&mut self, company_id: u64, offer: Offer<StockOption>) {
-        self.get_mut_option_offers(company_id)
-            .remove_offer(offer.id as usize);
+    /// Builds back the company of every resting trade offer, after the trade house is loaded
+    pub fn reindex(&mut self) {
+        self.offer_companies = self
+            .trade_offers
+            .iter()
+            .flat_map(|(company_id, offers)| {
+                let offer_ids = offers
+                    .buyer_offers
+                    .iter()
+                    .chain(offers.seller_offers.iter());
+                offer_ids.map(move |offer| (offer.id, *company_id))
+            })
+            .collect();
     }
 
-    pub fn get_appropriate_trade_offer(
+    /// See `Offers::fill_best_matching_offer`, forgets the offer once it's filled entirely
+    pub fn fill_best_matching_trade_offer(
         &mut self,
         company_id: u64,
+        action: TradeAction,
         strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-        offer_ask: TradeAction,
-    ) -> Option<Vec<usize>> {
-        match offer_ask {
-            TradeAction::Buy => self.get_appropriate_buyer_trade_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
-            TradeAction::Sell => self.get_appropriate_seller_trade_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
+        number_of_shares: u64,
+    ) -> Option<(Offer<Trade>, u64)> {
+        let (offer, filled) = self
+            .get_mut_trade_offers(company_id)
+            .fill_best_matching_offer(action, strike_price, number_of_shares)?;
+        if filled >= offer.data.number_of_shares {
+            self.offer_companies.remove(&offer.id);
         }
+        Some((offer, filled))
     }
 
-    /// Returns the indices of trade_house.trade_offers which matches the strike_price
-    pub fn get_appropriate_buyer_trade_offer(
+    /// See `Offers::reduce_best_offer`, forgets the offer once it has no shares left
+    pub fn reduce_best_trade_offer(
         &mut self,
         company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.trade_offers
-                .get(&company_id)?
-                .buyer_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price >= strike_price - acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+        offer_ask: TradeAction,
+        number_of_shares: u64,
+    ) -> Option<Offer<Trade>> {
+        let offer = self
+            .get_mut_trade_offers(company_id)
+            .reduce_best_offer(offer_ask, number_of_shares)?;
+        if number_of_shares >= offer.data.number_of_shares {
+            self.offer_companies.remove(&offer.id);
+        }
+        Some(offer)
     }
 
-    /// Returns the indices of trade_house.trade_offers which matches the strike_price
-    pub fn get_appropriate_seller_trade_offer(
+    /// Takes the offer out of the order book and gives the agent back
+    /// the money or shares that were put up for it
+    pub fn cancel_trade_offer(
         &mut self,
-        company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.trade_offers
-                .get(&company_id)?
-                .seller_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price <= strike_price + acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+        agents: &mut Agents,
+        offer_id: u64,
+    ) -> Result<FailedOffer<Trade>, SimulationError> {
+        let Some((company_id, _, _)) = self.find_trade_offer(offer_id) else {
+            return Err(SimulationError::OfferNotFound(offer_id));
+        };
+        let offers = self.get_mut_trade_offers(company_id);
+        let Some((offer_ask, offer)) = offers.remove_offer_with_action(offer_id) else {
+            return Err(SimulationError::OfferNotFound(offer_id));
+        };
+        self.offer_companies.remove(&offer_id);
+        agents.refund_whole_order(offer.id)?;
+        Ok(FailedOffer(offer, offer_ask))
     }
 
-    pub fn get_appropriate_option_offer(
+    /// Changes the strike price and/or the number of shares of a resting offer.
+    /// The difference in money or shares is taken from or given back to the agent.
+    ///
+    /// Like on an exchange, the offer keeps its place in the queue only when its
+    /// number of shares is reduced, any price change or increase in shares sends it
+    /// to the back of its price level. Amending an offer so that it would be
+    /// matched immediately isn't allowed; cancel it and trade again instead.
+    pub fn amend_trade_offer(
         &mut self,
-        company_id: u64,
+        agents: &mut Agents,
+        offer_id: u64,
         strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-        offer_ask: TradeAction,
-    ) -> Option<Vec<usize>> {
-        match offer_ask {
-            TradeAction::Buy => self.get_appropriate_buyer_option_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
-            TradeAction::Sell => self.get_appropriate_seller_option_offer(
-                company_id,
-                strike_price,
-                acceptable_strike_price_deviation,
-            ),
+        number_of_shares: u64,
+    ) -> Result<(), SimulationError> {
+        if number_of_shares == 0 {
+            return self.cancel_trade_offer(agents, offer_id).map(|_| ());
+        }
+        let Some((company_id, offer_ask, offer)) = self.find_trade_offer(offer_id) else {
+            return Err(SimulationError::OfferNotFound(offer_id));
+        };
+        let offer = offer.clone();
+        let offers = self.get_mut_trade_offers(company_id);
+        if offers
+            .best_matching_offer(offer_ask, strike_price)
+            .is_some()
+        {
+            return Err(SimulationError::UnDoable);
+        }
+
+        agents.amend_order(offer_id, strike_price, number_of_shares)?;
+
+        let keeps_priority =
+            strike_price == offer.strike_price && number_of_shares <= offer.data.number_of_shares;
+        let side = offers.side_mut(offer_ask);
+        if keeps_priority {
+            if let Some(offer) = side.get_mut(offer_id) {
+                // An iceberg keeps showing what it was showing, as long as it still has the shares
+                let shown = offer.data.matchable_shares().min(number_of_shares);
+                offer.data = offer.data.resized(number_of_shares);
+                if let Visibility::Iceberg { peak, .. } = offer.data.visibility {
+                    offer.data.visibility = Visibility::Iceberg { peak, shown };
+                }
+            }
+            return Ok(());
         }
+        let Some(mut offer) = side.remove(offer_id) else {
+            return Err(SimulationError::OfferNotFound(offer_id));
+        };
+        offer.strike_price = strike_price;
+        offer.data = offer.data.resized(number_of_shares);
+        side.push(offer);
+        Ok(())
     }
 
-    /// Returns the indices of trade_house.option_offers which matches the strike_price
-    pub fn get_appropriate_buyer_option_offer(
+    pub fn add_option_offer(
         &mut self,
+        offerer_id: u64,
         company_id: u64,
         strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.option_offers
-                .get(&company_id)?
-                .buyer_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price >= strike_price - acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+        option: StockOption,
+        offer_ask: TradeAction,
+        order_type: OrderType,
+    ) {
+        let mut offer = self.new_offer(offerer_id, strike_price, option, order_type);
+        self.next_option_offer_id += 1;
+        offer.id = self.next_option_offer_id;
+        self.get_mut_option_offers(company_id)
+            .add_offer(offer, offer_ask);
     }
 
-    /// Returns the indices of trade_house.option_offers which matches the strike_price
-    pub fn get_appropriate_seller_option_offer(
-        &mut self,
+    pub fn remove_option_offer(&mut self, company_id: u64, offer: Offer<StockOption>) {
+        self.get_mut_option_offers(company_id)
+            .remove_offer(offer.id);
+    }
+
+    /// Returns the best resting trade offer of the given action,
+    /// i.e. the highest buyer offer or the lowest seller offer
+    pub fn get_best_trade_offer(
+        &self,
         company_id: u64,
-        strike_price: f64,
-        acceptable_strike_price_deviation: f64,
-    ) -> Option<Vec<usize>> {
-        Some(
-            self.option_offers
-                .get(&company_id)?
-                .seller_offers
-                .iter()
-                .enumerate()
-                .filter_map(|(i, offer)| {
-                    if offer.strike_price <= strike_price + acceptable_strike_price_deviation {
-                        return Some(i);
-                    }
-                    None
-                })
-                .collect::<Vec<usize>>(),
-        )
+        offer_ask: TradeAction,
+    ) -> Option<&Offer<Trade>> {
+        self.trade_offers.get(&company_id)?.best_offer(offer_ask)
     }
 
-    pub fn tick(
-        &mut self,
-    ) -> (
-        HashMap<u64, Vec<FailedOffer<Trade>>>,
-        HashMap<u64, Vec<FailedOffer<StockOption>>>,
-    ) {
-        let mut trade_offers = HashMap::new();
-        let mut option_offers = HashMap::new();
+    /// What the order book of the company shows, see `Offers::snapshot`
+    pub fn get_trade_book_snapshot(&self, company_id: u64, depth: usize) -> BookSnapshot {
+        self.trade_offers
+            .get(&company_id)
+            .map(|offers| offers.snapshot(depth))
+            .unwrap_or_default()
+    }
+
+    /// Returns the best resting option offer of the given action,
+    /// i.e. the highest buyer offer or the lowest seller offer
+    pub fn get_best_option_offer(
+        &self,
+        company_id: u64,
+        offer_ask: TradeAction,
+    ) -> Option<&Offer<StockOption>> {
+        self.option_offers.get(&company_id)?.best_offer(offer_ask)
+    }
+
+    /// Takes out the offers good till a tick that has come, whatever the trade house's own tick
+    pub fn expire(&mut self, tick: u64) -> (ExpiredOffers<Trade>, ExpiredOffers<StockOption>) {
+        let mut trade_offers = BTreeMap::new();
+        let mut option_offers = BTreeMap::new();
+        for (company_id, offers) in self.trade_offers.iter_mut() {
+            let expired_trades = offers.expire(tick);
+            for FailedOffer(offer, _) in expired_trades.iter() {
+                self.offer_companies.remove(&offer.id);
+            }
+            if !expired_trades.is_empty() {
+                trade_offers.insert(*company_id, expired_trades);
+            }
+        }
+        for (company_id, offers) in self.option_offers.iter_mut() {
+            let expired_options = offers.expire(tick);
+            if !expired_options.is_empty() {
+                option_offers.insert(*company_id, expired_options);
+            }
+        }
+        (trade_offers, option_offers)
+    }
+
+    pub fn tick(&mut self) -> (ExpiredOffers<Trade>, ExpiredOffers<StockOption>) {
+        self.current_tick += 1;
+        let mut trade_offers = BTreeMap::new();
+        let mut option_offers = BTreeMap::new();
         for (company_id, offers) in self.trade_offers.iter_mut() {
             let expired_trades = offers.tick();
+            for FailedOffer(offer, _) in expired_trades.iter() {
+                self.offer_companies.remove(&offer.id);
+            }
             if !expired_trades.is_empty() {
                 trade_offers.insert(*company_id, expired_trades);
             }
@@ -312,85 +555,406 @@ impl<T: Clone + Default> Offers<T> {
         Self::default()
     }
 
-    pub fn remove_offer(&mut self, offer_id: usize) {
-        for i in 0..self.seller_offers.len() {
-            if self.seller_offers[i].id != offer_id as u64 {
-                continue;
-            }
-            self.seller_offers.remove(i);
-            return;
-        }
-        for i in 0..self.buyer_offers.len() {
-            if self.buyer_offers[i].id != offer_id as u64 {
-                continue;
-            }
-            self.buyer_offers.remove(i);
-            return;
+    pub fn side(&self, offer_ask: TradeAction) -> &BookSide<T> {
+        match offer_ask {
+            TradeAction::Buy => &self.buyer_offers,
+            TradeAction::Sell => &self.seller_offers,
         }
     }
 
-    pub fn add_offer(&mut self, trade: Offer<T>, offer_ask: TradeAction) {
+    pub fn side_mut(&mut self, offer_ask: TradeAction) -> &mut BookSide<T> {
         match offer_ask {
-            TradeAction::Buy => self.add_buyer_offer(trade),
-            TradeAction::Sell => self.add_seller_offer(trade),
+            TradeAction::Buy => &mut self.buyer_offers,
+            TradeAction::Sell => &mut self.seller_offers,
         }
     }
 
-    pub fn add_seller_offer(&mut self, trade: Offer<T>) {
-        if trade.strike_price > self.highest_strike_price {
-            self.highest_strike_price = trade.strike_price;
+    pub fn remove_offer(&mut self, offer_id: u64) -> Option<Offer<T>> {
+        self.remove_offer_with_action(offer_id)
+            .map(|(_, offer)| offer)
+    }
+
+    pub fn remove_offer_with_action(&mut self, offer_id: u64) -> Option<(TradeAction, Offer<T>)> {
+        if let Some(offer) = self.seller_offers.remove(offer_id) {
+            return Some((TradeAction::Sell, offer));
         }
-        if trade.strike_price < self.lowest_strike_price {
-            self.lowest_strike_price = trade.strike_price;
+        let offer = self.buyer_offers.remove(offer_id)?;
+        Some((TradeAction::Buy, offer))
+    }
+
+    pub fn get_offer(&self, offer_id: u64) -> Option<(TradeAction, &Offer<T>)> {
+        if let Some(offer) = self.seller_offers.get(offer_id) {
+            return Some((TradeAction::Sell, offer));
         }
+        let offer = self.buyer_offers.get(offer_id)?;
+        Some((TradeAction::Buy, offer))
+    }
+
+    pub fn add_offer(&mut self, trade: Offer<T>, offer_ask: TradeAction) {
+        self.side_mut(offer_ask).push(trade);
+    }
+
+    pub fn add_seller_offer(&mut self, trade: Offer<T>) {
         self.seller_offers.push(trade);
     }
 
     pub fn add_buyer_offer(&mut self, trade: Offer<T>) {
-        if trade.strike_price > self.highest_strike_price {
-            self.highest_strike_price = trade.strike_price;
+        self.buyer_offers.push(trade);
+    }
+
+    /// The highest priced buyer offer, earliest first
+    pub fn best_buyer_offer(&self) -> Option<&Offer<T>> {
+        self.buyer_offers.highest()
+    }
+
+    /// The lowest priced seller offer, earliest first
+    pub fn best_seller_offer(&self) -> Option<&Offer<T>> {
+        self.seller_offers.lowest()
+    }
+
+    pub fn best_offer(&self, offer_ask: TradeAction) -> Option<&Offer<T>> {
+        match offer_ask {
+            TradeAction::Buy => self.best_buyer_offer(),
+            TradeAction::Sell => self.best_seller_offer(),
         }
-        if trade.strike_price < self.lowest_strike_price {
-            self.lowest_strike_price = trade.strike_price;
+    }
+
+    pub fn best_offer_mut(&mut self, offer_ask: TradeAction) -> Option<&mut Offer<T>> {
+        match offer_ask {
+            TradeAction::Buy => self.buyer_offers.highest_mut(),
+            TradeAction::Sell => self.seller_offers.lowest_mut(),
+        }
+    }
+
+    pub fn pop_best_offer(&mut self, offer_ask: TradeAction) -> Option<Offer<T>> {
+        match offer_ask {
+            TradeAction::Buy => self.buyer_offers.pop_highest(),
+            TradeAction::Sell => self.seller_offers.pop_lowest(),
+        }
+    }
+
+    /// Iterates over the offers of the given action in the order they would be matched,
+    /// best price first and then by arrival time
+    pub fn iter_by_priority(
+        &self,
+        offer_ask: TradeAction,
+    ) -> Box<dyn Iterator<Item = &Offer<T>> + '_> {
+        match offer_ask {
+            TradeAction::Buy => Box::new(
+                self.buyer_offers
+                    .levels()
+                    .rev()
+                    .flat_map(|(_, level)| level.iter()),
+            ),
+            TradeAction::Sell => Box::new(self.seller_offers.iter()),
         }
-        self.buyer_offers.push(trade);
+    }
+
+    /// Returns the offer an incoming `action` at `strike_price` would be matched against
+    pub fn best_matching_offer(&self, action: TradeAction, strike_price: f64) -> Option<&Offer<T>> {
+        let offer = self.best_offer(action.complement())?;
+        if !action.crosses(strike_price, offer.strike_price) {
+            return None;
+        }
+        Some(offer)
     }
 
     pub fn tick(&mut self) -> Vec<FailedOffer<T>> {
+        self.take_expired(Offer::tick)
+    }
+
+    /// Takes out the offers good till a tick that has come
+    pub fn expire(&mut self, tick: u64) -> Vec<FailedOffer<T>> {
+        self.take_expired(|offer| offer.is_expired_at(tick).then(|| offer.clone()))
+    }
+
+    fn take_expired(
+        &mut self,
+        mut expired: impl FnMut(&mut Offer<T>) -> Option<Offer<T>>,
+    ) -> Vec<FailedOffer<T>> {
+        let mut expired_offers = Vec::new();
+        expired_offers.extend(
+            self.seller_offers
+                .take_expired(&mut expired)
+                .into_iter()
+                .map(|offer| FailedOffer(offer, TradeAction::Sell)),
+        );
+        expired_offers.extend(
+            self.buyer_offers
+                .take_expired(&mut expired)
+                .into_iter()
+                .map(|offer| FailedOffer(offer, TradeAction::Buy)),
+        );
+        expired_offers
+    }
+}
+
+impl Offers<Trade> {
+    /// How many of `number_of_shares` an incoming `action` of the agent at `strike_price`
+    /// could resolve right now, how many it would use up, and the worst price
+    /// they would be resolved at.
+    /// The agent's own offers can't be matched with, they're treated the way
+    /// `self_trade_prevention` treats them when the transaction is matched.
+    /// The shares decremented against them are used up without being resolved
+    pub fn liquidity(
+        &self,
+        agent_id: u64,
+        action: TradeAction,
+        strike_price: f64,
+        number_of_shares: u64,
+        self_trade_prevention: SelfTradePrevention,
+    ) -> (u64, u64, f64) {
+        let mut number_of_shares_left = number_of_shares;
+        let mut available_shares = 0;
+        let mut worst_price = strike_price;
+        for offer in self.iter_by_priority(action.complement()) {
+            if number_of_shares_left == 0 || !action.crosses(strike_price, offer.strike_price) {
+                break;
+            }
+            if offer.offerer_id == agent_id {
+                match self_trade_prevention {
+                    SelfTradePrevention::CancelNewest | SelfTradePrevention::CancelBoth => break,
+                    SelfTradePrevention::CancelOldest => continue,
+                    SelfTradePrevention::Decrement => {
+                        number_of_shares_left -=
+                            number_of_shares_left.min(offer.data.number_of_shares);
+                        continue;
+                    }
+                }
+            }
+            let filled = number_of_shares_left.min(offer.data.number_of_shares);
+            number_of_shares_left -= filled;
+            available_shares += filled;
+            worst_price = offer.strike_price;
+        }
+        (
+            available_shares,
+            number_of_shares - number_of_shares_left,
+            worst_price,
+        )
+    }
+
+    /// Fills up to `number_of_shares` against the best offer that crosses
+    /// an incoming `action` at `strike_price`.
+    /// A partially filled offer keeps its place in the queue.
+    ///
+    /// Returns the matched offer as it was before the fill, along with the filled amount
+    pub fn fill_best_matching_offer(
+        &mut self,
+        action: TradeAction,
+        strike_price: f64,
+        number_of_shares: u64,
+    ) -> Option<(Offer<Trade>, u64)> {
+        let resting_action = action.complement();
+        let offer = self.best_offer_mut(resting_action)?;
+        if !action.crosses(strike_price, offer.strike_price) {
+            return None;
+        }
+        let matched_offer = offer.clone();
+        let filled = number_of_shares.min(offer.data.matchable_shares());
+        let refreshed = offer.data.resolve(filled);
+        if offer.data.number_of_shares == 0 {
+            self.pop_best_offer(resting_action);
+        } else if refreshed {
+            // Showing a new peak costs the offer its place in the queue
+            if let Some(offer) = self.pop_best_offer(resting_action) {
+                self.add_offer(offer, resting_action);
+            }
+        }
+        Some((matched_offer, filled))
+    }
+
+    /// Takes shares out of the best offer of the given action without resolving them,
+    /// removing the offer once it has none left.
+    ///
+    /// Returns the offer as it was before
+    pub fn reduce_best_offer(
+        &mut self,
+        offer_ask: TradeAction,
+        number_of_shares: u64,
+    ) -> Option<Offer<Trade>> {
+        let offer = self.best_offer_mut(offer_ask)?;
+        let reduced_offer = offer.clone();
+        offer.data = offer
+            .data
+            .resized(offer.data.number_of_shares.saturating_sub(number_of_shares));
+        if offer.data.number_of_shares == 0 {
+            self.pop_best_offer(offer_ask);
+        }
+        Some(reduced_offer)
+    }
+
+    /// The shown shares of the best `depth` price levels on both sides,
+    /// hidden offers and the hidden part of icebergs are left out
+    pub fn snapshot(&self, depth: usize) -> BookSnapshot {
+        let shown_levels = |levels: &mut dyn Iterator<Item = (f64, &VecDeque<Offer<Trade>>)>| {
+            levels
+                .map(|(price, level)| {
+                    let shown_shares = level.iter().map(|offer| offer.data.shown_shares()).sum();
+                    (price, shown_shares)
+                })
+                .filter(|(_, shown_shares)| *shown_shares != 0)
+                .take(depth)
+                .collect()
+        };
+        BookSnapshot {
+            buyer_levels: shown_levels(&mut self.buyer_offers.levels().rev()),
+            seller_levels: shown_levels(&mut self.seller_offers.levels()),
+        }
+    }
+}
+
+impl<T: Clone + Default> BookSide<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Number of distinct price levels
+    pub fn depth(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Adds the offer to the back of the queue of its price level
+    pub fn push(&mut self, offer: Offer<T>) {
+        let price = Price(offer.strike_price);
+        self.index.insert(offer.id, price);
+        self.levels.entry(price).or_default().push_back(offer);
+    }
+
+    pub fn get(&self, offer_id: u64) -> Option<&Offer<T>> {
+        let price = self.index.get(&offer_id)?;
+        self.levels
+            .get(price)?
+            .iter()
+            .find(|offer| offer.id == offer_id)
+    }
+
+    /// Changing the strike price through this reference would break the order book,
+    /// remove the offer and push it again instead
+    pub fn get_mut(&mut self, offer_id: u64) -> Option<&mut Offer<T>> {
+        let price = self.index.get(&offer_id)?;
+        self.levels
+            .get_mut(price)?
+            .iter_mut()
+            .find(|offer| offer.id == offer_id)
+    }
+
+    pub fn remove(&mut self, offer_id: u64) -> Option<Offer<T>> {
+        let price = *self.index.get(&offer_id)?;
+        let position = self
+            .levels
+            .get(&price)?
+            .iter()
+            .position(|offer| offer.id == offer_id)?;
+        self.remove_at(price, position)
+    }
+
+    fn remove_at(&mut self, price: Price, position: usize) -> Option<Offer<T>> {
+        let level = self.levels.get_mut(&price)?;
+        let offer = level.remove(position)?;
+        if level.is_empty() {
+            self.levels.remove(&price);
+        }
+        self.index.remove(&offer.id);
+        Some(offer)
+    }
+
+    pub fn lowest(&self) -> Option<&Offer<T>> {
+        self.levels.values().next()?.front()
+    }
+
+    pub fn highest(&self) -> Option<&Offer<T>> {
+        self.levels.values().next_back()?.front()
+    }
+
+    pub fn lowest_mut(&mut self) -> Option<&mut Offer<T>> {
+        self.levels.values_mut().next()?.front_mut()
+    }
+
+    pub fn highest_mut(&mut self) -> Option<&mut Offer<T>> {
+        self.levels.values_mut().next_back()?.front_mut()
+    }
+
+    pub fn pop_lowest(&mut self) -> Option<Offer<T>> {
+        let price = *self.levels.keys().next()?;
+        self.remove_at(price, 0)
+    }
+
+    pub fn pop_highest(&mut self) -> Option<Offer<T>> {
+        let price = *self.levels.keys().next_back()?;
+        self.remove_at(price, 0)
+    }
+
+    /// Iterates over every offer, from the lowest price level to the highest,
+    /// and in arrival order inside a level
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Offer<T>> {
+        self.levels.values().flatten()
+    }
+
+    /// Iterates over the price levels, lowest first
+    pub fn levels(&self) -> impl DoubleEndedIterator<Item = (f64, &VecDeque<Offer<T>>)> {
+        self.levels.iter().map(|(price, level)| (price.0, level))
+    }
+
+    /// Takes out the offers the closure returns as expired
+    fn take_expired(
+        &mut self,
+        mut expired: impl FnMut(&mut Offer<T>) -> Option<Offer<T>>,
+    ) -> Vec<Offer<T>> {
         let mut expired_offers = Vec::new();
-        for i in (0..self.seller_offers.len()).rev() {
-            let Some(offer) = self.seller_offers[i].tick() else {
-                continue;
-            };
-            expired_offers.push(FailedOffer(offer, TradeAction::Sell));
-            self.seller_offers.remove(i);
-        }
-        for i in (0..self.buyer_offers.len()).rev() {
-            let Some(offer) = self.buyer_offers[i].tick() else {
-                continue;
-            };
-            expired_offers.push(FailedOffer(offer, TradeAction::Buy));
-            self.buyer_offers.remove(i);
+        for level in self.levels.values_mut() {
+            level.retain_mut(|offer| {
+                let Some(expired_offer) = expired(offer) else {
+                    return true;
+                };
+                expired_offers.push(expired_offer);
+                false
+            });
+        }
+        self.levels.retain(|_, level| !level.is_empty());
+        for offer in expired_offers.iter() {
+            self.index.remove(&offer.id);
         }
         expired_offers
     }
 }
 
 impl<T: Clone + Default> Offer<T> {
+    /// The id is given out by the trade house once the offer is added to it
     pub fn new(offerer_id: u64, strike_price: f64, data: T) -> Self {
         Self {
-            id: random(),
+            id: 0,
             offerer_id,
             strike_price,
             data,
             lifetime: OFFER_LIFETIME,
+            order_type: OrderType::Limit,
         }
     }
     pub fn tick(&mut self) -> Option<Offer<T>> {
+        if matches!(
+            self.order_type,
+            OrderType::GoodTillCancelled | OrderType::GoodTillTick(_)
+        ) {
+            return None;
+        }
         self.lifetime -= 1;
         if self.lifetime == 0 {
             return Some(self.clone());
         }
         None
     }
+
+    /// Whether the offer is good till a tick that has come
+    pub fn is_expired_at(&self, tick: u64) -> bool {
+        matches!(self.order_type, OrderType::GoodTillTick(last_tick) if last_tick <= tick)
+    }
 }
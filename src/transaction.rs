@@ -1,13 +1,12 @@
 use crate::{
     log,
     logger::Log,
-    trade_house::{Trade, TradeAction},
+    trade_house::{OrderType, Trade, TradeAction},
 };
 use serde::{Deserialize, Serialize};
 
-// Todo Decide if you want to store the Transaction
 /// Represents an exchange of captial between 2 agents
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Transaction {
     /// The agent which gave away his shares
     pub buyer_id: u64,
@@ -18,10 +17,14 @@ pub struct Transaction {
     pub number_of_shares: u64,
     /// The price per share at which the exchange was done
     pub strike_price: f64,
+    /// The order the buyer's money was put up for, 0 if there is none
+    pub buyer_order_id: u64,
+    /// The order the seller's shares were put up for, 0 if there is none
+    pub seller_order_id: u64,
 }
 
 /// Represents an exchange of captial between agent & company
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct CompanyTransaction {
     /// The agent which gave away his shares
     pub buyer_agent_id: u64,
@@ -33,13 +36,14 @@ pub struct CompanyTransaction {
     pub strike_price: f64,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TodoTransaction {
     pub agent_id: u64,
     pub company_id: u64,
     pub strike_price: f64,
     pub action: TradeAction,
     pub trade: Trade,
+    pub order_type: OrderType,
 }
 
 impl Transaction {
@@ -57,6 +61,32 @@ impl Transaction {
             company_id,
             number_of_shares,
             strike_price,
+            buyer_order_id: 0,
+            seller_order_id: 0,
+        }
+    }
+
+    /// Sets the orders whose reservations the transaction is settled from
+    pub fn with_order_ids(mut self, buyer_order_id: u64, seller_order_id: u64) -> Self {
+        self.buyer_order_id = buyer_order_id;
+        self.seller_order_id = seller_order_id;
+        self
+    }
+}
+
+impl CompanyTransaction {
+    pub fn new(
+        buyer_agent_id: u64,
+        seller_company_id: u64,
+        number_of_shares: u64,
+        strike_price: f64,
+    ) -> Self {
+        log!(info "CompanyTransaction: buyer_agent_id: {}, seller_company_id: {}, number_of_shares: {}, strike_price: {}", buyer_agent_id, seller_company_id, number_of_shares, strike_price);
+        Self {
+            buyer_agent_id,
+            seller_company_id,
+            number_of_shares,
+            strike_price,
         }
     }
 }
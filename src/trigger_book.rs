@@ -0,0 +1,185 @@
+use crate::{
+    trade_house::{OrderType, Price, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Holds the conditional orders of every company until their stop price is reached
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TriggerBook {
+    stop_orders: BTreeMap<u64, StopOrders>,
+    next_stop_order_id: u64,
+}
+
+/// The conditional orders of a certain company, grouped by stop price
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StopOrders {
+    /// Triggered once the price rises to the stop price
+    pub buyer_stops: BTreeMap<Price, Vec<StopOrder>>,
+    /// Triggered once the price falls to the stop price
+    pub seller_stops: BTreeMap<Price, Vec<StopOrder>>,
+}
+
+/// An order that is only sent to the market once a trade happens at its stop price.
+/// Nothing is put up for it until then. Its id is given out by the trigger book.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StopOrder {
+    pub id: u64,
+    pub agent_id: u64,
+    pub company_id: u64,
+    pub action: TradeAction,
+    pub stop_price: f64,
+    /// The strike price of the limit order sent once triggered,
+    /// a market order is sent instead if there is none
+    pub limit_price: Option<f64>,
+    pub trade: Trade,
+}
+
+impl StopOrder {
+    pub fn new(
+        agent_id: u64,
+        company_id: u64,
+        action: TradeAction,
+        stop_price: f64,
+        limit_price: Option<f64>,
+        trade: Trade,
+    ) -> Self {
+        Self {
+            id: 0,
+            agent_id,
+            company_id,
+            action,
+            stop_price,
+            limit_price,
+            trade,
+        }
+    }
+
+    pub fn is_triggered_by(&self, price: f64) -> bool {
+        match self.action {
+            TradeAction::Buy => price >= self.stop_price,
+            TradeAction::Sell => price <= self.stop_price,
+        }
+    }
+
+    /// The order that gets sent to the market once triggered
+    pub fn to_todo_transaction(&self) -> TodoTransaction {
+        let (strike_price, order_type) = match self.limit_price {
+            Some(limit_price) => (limit_price, OrderType::Limit),
+            None => (self.stop_price, OrderType::Market),
+        };
+        TodoTransaction {
+            agent_id: self.agent_id,
+            company_id: self.company_id,
+            strike_price,
+            action: self.action,
+            trade: self.trade.clone(),
+            order_type,
+        }
+    }
+}
+
+impl StopOrders {
+    fn side_mut(&mut self, action: TradeAction) -> &mut BTreeMap<Price, Vec<StopOrder>> {
+        match action {
+            TradeAction::Buy => &mut self.buyer_stops,
+            TradeAction::Sell => &mut self.seller_stops,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buyer_stops
+            .values()
+            .chain(self.seller_stops.values())
+            .map(|stops| stops.len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buyer_stops.is_empty() && self.seller_stops.is_empty()
+    }
+
+    /// Removes and returns every stop order triggered by a trade at `price`,
+    /// the ones closest to the price first and then by the order they were added in
+    pub fn trigger(&mut self, price: f64) -> Vec<StopOrder> {
+        let mut triggered = Vec::new();
+        let reached_buyer_stops: Vec<Price> = self
+            .buyer_stops
+            .range(..=Price(price))
+            .rev()
+            .map(|(stop_price, _)| *stop_price)
+            .collect();
+        for stop_price in reached_buyer_stops.iter() {
+            triggered.extend(self.buyer_stops.remove(stop_price).unwrap_or_default());
+        }
+        let reached_seller_stops: Vec<Price> = self
+            .seller_stops
+            .range(Price(price)..)
+            .map(|(stop_price, _)| *stop_price)
+            .collect();
+        for stop_price in reached_seller_stops.iter() {
+            triggered.extend(self.seller_stops.remove(stop_price).unwrap_or_default());
+        }
+        triggered
+    }
+}
+
+impl TriggerBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get_mut_stop_orders(&mut self, company_id: u64) -> &mut StopOrders {
+        self.stop_orders.entry(company_id).or_default()
+    }
+
+    /// Numbers the stop order and returns its id
+    pub fn add_stop_order(&mut self, mut stop_order: StopOrder) -> u64 {
+        self.next_stop_order_id += 1;
+        let id = self.next_stop_order_id;
+        stop_order.id = id;
+        self.get_mut_stop_orders(stop_order.company_id)
+            .side_mut(stop_order.action)
+            .entry(Price(stop_order.stop_price))
+            .or_default()
+            .push(stop_order);
+        id
+    }
+
+    pub fn remove_stop_order(&mut self, stop_order_id: u64) -> Option<StopOrder> {
+        for stop_orders in self.stop_orders.values_mut() {
+            for side in [&mut stop_orders.buyer_stops, &mut stop_orders.seller_stops] {
+                let Some((price, position)) = side.iter().find_map(|(price, stops)| {
+                    let position = stops.iter().position(|stop| stop.id == stop_order_id)?;
+                    Some((*price, position))
+                }) else {
+                    continue;
+                };
+                let stops = side.get_mut(&price)?;
+                let stop_order = stops.remove(position);
+                if stops.is_empty() {
+                    side.remove(&price);
+                }
+                return Some(stop_order);
+            }
+        }
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.stop_orders.values().map(|stops| stops.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stop_orders.values().all(|stops| stops.is_empty())
+    }
+
+    /// Removes and returns every stop order of the company triggered by a trade at `price`
+    pub fn trigger(&mut self, company_id: u64, price: f64) -> Vec<StopOrder> {
+        let Some(stop_orders) = self.stop_orders.get_mut(&company_id) else {
+            return Vec::new();
+        };
+        stop_orders.trigger(price)
+    }
+}
@@ -1,6 +1,6 @@
 use stocks::{
     entities::agents::{Agent, Agents},
-    trade_house::{Trade, TradeAction},
+    trade_house::{OrderType, Trade, TradeAction},
     transaction::{TodoTransaction, Transaction},
 };
 
@@ -17,8 +17,9 @@ fn asset_exchange_success() {
         strike_price: 1.0,
         action: TradeAction::Buy,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let buyer_order_id = agents
         .deduct_assets_from_todotransaction(&agent1_buys)
         .unwrap();
     let agent2_sells = TodoTransaction {
@@ -27,11 +28,13 @@ fn asset_exchange_success() {
         strike_price: 1.0,
         action: TradeAction::Sell,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let seller_order_id = agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction =
+        Transaction::new(0, 1, 0, 100, 1.0).with_order_ids(buyer_order_id, seller_order_id);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
@@ -58,8 +61,9 @@ fn asset_exchange_failure_1() {
         strike_price: 1.0,
         action: TradeAction::Buy,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let buyer_order_id = agents
         .deduct_assets_from_todotransaction(&agent1_buys)
         .unwrap();
     let agent2_sells = TodoTransaction {
@@ -68,11 +72,13 @@ fn asset_exchange_failure_1() {
         strike_price: 1.0,
         action: TradeAction::Sell,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let seller_order_id = agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction =
+        Transaction::new(0, 1, 0, 100, 1.0).with_order_ids(buyer_order_id, seller_order_id);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
@@ -96,8 +102,9 @@ fn asset_exchange_failure_2() {
         strike_price: 1.0,
         action: TradeAction::Buy,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let buyer_order_id = agents
         .deduct_assets_from_todotransaction(&agent1_buys)
         .unwrap();
     let agent2_sells = TodoTransaction {
@@ -106,11 +113,13 @@ fn asset_exchange_failure_2() {
         strike_price: 1.0,
         action: TradeAction::Sell,
         trade: Trade::new(100),
+        order_type: OrderType::Limit,
     };
-    agents
+    let seller_order_id = agents
         .deduct_assets_from_todotransaction(&agent2_sells)
         .unwrap();
-    let transaction = Transaction::new(0, 1, 0, 100, 1.0);
+    let transaction =
+        Transaction::new(0, 1, 0, 100, 1.0).with_order_ids(buyer_order_id, seller_order_id);
     agents
         .exchange_assets_from_transaction(&transaction)
         .unwrap();
@@ -0,0 +1,100 @@
+use stocks::{
+    candles::Candles,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company, MarketValue},
+    },
+    market::Market,
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+#[test]
+fn candles_are_built_at_every_resolution() {
+    let mut candles = Candles::new(&[1, 5], 2);
+    for (tick, price, number_of_shares) in [
+        (0, 2.0, 10),
+        (0, 4.0, 10),
+        (3, 1.0, 20),
+        (6, 3.0, 5),
+        (7, 5.0, 5),
+    ] {
+        candles.add_trade(0, tick, price, number_of_shares);
+    }
+    candles.add_trade(1, 7, 100.0, 1);
+
+    let five_ticks = &candles.get(0, 5).unwrap().candles;
+    assert_eq!(five_ticks.len(), 2);
+    let first = &five_ticks[0];
+    assert_eq!(first.start_tick, 0);
+    assert_eq!(
+        (first.open, first.high, first.low, first.close),
+        (2.0, 4.0, 1.0, 1.0)
+    );
+    assert_eq!(first.volume, 40);
+    assert_eq!(first.trade_count, 3);
+    assert_eq!(first.vwap(), 2.0);
+    assert_eq!(five_ticks[1].start_tick, 5);
+    assert_eq!(five_ticks[1].vwap(), 4.0);
+
+    // only the last 2 candles are kept
+    let every_tick = &candles.get(0, 1).unwrap().candles;
+    let start_ticks: Vec<u64> = every_tick.iter().map(|candle| candle.start_tick).collect();
+    assert_eq!(start_ticks, vec![6, 7]);
+    assert_eq!(candles.latest(1, 5).unwrap().close, 100.0);
+
+    let mut csv = Vec::new();
+    candles.get(0, 5).unwrap().write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(
+        lines[0],
+        "start_tick,open,high,low,close,volume,vwap,trade_count"
+    );
+    assert_eq!(lines[1], "0,2,4,1,1,40,2,3");
+}
+
+#[test]
+fn market_value_comes_from_recent_candle() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 20)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (tick, strike_price) in [(1, 1.0), (2, 3.0)] {
+        market.current_tick = tick;
+        for (agent_id, action) in [(1, TradeAction::Sell), (0, TradeAction::Buy)] {
+            market
+                .trade(
+                    false,
+                    &TodoTransaction {
+                        agent_id,
+                        company_id: 0,
+                        strike_price,
+                        action,
+                        trade: Trade::new(10),
+                        order_type: OrderType::Limit,
+                    },
+                    &mut agents,
+                    &mut companies,
+                    0.0,
+                )
+                .unwrap();
+        }
+    }
+
+    let mut market_value = MarketValue::new();
+    market.current_tick = 5;
+    market.tick_individual_company(0, &mut market_value);
+    assert_eq!(market_value.current_price, 2.0);
+    assert_eq!(market_value.highest_price, 3.0);
+    assert_eq!(market_value.lowest_price, 1.0);
+    assert_eq!(market_value.overall_movement_end, 3.0);
+
+    // no trades in the last candle, so the price stays put
+    market.current_tick = 10;
+    market.tick_individual_company(0, &mut market_value);
+    assert_eq!(market_value.current_price, 2.0);
+    assert_eq!(market_value.highest_price, 2.0);
+}
@@ -0,0 +1,88 @@
+use clap::Parser;
+use stocks::{
+    checkpoints::Checkpoints, cli::Cli, cli::DataDir, config::Config, simulation::Simulation,
+    snapshot::Snapshot,
+};
+
+fn temp_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    dir.to_str().unwrap().to_string()
+}
+
+fn execute(args: &[&str]) {
+    Cli::try_parse_from(std::iter::once("stocks").chain(args.iter().copied()))
+        .unwrap()
+        .execute(&mut Vec::new())
+        .unwrap();
+}
+
+#[test]
+fn only_the_newest_checkpoints_are_kept_and_damaged_ones_are_skipped() {
+    let dir = temp_dir("checkpoints_kept");
+    let checkpoints = Checkpoints::new(&dir, 3);
+    let mut config = Config {
+        seed: Some(5),
+        ..Default::default()
+    };
+    config.world.num_of_agents = 20;
+    config.world.num_of_companies = 2;
+    let mut simulation = Simulation::rand(config).unwrap();
+    for _ in 0..4 {
+        simulation.run_for(5).unwrap();
+        checkpoints.save(&simulation).unwrap();
+    }
+    let saved = checkpoints.list();
+    assert_eq!(
+        saved.iter().map(|(tick, _)| *tick).collect::<Vec<_>>(),
+        [10, 15, 20]
+    );
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+    assert_eq!(checkpoints.newest_valid().unwrap().header.tick, 20);
+
+    let (_, newest) = saved.last().unwrap();
+    let mut bytes = std::fs::read(newest).unwrap();
+    *bytes.last_mut().unwrap() ^= 1;
+    std::fs::write(newest, bytes).unwrap();
+    assert_eq!(checkpoints.newest_valid().unwrap().header.tick, 15);
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn runs_resume_from_the_newest_checkpoint() {
+    let dir = temp_dir("checkpoints_resume");
+    let data_dir = DataDir::new(&dir);
+    execute(&[
+        "init",
+        "--data-dir",
+        &dir,
+        "--agents",
+        "30",
+        "--companies",
+        "3",
+        "--seed",
+        "4",
+    ]);
+    execute(&[
+        "run",
+        "--data-dir",
+        &dir,
+        "--ticks",
+        "12",
+        "--checkpoint-every",
+        "5",
+    ]);
+    // as if the run crashed while saving the world
+    std::fs::write(data_dir.snapshot_file(), b"STOCKSIM").unwrap();
+
+    execute(&["run", "--data-dir", &dir, "--ticks", "3", "--resume"]);
+    let snapshot = Snapshot::load(&data_dir.snapshot_file()).unwrap();
+    assert_eq!(snapshot.header.tick, 13);
+    let ledger = data_dir.read_ledger().unwrap();
+    assert!(ledger
+        .records()
+        .windows(2)
+        .all(|records| records[0].tick() <= records[1].tick()));
+    assert!(ledger.records().iter().all(|record| record.tick() <= 13));
+    _ = std::fs::remove_dir_all(dir);
+}
@@ -0,0 +1,100 @@
+use clap::Parser;
+use stocks::cli::{Cli, Command, DataDir, ExportFormat};
+
+fn execute(args: &[&str]) -> String {
+    let mut out = Vec::new();
+    Cli::try_parse_from(std::iter::once("stocks").chain(args.iter().copied()))
+        .unwrap()
+        .execute(&mut out)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn arguments_are_parsed() {
+    let cli =
+        Cli::try_parse_from(["stocks", "run", "--ticks", "5", "--data-dir", "world"]).unwrap();
+    let Command::Run(args) = cli.command else {
+        panic!("expected the run command");
+    };
+    assert_eq!(args.ticks, Some(5));
+    assert_eq!(args.data_dir, "world");
+    assert_eq!(args.config, None);
+    assert!(!args.check_invariants);
+
+    let cli = Cli::try_parse_from(["stocks", "export", "--format", "csv"]).unwrap();
+    let Command::Export(args) = cli.command else {
+        panic!("expected the export command");
+    };
+    assert_eq!(args.format, ExportFormat::Csv);
+    assert!(Cli::try_parse_from(["stocks", "inspect", "--agent", "1", "--company", "1"]).is_err());
+}
+
+#[test]
+fn world_is_initialized_run_and_inspected() {
+    let data_dir = std::env::temp_dir().join(format!("cli_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&data_dir);
+    let data_dir = data_dir.to_str().unwrap();
+
+    let output = execute(&[
+        "init",
+        "--data-dir",
+        data_dir,
+        "--agents",
+        "40",
+        "--companies",
+        "3",
+        "--seed",
+        "9",
+    ]);
+    assert!(output.starts_with("Generated 40 agents and 3 companies with seed 9"));
+    let output = execute(&["inspect", "--data-dir", data_dir]);
+    assert!(output.contains("Agents: 40\nCompanies: 3\n"));
+    assert!(output.contains("Ledger records: 0\n"));
+
+    execute(&["run", "--data-dir", data_dir, "--ticks", "25"]);
+    let ledger = DataDir::new(data_dir).read_ledger().unwrap();
+    assert!(!ledger.is_empty());
+    let output = execute(&["inspect", "--data-dir", data_dir]);
+    assert!(output.contains(&format!("Ledger records: {}\n", ledger.len())));
+    let output = execute(&["inspect", "--data-dir", data_dir, "--company", "2"]);
+    assert!(output.starts_with("Company 2\nPrice: "));
+
+    let output = execute(&["replay", "--data-dir", data_dir, "--company", "0"]);
+    assert_eq!(
+        output
+            .lines()
+            .filter(|line| line.starts_with("tick "))
+            .count(),
+        ledger.by_company(0).count()
+    );
+
+    let export_dir = format!("{}/export", data_dir);
+    execute(&["export", "--data-dir", data_dir, "--output", &export_dir]);
+    let agents = std::fs::read_to_string(format!("{}/agents.json", export_dir)).unwrap();
+    assert!(agents.trim_start().starts_with('['));
+    execute(&[
+        "export",
+        "--data-dir",
+        data_dir,
+        "--format",
+        "csv",
+        "--output",
+        &export_dir,
+    ]);
+    let ledger_csv = std::fs::read_to_string(format!("{}/ledger.csv", export_dir)).unwrap();
+    assert_eq!(ledger_csv.lines().count(), ledger.len() + 1);
+    _ = std::fs::remove_dir_all(data_dir);
+}
+
+#[test]
+fn failed_commands_exit_with_an_error() {
+    let data_dir = std::env::temp_dir().join(format!("cli_missing_{}", std::process::id()));
+    _ = std::fs::remove_dir_all(&data_dir);
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_stocks"))
+        .args(["inspect", "--data-dir", data_dir.to_str().unwrap()])
+        .stdout(std::process::Stdio::null())
+        .status()
+        .unwrap();
+    assert!(!status.success());
+}
@@ -0,0 +1,49 @@
+use stocks::{config::Config, market::Market, DeserializationError, CONFIG_FILENAME};
+
+#[test]
+fn config_file_matches_the_defaults() {
+    let config = Config::load(&format!(
+        "{}/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        CONFIG_FILENAME
+    ))
+    .unwrap();
+    assert_eq!(config, Config::default());
+    assert_eq!(
+        Config::from_yaml(&config.to_yaml().unwrap()).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn left_out_parameters_keep_their_defaults() {
+    let config = Config::from_yaml("seed: 3\nmarket:\n  offer_lifetime: 4\n").unwrap();
+    assert_eq!(config.seed, Some(3));
+    assert_eq!(config.market.offer_lifetime, 4);
+    assert_eq!(
+        config.market.min_strike_price,
+        Config::default().market.min_strike_price
+    );
+    assert_eq!(config.world, Config::default().world);
+    assert_eq!(Market::from_config(&config.market).house.offer_lifetime, 4);
+}
+
+#[test]
+fn invalid_configs_are_rejected() {
+    for yaml in [
+        "world:\n  num_of_companies: 0\n",
+        "market:\n  lot_acceptance_probability: 1.5\n",
+        "market:\n  candle_resolutions: [1, 100]\n",
+        "companies:\n  max_profit_percent_for_negative_hype: 80.0\n",
+        "agents:\n  timeline_size: 10\n",
+    ] {
+        assert!(
+            matches!(
+                Config::from_yaml(yaml),
+                Err(DeserializationError::InvalidConfig(_))
+            ),
+            "{}",
+            yaml
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use rand::Rng;
+use stocks::{
+    config::Config,
+    entities::{agents::Agents, companies::Companies},
+    market::Market,
+    rng::RngStreams,
+    trade_house::{ExpiredOffers, OrderType, StockOption, Trade},
+    transaction::TodoTransaction,
+};
+
+/// A few ticks of the simulation loop, returning the ledger it produced
+fn run(seed: u64) -> Vec<u8> {
+    let config = Config::default();
+    let rng_streams = RngStreams::new(seed);
+    let mut company_rng = rng_streams.stream("companies");
+    let mut agent_rng = rng_streams.stream("agents");
+    let mut market_rng = rng_streams.stream("market");
+
+    let mut companies = Companies::rand(5, 0, &mut company_rng);
+    // the agents only buy, and only bet on lots that fit their strike price
+    for company_id in companies.iter() {
+        let current_price = companies.get_current_price(company_id).unwrap();
+        companies.lots[company_id as usize].strike_price = current_price;
+    }
+    let mut agents = Agents::new();
+    agents
+        .rand_introduce_new_agents(
+            rng_streams.stream("agent preferences"),
+            rng_streams.stream("agent balances"),
+            50,
+            companies.num_of_companies,
+        )
+        .unwrap();
+    for agent_id in agents.iter() {
+        let company_id = companies.rand_company_id(&mut agent_rng);
+        agents
+            .give_assets(agent_id, company_id, 0.0, agent_rng.gen_range(0..100))
+            .unwrap();
+    }
+    let mut market = Market::new();
+    let mut expired_trades: ExpiredOffers<Trade> = ExpiredOffers::new();
+    let mut expired_options: ExpiredOffers<StockOption> = ExpiredOffers::new();
+
+    for tick in 1..=60 {
+        market.current_tick = tick;
+        agents.try_offers.clear();
+        if tick % 5 == 0 {
+            for company_id in companies.iter() {
+                let market_value = &mut companies.market_values[company_id as usize];
+                market.tick_individual_company(company_id, market_value);
+            }
+            market.tick_failures(&mut expired_trades, &mut expired_options);
+        }
+        if tick % 20 == 0 {
+            let news_release = companies
+                .rand_release_news(&mut agents, &mut company_rng, &config.companies)
+                .unwrap();
+            market
+                .ledger
+                .record_company_transactions(tick, &news_release.company_transactions);
+        }
+        agents
+            .alert_agents(&expired_trades, &expired_options)
+            .unwrap();
+        expired_trades.clear();
+        expired_options.clear();
+
+        let mut todo_transactions = Vec::new();
+        for agent_id in agents.iter() {
+            let (company_id, action) = agents
+                .preferences
+                .get_preferred_random(agent_id, &mut agent_rng)
+                .unwrap();
+            let current_price = companies.get_current_price(company_id).unwrap();
+            todo_transactions.push(TodoTransaction {
+                agent_id,
+                company_id,
+                strike_price: (current_price + agent_rng.gen_range(-10.0..10.0)).max(0.01),
+                action,
+                trade: Trade::new(agent_rng.gen_range(1..20)),
+                order_type: OrderType::Limit,
+            });
+        }
+        market
+            .rand_do_trade(
+                &mut market_rng,
+                &mut agents,
+                &mut companies,
+                &mut todo_transactions,
+                &config.market,
+            )
+            .unwrap();
+    }
+
+    assert!(!market.ledger.is_empty());
+    bincode::serialize(market.ledger.records()).unwrap()
+}
+
+#[test]
+fn same_seed_gives_identical_ledgers() {
+    assert_eq!(run(42), run(42));
+}
+
+#[test]
+fn different_seeds_give_different_ledgers() {
+    assert_ne!(run(1), run(2));
+}
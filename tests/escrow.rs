@@ -0,0 +1,218 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::{ActionState, Market},
+    trade_house::{ExpiredOffers, OrderType, Trade, TradeAction},
+    transaction::{TodoTransaction, Transaction},
+    SimulationError, OFFER_LIFETIME,
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    strike_price: f64,
+    action: TradeAction,
+    number_of_shares: u64,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type: OrderType::Limit,
+    }
+}
+
+#[test]
+fn expired_offers_are_refunded_to_their_offerer() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, strike_price, action) in
+        [(0, 1.0, TradeAction::Buy), (1, 2.0, TradeAction::Sell)]
+    {
+        market
+            .trade(
+                false,
+                &todo_transaction(agent_id, strike_price, action, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    assert_eq!(agents.escrow.len(), 2);
+    assert_eq!(agents.escrow.total_cash(), 10.0);
+    assert_eq!(agents.escrow.total_shares(0), 10);
+
+    let mut expired_trades = ExpiredOffers::new();
+    let mut expired_options = ExpiredOffers::new();
+    for _ in 0..=OFFER_LIFETIME {
+        market.tick_failures(&mut expired_trades, &mut expired_options);
+    }
+    agents
+        .alert_agents(&expired_trades, &expired_options)
+        .unwrap();
+
+    assert!(agents.escrow.is_empty());
+    assert_eq!(agents.balances.get(0).unwrap(), 100.0);
+    assert_eq!(agents.holdings.get(1, 0), 10);
+}
+
+#[test]
+fn a_failed_refund_does_not_hold_back_the_others() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, strike_price, action) in
+        [(0, 1.0, TradeAction::Buy), (1, 2.0, TradeAction::Sell)]
+    {
+        market
+            .trade(
+                false,
+                &todo_transaction(agent_id, strike_price, action, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    let mut expired_trades = ExpiredOffers::new();
+    let mut expired_options = ExpiredOffers::new();
+    for _ in 0..=OFFER_LIFETIME {
+        market.tick_failures(&mut expired_trades, &mut expired_options);
+    }
+    // every expired offer has already been refunded but the buyer's
+    for offer in expired_trades[&0].iter() {
+        if offer.1 == TradeAction::Sell {
+            agents.refund_whole_order(offer.0.id).unwrap();
+        }
+    }
+    assert!(agents
+        .refund_whole_order(expired_trades[&0][0].0.id)
+        .is_err());
+
+    agents
+        .alert_agents(&expired_trades, &expired_options)
+        .unwrap();
+    assert!(agents.escrow.is_empty());
+    assert_eq!(agents.balances.get(0).unwrap(), 100.0);
+    assert_eq!(agents.holdings.get(1, 0), 10);
+}
+
+#[test]
+fn lot_bets_are_settled_or_refunded() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[(0, 5)], &[]),
+        Agent::new(1, 100.0, &[], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+    ]);
+    // 10 lots of a single share at 2.0, too small to be compressed
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (2.0, 10, 1))]);
+    let mut market = Market::new();
+    for agent_id in [0, 1] {
+        let state = market
+            .trade(
+                true,
+                &todo_transaction(agent_id, 2.0, TradeAction::Buy, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+        assert!(matches!(state, ActionState::AddedToLots));
+    }
+    // sellers can't bet on lots, so this goes to the order book
+    let state = market
+        .trade(
+            true,
+            &todo_transaction(2, 2.0, TradeAction::Sell, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert!(matches!(state, ActionState::AddedToOffers(_)));
+    assert_eq!(agents.balances.get(1).unwrap(), 80.0);
+
+    let company_transactions = companies.lots[0].finalize(0, &mut agents).unwrap();
+
+    // the lots go to agent 0, adding to the shares it already had
+    assert_eq!(company_transactions.len(), 1);
+    assert_eq!(company_transactions[0].buyer_agent_id, 0);
+    assert_eq!(agents.holdings.get(0, 0), 15);
+    assert_eq!(agents.balances.get(0).unwrap(), 80.0);
+    // agent 1 gets its money back
+    assert_eq!(agents.holdings.get(1, 0), 0);
+    assert_eq!(agents.balances.get(1).unwrap(), 100.0);
+    // only agent 2's offer is left in escrow
+    assert_eq!(agents.escrow.len(), 1);
+    assert_eq!(agents.escrow.total_cash(), 0.0);
+}
+
+#[test]
+fn lots_above_the_strike_price_are_skipped() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[])]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (3.0, 10, 1))]);
+    let mut market = Market::new();
+    let state = market
+        .trade(
+            true,
+            &todo_transaction(0, 2.0, TradeAction::Buy, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    // the buyer never pays more than it asked for, so its order rests instead
+    assert!(matches!(state, ActionState::AddedToOffers(_)));
+    assert_eq!(companies.lots[0].total_num_of_bets, 0);
+    assert_eq!(agents.balances.get(0).unwrap(), 80.0);
+}
+
+#[test]
+fn transactions_are_settled_only_out_of_reservations_that_cover_them() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let buyer_order_id = agents
+        .deduct_assets_from_todotransaction(&todo_transaction(0, 2.0, TradeAction::Buy, 10))
+        .unwrap();
+    let seller_order_id = agents
+        .deduct_assets_from_todotransaction(&todo_transaction(1, 2.0, TradeAction::Sell, 10))
+        .unwrap();
+    let transaction = |strike_price: f64, seller_order_id: u64| {
+        Transaction::new(0, 1, 0, 10, strike_price).with_order_ids(buyer_order_id, seller_order_id)
+    };
+
+    // priced above what the buyer put up
+    assert!(matches!(
+        agents.exchange_assets_from_transaction(&transaction(3.0, seller_order_id)),
+        Err(SimulationError::Unspendable)
+    ));
+    // the seller's order is gone, and the buyer's reservation is left as it was
+    assert!(matches!(
+        agents.exchange_assets_from_transaction(&transaction(2.0, seller_order_id + 1)),
+        Err(SimulationError::OfferNotFound(_))
+    ));
+    assert_eq!(agents.escrow.get(buyer_order_id).unwrap().cash(), 20.0);
+    assert_eq!(agents.escrow.get(seller_order_id).unwrap().shares(), 10);
+    assert_eq!(agents.balances.get(0).unwrap(), 80.0);
+
+    agents
+        .exchange_assets_from_transaction(&transaction(1.5, seller_order_id))
+        .unwrap();
+    assert!(agents.escrow.is_empty());
+    assert_eq!(agents.balances.get(0).unwrap(), 85.0);
+    assert_eq!(agents.balances.get(1).unwrap(), 15.0);
+    assert_eq!(agents.holdings.get(0, 0), 10);
+}
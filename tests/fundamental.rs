@@ -0,0 +1,147 @@
+use stocks::{
+    config::Config,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::Market,
+    rng::RngStreams,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    strategies::{
+        fundamental::{FundamentalConfig, FundamentalStrategy},
+        MarketView, Strategy,
+    },
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+/// A company worth 100 a share, with 1100 shares held by two agents
+fn world() -> (Agents, Companies) {
+    let mut rng = RngStreams::new(9).stream("test");
+    let mut companies = Companies::rand(1, 0, &mut rng);
+    companies.balances[0] = 100_000.0;
+    companies.expected_profits[0] = 1_000.0;
+    let mut agents = Agents::new();
+    agents.create_agents(2);
+    agents.balances.0[0] = 10_000.0;
+    agents.holdings.push(0, 0, 100);
+    agents.holdings.push(1, 0, 1_000);
+    (agents, companies)
+}
+
+fn strategy(estimate_noise: f64) -> FundamentalStrategy {
+    FundamentalStrategy::new(
+        FundamentalConfig {
+            estimate_noise,
+            ..Default::default()
+        },
+        5.0,
+        3,
+    )
+}
+
+#[test]
+fn fundamentalists_buy_below_the_fair_value_and_sell_above_it() {
+    let (agents, mut companies) = world();
+    let market = Market::new();
+    let strategy = strategy(0.0);
+    let mut rng = RngStreams::new(1).stream("decisions");
+
+    companies.market_values[0].current_price = 80.0;
+    let view = MarketView::new(1, &agents, &companies, &market);
+    assert_eq!(strategy.fair_value(&view, 0), Some(100.0));
+    let orders = strategy.decide(0, &view, &mut rng).unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].action, TradeAction::Buy);
+    assert!((orders[0].strike_price - 80.8).abs() < 1e-9);
+    assert_eq!(orders[0].trade.number_of_shares, 12);
+
+    companies.market_values[0].current_price = 120.0;
+    let view = MarketView::new(1, &agents, &companies, &market);
+    let orders = strategy.decide(0, &view, &mut rng).unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].action, TradeAction::Sell);
+    assert!((orders[0].strike_price - 118.8).abs() < 1e-9);
+    assert_eq!(orders[0].trade.number_of_shares, 10);
+
+    companies.market_values[0].current_price = 102.0;
+    let view = MarketView::new(1, &agents, &companies, &market);
+    assert!(strategy.decide(0, &view, &mut rng).unwrap().is_empty());
+}
+
+#[test]
+fn every_fundamentalist_has_its_own_estimate() {
+    let (agents, companies) = world();
+    let market = Market::new();
+    let view = MarketView::new(1, &agents, &companies, &market);
+    let strategy = strategy(0.2);
+    let estimates: Vec<f64> = (0..2)
+        .map(|agent_id| strategy.estimate(agent_id, &view, 0).unwrap())
+        .collect();
+    assert_ne!(estimates[0], estimates[1]);
+    assert_eq!(strategy.estimate(0, &view, 0), Some(estimates[0]));
+
+    let yaml = "seed: 2
+world:
+  num_of_agents: 40
+  num_of_companies: 2
+agents:
+  strategies:
+    - strategy:
+        kind: preferences
+      share: 0.5
+    - strategy:
+        kind: fundamental
+        estimate_noise: 0.2
+      share: 0.5
+";
+    let mut simulation = Simulation::rand(Config::from_yaml(yaml).unwrap()).unwrap();
+    simulation.run_for(45).unwrap();
+    assert!(Config::from_yaml(
+        "agents:\n  strategies:\n    - strategy:\n        kind: fundamental\n        threshold: -0.1\n      share: 1.0\n"
+    )
+    .is_err());
+}
+
+#[test]
+fn fundamentalists_never_buy_above_their_estimate() {
+    let yaml = "agents:
+  strategies:
+    - strategy:
+        kind: fundamental
+        estimate_noise: 0.0
+        price_tolerance: 0.1
+      share: 1.0
+";
+    // worth 100 a share, but last valued at 93
+    let mut agents = Agents::load(&[
+        Agent::new(0, 10_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 1_100)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100_000.0, 1_000.0, 1.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 93.0;
+    let mut market = Market::new();
+    for strike_price in [99.0, 103.0] {
+        let todo_transaction = TodoTransaction {
+            agent_id: 1,
+            company_id: 0,
+            strike_price,
+            action: TradeAction::Sell,
+            trade: Trade::new(5),
+            order_type: OrderType::GoodTillCancelled,
+        };
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    let snapshot = Snapshot::new(0, 1, agents, companies, market, None);
+    let mut simulation = Simulation::from_snapshot(Config::from_yaml(yaml).unwrap(), snapshot);
+    simulation.step().unwrap();
+
+    // 102.3 is within the tolerance of the price, but past the estimate
+    assert_eq!(simulation.agents().holdings.get(0, 0), 5);
+    let offers = simulation.market().house.get_trade_offers(0).unwrap();
+    assert_eq!(offers.best_seller_offer().unwrap().strike_price, 103.0);
+    assert_eq!(offers.best_buyer_offer().unwrap().strike_price, 100.0);
+}
@@ -0,0 +1,111 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::Market,
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    strike_price: f64,
+    action: TradeAction,
+    trade: Trade,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade,
+        order_type: OrderType::Limit,
+    }
+}
+
+#[test]
+fn iceberg_shows_peak_and_loses_priority_on_refresh() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 100)], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, trade) in [(1, Trade::iceberg(100, 10)), (2, Trade::new(10))] {
+        market
+            .trade(
+                false,
+                &todo_transaction(agent_id, 1.0, TradeAction::Sell, trade),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    let snapshot = market.house.get_trade_book_snapshot(0, 5);
+    assert_eq!(snapshot.seller_levels, vec![(1.0, 20)]);
+
+    // resolves the shown peak of the iceberg, which then goes behind agent 2
+    market
+        .trade(
+            false,
+            &todo_transaction(0, 1.0, TradeAction::Buy, Trade::new(15)),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    assert_eq!(agents.balances.get(1).unwrap(), 10.0);
+    assert_eq!(agents.balances.get(2).unwrap(), 5.0);
+    let snapshot = market.house.get_trade_book_snapshot(0, 5);
+    assert_eq!(snapshot.seller_levels, vec![(1.0, 15)]);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.best_seller_offer().unwrap().offerer_id, 2);
+    assert_eq!(
+        offers
+            .seller_offers
+            .iter()
+            .last()
+            .unwrap()
+            .data
+            .number_of_shares,
+        90
+    );
+}
+
+#[test]
+fn hidden_offer_matches_without_being_shown() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    market
+        .trade(
+            false,
+            &todo_transaction(1, 2.0, TradeAction::Sell, Trade::hidden(10)),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    let snapshot = market.house.get_trade_book_snapshot(0, 5);
+    assert!(snapshot.seller_levels.is_empty());
+
+    market
+        .trade(
+            false,
+            &todo_transaction(0, 2.0, TradeAction::Buy, Trade::new(10)),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    assert_eq!(agents.holdings.get(0, 0), 10);
+    assert_eq!(agents.balances.get(1).unwrap(), 20.0);
+}
@@ -0,0 +1,140 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    invariants::{Divergence, InvariantChecker},
+    market::Market,
+    trade_house::{ExpiredOffers, OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    OFFER_LIFETIME,
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    strike_price: f64,
+    action: TradeAction,
+    number_of_shares: u64,
+    order_type: OrderType,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type,
+    }
+}
+
+#[test]
+fn trading_adds_up() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 30)], &[]),
+        Agent::new(2, 500.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (3.0, 10, 1))]);
+    let mut market = Market::new();
+    let mut invariants = InvariantChecker::new(&agents, &companies);
+
+    for (agent_id, strike_price, action, number_of_shares, order_type) in [
+        (1, 1.0, TradeAction::Sell, 10, OrderType::Limit),
+        (1, 2.0, TradeAction::Sell, 10, OrderType::Limit),
+        (0, 2.5, TradeAction::Buy, 15, OrderType::Limit),
+        (0, 5.0, TradeAction::Buy, 20, OrderType::Market),
+        (2, 0.5, TradeAction::Buy, 10, OrderType::Limit),
+        (2, 0.5, TradeAction::Sell, 5, OrderType::ImmediateOrCancel),
+    ] {
+        market
+            .trade(
+                false,
+                &todo_transaction(agent_id, strike_price, action, number_of_shares, order_type),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+        invariants
+            .check(0, "trading", &agents, &companies, &market)
+            .unwrap();
+    }
+    market
+        .trade(
+            true,
+            &todo_transaction(2, 3.0, TradeAction::Buy, 10, OrderType::Limit),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    invariants
+        .check(0, "betting on lots", &agents, &companies, &market)
+        .unwrap();
+
+    let mut expired_trades = ExpiredOffers::new();
+    let mut expired_options = ExpiredOffers::new();
+    for _ in 0..=OFFER_LIFETIME {
+        market.tick_failures(&mut expired_trades, &mut expired_options);
+    }
+    agents
+        .alert_agents(&expired_trades, &expired_options)
+        .unwrap();
+    invariants
+        .check(1, "refunding expired offers", &agents, &companies, &market)
+        .unwrap();
+
+    let company_transactions = companies.lots[0].finalize(0, &mut agents).unwrap();
+    for transaction in company_transactions.iter() {
+        companies.balances[0] += transaction.strike_price * transaction.number_of_shares as f64;
+    }
+    invariants.expect_company_transactions(&company_transactions);
+    invariants
+        .check(2, "distributing lots", &agents, &companies, &market)
+        .unwrap();
+    assert!(invariants.first_violation().is_none());
+    assert!(agents.escrow.is_empty());
+}
+
+#[test]
+fn first_divergence_is_reported() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[(0, 10)], &[])]);
+    let companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let market = Market::new();
+    let mut invariants = InvariantChecker::new(&agents, &companies);
+
+    invariants.expect_cash(5.0);
+    agents.balances.add(0, 5.0).unwrap();
+    invariants
+        .check(1, "granting money", &agents, &companies, &market)
+        .unwrap();
+
+    agents.balances.add(0, 5.0).unwrap();
+    let violation = invariants
+        .check(2, "leaking money", &agents, &companies, &market)
+        .unwrap_err();
+    assert_eq!(
+        violation.divergence,
+        Divergence::Cash {
+            expected: 205.0,
+            actual: 210.0
+        }
+    );
+
+    agents.holdings.push(0, 0, 1);
+    let violation = invariants
+        .check(3, "leaking shares", &agents, &companies, &market)
+        .unwrap_err();
+    assert_eq!(
+        violation.divergence,
+        Divergence::Shares {
+            company_id: 0,
+            expected: 10,
+            actual: 11
+        }
+    );
+
+    let first_violation = invariants.first_violation().unwrap();
+    assert_eq!(first_violation.tick, 2);
+    assert_eq!(first_violation.event, "leaking money");
+}
@@ -0,0 +1,120 @@
+use clap::Parser;
+use serde::Serialize;
+use stocks::{
+    cli::{Cli, CliError, DataDir},
+    config::Config,
+    journal::{Journal, Replayer},
+    simulation::Simulation,
+    snapshot::Snapshot,
+};
+
+fn temp_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.to_str().unwrap().to_string()
+}
+
+fn execute(args: &[&str]) -> Result<String, CliError> {
+    let mut out = Vec::new();
+    Cli::try_parse_from(std::iter::once("stocks").chain(args.iter().copied()))
+        .unwrap()
+        .execute(&mut out)?;
+    Ok(String::from_utf8(out).unwrap())
+}
+
+fn bytes(data: &impl Serialize) -> Vec<u8> {
+    bincode::serialize(data).unwrap()
+}
+
+#[test]
+fn replaying_the_journal_rebuilds_the_world() {
+    let dir = temp_dir("journal_replay");
+    let base_file = format!("{}/journal.snapshot", dir);
+    let mut config = Config {
+        seed: Some(21),
+        ..Default::default()
+    };
+    config.world.num_of_agents = 40;
+    config.world.num_of_companies = 3;
+    let mut simulation = Simulation::rand(config).unwrap();
+    simulation.run_for(3).unwrap();
+    simulation.save_snapshot(&base_file).unwrap();
+    simulation.record_journal(Journal::new());
+    simulation.run_for(42).unwrap();
+
+    let mut replayer = Replayer::new(Snapshot::load(&base_file).unwrap());
+    replayer
+        .replay(simulation.market().journal.entries(), None)
+        .unwrap();
+    assert_eq!(replayer.tick(), 45);
+    assert_eq!(bytes(replayer.agents()), bytes(simulation.agents()));
+    assert_eq!(bytes(replayer.companies()), bytes(simulation.companies()));
+    assert_eq!(bytes(replayer.market()), bytes(simulation.market()));
+    assert_eq!(
+        replayer.market().ledger.records(),
+        simulation.market().ledger.records()
+    );
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn worlds_can_be_inspected_at_any_journaled_tick() {
+    let journaled = temp_dir("journal_inspect");
+    let straight = temp_dir("journal_inspect_straight");
+    for dir in [&journaled, &straight] {
+        execute(&[
+            "init",
+            "--data-dir",
+            dir,
+            "--agents",
+            "30",
+            "--companies",
+            "3",
+            "--seed",
+            "8",
+        ])
+        .unwrap();
+    }
+    execute(&[
+        "run",
+        "--data-dir",
+        &journaled,
+        "--ticks",
+        "12",
+        "--journal",
+    ])
+    .unwrap();
+    execute(&[
+        "run",
+        "--data-dir",
+        &journaled,
+        "--ticks",
+        "10",
+        "--journal",
+    ])
+    .unwrap();
+    execute(&["run", "--data-dir", &straight, "--ticks", "17"]).unwrap();
+
+    for inspected in [["--agent", "4"], ["--company", "1"]] {
+        let at_tick = execute(
+            &[
+                &["inspect", "--data-dir", &journaled, "--at-tick", "17"][..],
+                &inspected[..],
+            ]
+            .concat(),
+        )
+        .unwrap();
+        let expected =
+            execute(&[&["inspect", "--data-dir", &straight][..], &inspected[..]].concat()).unwrap();
+        assert_eq!(at_tick, expected);
+    }
+    assert!(matches!(
+        execute(&["inspect", "--data-dir", &journaled, "--at-tick", "23"]),
+        Err(CliError::TickNotJournaled(23))
+    ));
+    assert!(DataDir::new(&journaled).replay_journal(22).is_ok());
+    for dir in [journaled, straight] {
+        _ = std::fs::remove_dir_all(dir);
+    }
+}
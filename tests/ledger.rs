@@ -0,0 +1,150 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    ledger::{Ledger, LedgerRecord},
+    market::{ActionState, Market},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::{CompanyTransaction, TodoTransaction, Transaction},
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    company_id: u64,
+    action: TradeAction,
+    number_of_shares: u64,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id,
+        strike_price: 1.0,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type: OrderType::Limit,
+    }
+}
+
+#[test]
+fn trades_are_recorded_and_queried() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10), (1, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[
+        Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+    ]);
+    let mut market = Market::new();
+    let mut resting_offer_ids = Vec::new();
+    for (tick, company_id) in [(1, 0), (2, 1)] {
+        market.current_tick = tick;
+        let ActionState::AddedToOffers(offer_id) = market
+            .trade(
+                false,
+                &todo_transaction(1, company_id, TradeAction::Sell, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap()
+        else {
+            panic!("expected the offer to rest");
+        };
+        resting_offer_ids.push(offer_id);
+        market
+            .trade(
+                false,
+                &todo_transaction(0, company_id, TradeAction::Buy, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    market
+        .ledger
+        .record_company_transactions(3, &[CompanyTransaction::new(0, 1, 5, 2.0)]);
+
+    assert_eq!(market.ledger.len(), 3);
+    let LedgerRecord::Trade {
+        tick,
+        transaction,
+        aggressor,
+    } = &market.ledger.records()[0]
+    else {
+        panic!("expected a trade between agents");
+    };
+    assert_eq!(*tick, 1);
+    assert_eq!(*aggressor, TradeAction::Buy);
+    assert_eq!(transaction.seller_order_id, resting_offer_ids[0]);
+    assert_ne!(transaction.buyer_order_id, 0);
+
+    assert_eq!(market.ledger.by_company(1).count(), 2);
+    assert_eq!(market.ledger.by_agent(1).count(), 2);
+    assert_eq!(market.ledger.by_agent(0).count(), 3);
+    let ticks: Vec<u64> = market
+        .ledger
+        .by_tick_range(2..)
+        .map(LedgerRecord::tick)
+        .collect();
+    assert_eq!(ticks, vec![2, 3]);
+}
+
+#[test]
+fn ledger_is_appended_to_its_file() {
+    let file_path = std::env::temp_dir().join(format!("ledger_{}.bin", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    _ = std::fs::remove_file(file_path);
+
+    let mut ledger = Ledger::open(file_path).unwrap();
+    ledger.record_transaction(
+        1,
+        &Transaction::new(0, 1, 0, 10, 1.0).with_order_ids(1, 2),
+        TradeAction::Sell,
+    );
+    ledger.save().unwrap();
+    ledger.record_company_transactions(2, &[CompanyTransaction::new(0, 0, 5, 2.0)]);
+    ledger.save().unwrap();
+
+    let reopened = Ledger::open(file_path).unwrap();
+    std::fs::remove_file(file_path).unwrap();
+    assert_eq!(reopened.records(), ledger.records());
+}
+
+#[test]
+fn a_partly_written_record_is_cut_off_the_ledger() {
+    let file_path = std::env::temp_dir().join(format!("ledger_torn_{}.bin", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    _ = std::fs::remove_file(file_path);
+
+    let mut ledger = Ledger::open(file_path).unwrap();
+    for tick in 1..=2 {
+        ledger.record_transaction(
+            tick,
+            &Transaction::new(0, 1, 0, 10, 1.0).with_order_ids(1, 2),
+            TradeAction::Sell,
+        );
+    }
+    ledger.save().unwrap();
+    let complete_len = std::fs::metadata(file_path).unwrap().len();
+    // a crash halfway through appending the third record
+    ledger.record_company_transactions(3, &[CompanyTransaction::new(0, 0, 5, 2.0)]);
+    ledger.save().unwrap();
+    let len = std::fs::metadata(file_path).unwrap().len();
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(file_path)
+        .unwrap();
+    file.set_len((complete_len + len) / 2).unwrap();
+
+    let mut reopened = Ledger::open(file_path).unwrap();
+    assert_eq!(reopened.records(), &ledger.records()[..2]);
+    assert_eq!(std::fs::metadata(file_path).unwrap().len(), complete_len);
+    // new records go right after the last complete one
+    reopened.record_company_transactions(3, &[CompanyTransaction::new(0, 0, 5, 2.0)]);
+    reopened.save().unwrap();
+    let reopened = Ledger::open(file_path).unwrap();
+    std::fs::remove_file(file_path).unwrap();
+    assert_eq!(reopened.records(), ledger.records());
+}
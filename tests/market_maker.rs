@@ -0,0 +1,228 @@
+use rand::RngCore;
+use serde::Serialize;
+use stocks::{
+    config::Config,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    journal::{Event, Journal, Replayer},
+    market::{ActionState, Market},
+    rng::RngStreams,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    strategies::{
+        market_maker::{MarketMakerConfig, MarketMakerStrategy},
+        MarketView, Strategy,
+    },
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+
+fn bytes(data: &impl Serialize) -> Vec<u8> {
+    bincode::serialize(data).unwrap()
+}
+
+#[test]
+fn market_makers_requote_both_sides_leaning_against_their_inventory() {
+    let mut rng = RngStreams::new(4).stream("test");
+    let mut companies = Companies::rand(1, 0, &mut rng);
+    let mut agents = Agents::new();
+    agents.create_agents(2);
+    agents.balances.0[0] = 10_000.0;
+    agents.holdings.push(0, 0, 50);
+    let mut market = Market::new();
+    market.current_tick = 1;
+    market.add_transaction(0, 100.0, 5);
+    let strategy = MarketMakerStrategy {
+        config: MarketMakerConfig::default(),
+        min_strike_price: 5.0,
+    };
+
+    let view = MarketView::new(2, &agents, &companies, &market);
+    let (bid, ask) = strategy.quotes(0, &view).unwrap();
+    assert!((bid.strike_price - 99.0).abs() < 1e-9);
+    assert!((ask.strike_price - 101.0).abs() < 1e-9);
+    assert_eq!((bid.number_of_shares, ask.number_of_shares), (10, 10));
+    assert!(strategy
+        .change_offers(0, &view, &mut rng)
+        .unwrap()
+        .is_empty());
+    let orders = strategy.decide(0, &view, &mut rng).unwrap();
+    assert_eq!(orders.len(), 2);
+    let mut offer_ids = Vec::new();
+    for order in orders.iter() {
+        let Ok(ActionState::AddedToOffers(offer_id)) =
+            market.submit(order, &mut agents, &mut companies)
+        else {
+            panic!("the quotes shouldn't be matched");
+        };
+        offer_ids.push(offer_id);
+    }
+
+    // once it holds more than its target both quotes lean down, and they're moved in place
+    agents.holdings.push(0, 0, 40);
+    let view = MarketView::new(3, &agents, &companies, &market);
+    let (bid, ask) = strategy.quotes(0, &view).unwrap();
+    assert!(bid.strike_price < 99.0 && ask.strike_price < 101.0);
+    assert!(strategy.decide(0, &view, &mut rng).unwrap().is_empty());
+    let offer_changes = strategy.change_offers(0, &view, &mut rng).unwrap();
+    assert_eq!(offer_changes.len(), 2);
+    for offer_change in offer_changes.iter() {
+        market
+            .change_offer(&mut agents, &mut companies, 0, offer_change)
+            .unwrap();
+    }
+    assert_eq!(
+        market
+            .house
+            .get_trade_offers(0)
+            .unwrap()
+            .best_seller_offer()
+            .unwrap()
+            .strike_price,
+        ask.strike_price
+    );
+    assert_eq!(
+        market
+            .house
+            .find_trade_offer(offer_ids[0])
+            .unwrap()
+            .2
+            .strike_price,
+        bid.strike_price
+    );
+
+    // nobody can cancel an offer that isn't theirs
+    assert!(matches!(
+        market.cancel_offer(&mut agents, 1, offer_ids[1]),
+        Err(SimulationError::OfferNotFound(_))
+    ));
+    let cancelled = market.cancel_offer(&mut agents, 0, offer_ids[1]).unwrap();
+    assert_eq!(cancelled.1, TradeAction::Sell);
+    assert_eq!(agents.holdings.get(0, 0), 90);
+}
+
+#[test]
+fn market_makers_trade_in_a_journaled_simulation() {
+    let yaml = "seed: 11
+world:
+  num_of_agents: 40
+  num_of_companies: 2
+agents:
+  strategies:
+    - strategy:
+        kind: preferences
+      share: 0.9
+    - strategy:
+        kind: market_maker
+        spread: 0.04
+        target_inventory: 0
+      share: 0.1
+";
+    let mut simulation = Simulation::rand(Config::from_yaml(yaml).unwrap()).unwrap();
+    for agent_id in 36..40 {
+        assert_eq!(simulation.agents().strategy_id(agent_id), 1);
+    }
+    let base_file =
+        std::env::temp_dir().join(format!("market_maker_{}.snapshot", std::process::id()));
+    let base_file = base_file.to_str().unwrap();
+    simulation.save_snapshot(base_file).unwrap();
+    simulation.record_journal(Journal::new());
+    simulation.run_for(15).unwrap();
+
+    let requotes = simulation
+        .market()
+        .journal
+        .entries()
+        .iter()
+        .filter(|entry| matches!(entry.event, Event::OfferAmended { .. }))
+        .count();
+    assert!(requotes > 0);
+
+    let mut replayer = Replayer::new(Snapshot::load(base_file).unwrap());
+    replayer
+        .replay(simulation.market().journal.entries(), None)
+        .unwrap();
+    assert_eq!(bytes(replayer.agents()), bytes(simulation.agents()));
+    assert_eq!(bytes(replayer.market()), bytes(simulation.market()));
+    _ = std::fs::remove_file(base_file);
+
+    for yaml in [
+        "agents:\n  strategies:\n    - strategy:\n        kind: market_maker\n        spread: 0.0\n      share: 1.0\n",
+        "agents:\n  strategies:\n    - strategy:\n        kind: market_maker\n        target_inventory: 200\n      share: 1.0\n",
+    ] {
+        assert!(Config::from_yaml(yaml).is_err(), "{}", yaml);
+    }
+}
+
+/// Never trades
+struct Idle;
+
+impl Strategy for Idle {
+    fn decide(
+        &self,
+        _: u64,
+        _: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        Ok(Vec::new())
+    }
+}
+
+#[test]
+fn quotes_rest_where_the_market_maker_puts_them() {
+    let yaml = "agents:
+  strategies:
+    - strategy:
+        kind: market_maker
+      share: 1.0
+";
+    let mut agents = Agents::load(&[
+        Agent::new(0, 10_000.0, &[(0, 50)], &[]),
+        Agent::new(1, 0.0, &[(0, 5)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100_000.0, 0.0, 1.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    market.current_tick = 1;
+    market.add_transaction(0, 100.0, 5);
+    // close enough above the bid that it'd be matched if the bid was widened
+    let todo_transaction = TodoTransaction {
+        agent_id: 1,
+        company_id: 0,
+        strike_price: 103.0,
+        action: TradeAction::Sell,
+        trade: Trade::new(5),
+        order_type: OrderType::GoodTillCancelled,
+    };
+    market
+        .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+        .unwrap();
+    let snapshot = Snapshot::new(0, 1, agents, companies, market, None);
+    let mut simulation = Simulation::from_snapshot(Config::from_yaml(yaml).unwrap(), snapshot);
+    let strategy_id = simulation.add_strategy(Box::new(Idle));
+    simulation.assign_strategy(1, strategy_id).unwrap();
+
+    for _ in 0..2 {
+        simulation.step().unwrap();
+        let offers = simulation.market().house.get_trade_offers(0).unwrap();
+        let quotes: Vec<_> = offers
+            .buyer_offers
+            .iter()
+            .chain(offers.seller_offers.iter())
+            .map(|offer| (offer.offerer_id, offer.strike_price))
+            .collect();
+        assert_eq!(quotes.len(), 3, "{:?}", quotes);
+        for (agent_id, strike_price) in [(0, 99.0), (0, 101.0), (1, 103.0)] {
+            assert!(
+                quotes
+                    .iter()
+                    .any(|quote| quote.0 == agent_id && (quote.1 - strike_price).abs() < 1e-9),
+                "{:?}",
+                quotes
+            );
+        }
+    }
+    assert_eq!(simulation.agents().holdings.get(0, 0), 40);
+}
@@ -0,0 +1,150 @@
+use stocks::{
+    config::Config,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::Market,
+    rng::RngStreams,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    strategies::{
+        momentum::{MomentumConfig, MomentumStrategy, TrendSignal},
+        MarketView, Strategy, StrategyConfig,
+    },
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+/// A market where company 0 traded once a tick at the given prices
+fn market_with_prices(prices: &[f64]) -> Market {
+    let mut market = Market::new();
+    for (tick, price) in prices.iter().enumerate() {
+        market.current_tick = tick as u64 + 1;
+        market.add_transaction(0, *price, 10);
+    }
+    market
+}
+
+#[test]
+fn momentum_agents_buy_rising_companies_and_sell_falling_ones() {
+    let mut rng = RngStreams::new(2).stream("test");
+    let companies = Companies::rand(1, 0, &mut rng);
+    let mut agents = Agents::new();
+    agents.create_agents(1);
+    agents.balances.0[0] = 10_000.0;
+    agents.holdings.push(0, 0, 40);
+
+    for signal in [TrendSignal::Crossover, TrendSignal::Return] {
+        let strategy = MomentumStrategy {
+            config: MomentumConfig {
+                signal,
+                short_lookback: 2,
+                long_lookback: 4,
+                position_size: 0.5,
+                ..Default::default()
+            },
+            min_strike_price: 5.0,
+        };
+        let rising = market_with_prices(&[10.0, 11.0, 12.0, 13.0, 14.0]);
+        let view = MarketView::new(6, &agents, &companies, &rising);
+        let orders = strategy.decide(0, &view, &mut rng).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].action, TradeAction::Buy);
+        assert!(orders[0].strike_price > 14.0);
+        assert_eq!(
+            orders[0].trade.number_of_shares,
+            (5_000.0 / orders[0].strike_price).floor() as u64
+        );
+
+        let falling = market_with_prices(&[14.0, 13.0, 12.0, 11.0, 10.0]);
+        let view = MarketView::new(6, &agents, &companies, &falling);
+        let orders = strategy.decide(0, &view, &mut rng).unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].action, TradeAction::Sell);
+        assert_eq!(orders[0].trade.number_of_shares, 40);
+
+        let too_short = market_with_prices(&[10.0, 11.0, 12.0]);
+        let view = MarketView::new(4, &agents, &companies, &too_short);
+        assert!(strategy.decide(0, &view, &mut rng).unwrap().is_empty());
+    }
+}
+
+#[test]
+fn momentum_agents_are_set_up_from_the_config() {
+    let yaml = "seed: 6
+world:
+  num_of_agents: 40
+  num_of_companies: 2
+agents:
+  strategies:
+    - strategy:
+        kind: preferences
+      share: 0.5
+    - strategy:
+        kind: momentum
+        signal: return
+        long_lookback: 3
+      share: 0.5
+";
+    let config = Config::from_yaml(yaml).unwrap();
+    let StrategyConfig::Momentum(momentum) = &config.agents.strategies[1].strategy else {
+        panic!("expected a momentum strategy");
+    };
+    assert_eq!(momentum.signal, TrendSignal::Return);
+    assert_eq!(momentum.long_lookback, 3);
+    assert_eq!(momentum.resolution, MomentumConfig::default().resolution);
+    let mut simulation = Simulation::rand(config).unwrap();
+    simulation.run_for(30).unwrap();
+    assert_eq!(simulation.agents().strategy_id(39), 1);
+
+    for yaml in [
+        "agents:\n  strategies:\n    - strategy:\n        kind: momentum\n        short_lookback: 20\n      share: 1.0\n",
+        "agents:\n  strategies:\n    - strategy:\n        kind: momentum\n        resolution: 7\n      share: 1.0\n",
+        "agents:\n  strategies:\n    - strategy:\n        kind: momentum\n        lookback: 7\n      share: 1.0\n",
+    ] {
+        assert!(Config::from_yaml(yaml).is_err(), "{}", yaml);
+    }
+}
+
+#[test]
+fn momentum_orders_are_filled_within_the_price_tolerance() {
+    let yaml = "agents:
+  strategies:
+    - strategy:
+        kind: momentum
+        short_lookback: 2
+        long_lookback: 4
+        price_tolerance: 0.01
+      share: 1.0
+";
+    let mut agents = Agents::load(&[
+        Agent::new(0, 10_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    // without any news, preferences could never be drawn from it
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 1.0, (0.0, 0, 0))]);
+    let mut market = market_with_prices(&[10.0, 11.0, 12.0, 13.0, 14.0]);
+    // one offer within 1% of the last price, and one past it
+    for strike_price in [14.1, 14.42] {
+        let todo_transaction = TodoTransaction {
+            agent_id: 1,
+            company_id: 0,
+            strike_price,
+            action: TradeAction::Sell,
+            trade: Trade::new(5),
+            order_type: OrderType::GoodTillCancelled,
+        };
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    let snapshot = Snapshot::new(0, 5, agents, companies, market, None);
+    let mut simulation = Simulation::from_snapshot(Config::from_yaml(yaml).unwrap(), snapshot);
+    simulation.step().unwrap();
+
+    assert_eq!(simulation.agents().holdings.get(0, 0), 5);
+    let offers = simulation.market().house.get_trade_offers(0).unwrap();
+    assert_eq!(offers.best_seller_offer().unwrap().strike_price, 14.42);
+    assert!((offers.best_buyer_offer().unwrap().strike_price - 14.14).abs() < 1e-9);
+}
@@ -0,0 +1,186 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::{ActionState, Market},
+    trade_house::{ExpiredOffers, FailedOffer, OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    OFFER_LIFETIME,
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    strike_price: f64,
+    action: TradeAction,
+    number_of_shares: u64,
+    order_type: OrderType,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type,
+    }
+}
+
+/// Agent 0 has money, agents 1 and 2 sell 10 shares at 1.0 and 2.0
+fn market_with_seller_offers() -> (Agents, Companies, Market) {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, strike_price) in [(1, 1.0), (2, 2.0)] {
+        market
+            .trade(
+                false,
+                &todo_transaction(
+                    agent_id,
+                    strike_price,
+                    TradeAction::Sell,
+                    10,
+                    OrderType::Limit,
+                ),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    (agents, companies, market)
+}
+
+#[test]
+fn market_order_ignores_strike_price() {
+    let (mut agents, mut companies, mut market) = market_with_seller_offers();
+    let state = market
+        .trade(
+            false,
+            &todo_transaction(0, 0.5, TradeAction::Buy, 30, OrderType::Market),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    let ActionState::Cancelled(transactions) = state else {
+        panic!(
+            "expected the rest of the market order to be cancelled, got {:?}",
+            state
+        );
+    };
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(agents.holdings.get(0, 0), 20);
+    // only what was bought is paid for
+    assert_eq!(agents.balances.get(0).unwrap(), 70.0);
+    assert!(market.house.get_mut_trade_offers(0).buyer_offers.is_empty());
+}
+
+#[test]
+fn immediate_or_cancel_refunds_the_rest() {
+    let (mut agents, mut companies, mut market) = market_with_seller_offers();
+    let state = market
+        .trade(
+            false,
+            &todo_transaction(0, 1.5, TradeAction::Buy, 15, OrderType::ImmediateOrCancel),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    let ActionState::Cancelled(transactions) = state else {
+        panic!(
+            "expected the rest of the order to be cancelled, got {:?}",
+            state
+        );
+    };
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(agents.holdings.get(0, 0), 10);
+    // bought at the price of the resting offer, the difference is given back
+    assert_eq!(agents.balances.get(0).unwrap(), 100.0 - 1.0 * 10.0);
+    assert!(market.house.get_mut_trade_offers(0).buyer_offers.is_empty());
+}
+
+#[test]
+fn fill_or_kill_resolves_everything_or_nothing() {
+    let (mut agents, mut companies, mut market) = market_with_seller_offers();
+    let state = market
+        .trade(
+            false,
+            &todo_transaction(0, 1.5, TradeAction::Buy, 15, OrderType::FillOrKill),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert!(matches!(state, ActionState::Killed));
+    assert_eq!(agents.balances.get(0).unwrap(), 100.0);
+    assert_eq!(market.house.get_mut_trade_offers(0).seller_offers.len(), 2);
+
+    let state = market
+        .trade(
+            false,
+            &todo_transaction(0, 2.0, TradeAction::Buy, 15, OrderType::FillOrKill),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    assert!(matches!(state, ActionState::InstantlyResolved(_)));
+    assert_eq!(agents.holdings.get(0, 0), 15);
+}
+
+#[test]
+fn resting_order_lifetimes() {
+    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[])]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for order_type in [
+        OrderType::Limit,
+        OrderType::GoodTillCancelled,
+        OrderType::GoodTillTick(3),
+    ] {
+        market
+            .trade(
+                false,
+                &todo_transaction(0, 1.0, TradeAction::Buy, 10, order_type),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+
+    // the trade house ticks every tick here, good till tick offers don't count its ticks
+    let mut expired = Vec::new();
+    for tick in 1..=(OFFER_LIFETIME * 2) {
+        market.current_tick = tick;
+        let (mut expired_trades, mut expired_options) =
+            (ExpiredOffers::new(), ExpiredOffers::new());
+        market.expire_offers(&mut expired_trades, &mut expired_options);
+        market.tick_failures(&mut expired_trades, &mut expired_options);
+        for FailedOffer(offer, _) in expired_trades.remove(&0).unwrap_or_default() {
+            expired.push((tick, offer.order_type));
+        }
+    }
+
+    assert_eq!(
+        expired,
+        [
+            (3, OrderType::GoodTillTick(3)),
+            (OFFER_LIFETIME, OrderType::Limit)
+        ]
+    );
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.buyer_offers.len(), 1);
+    assert_eq!(
+        offers.best_buyer_offer().unwrap().order_type,
+        OrderType::GoodTillCancelled
+    );
+}
@@ -0,0 +1,141 @@
+use stocks::{
+    config::Config,
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    journal::{Journal, Replayer},
+    market::Market,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+/// `seller_id` rests an offer of the shares, and `buyer_id` takes it
+fn trade(
+    market: &mut Market,
+    agents: &mut Agents,
+    companies: &mut Companies,
+    (seller_id, buyer_id): (u64, u64),
+    number_of_shares: u64,
+    strike_price: f64,
+) {
+    for (agent_id, action) in [(seller_id, TradeAction::Sell), (buyer_id, TradeAction::Buy)] {
+        let todo_transaction = TodoTransaction {
+            agent_id,
+            company_id: 0,
+            strike_price,
+            action,
+            trade: Trade::new(number_of_shares),
+            order_type: OrderType::Limit,
+        };
+        market
+            .trade(false, &todo_transaction, agents, companies, 0.0)
+            .unwrap();
+    }
+}
+
+#[test]
+fn positions_keep_their_cost_basis_and_profits() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 100.0, &[(0, 20)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    companies.market_values[0].current_price = 2.0;
+    let mut market = Market::new();
+    agents.open_positions(&companies);
+    assert_eq!(agents.position(1, 0).unwrap().average_cost(), Some(2.0));
+
+    trade(&mut market, &mut agents, &mut companies, (1, 0), 5, 3.0);
+    trade(&mut market, &mut agents, &mut companies, (1, 0), 5, 1.0);
+    let position = agents.position(0, 0).unwrap();
+    assert_eq!(position.number_of_shares(), 10);
+    assert_eq!(position.average_cost(), Some(2.0));
+    // the shares sold at 3 and at 1 both cost 2
+    assert_eq!(agents.realized_profit(1), 0.0);
+
+    // the oldest shares are sold first: 5 bought at 3 and 1 bought at 1
+    trade(&mut market, &mut agents, &mut companies, (0, 1), 6, 4.0);
+    let position = agents.position(0, 0).unwrap();
+    assert_eq!(position.number_of_shares(), 4);
+    assert_eq!(position.cost_basis(), 4.0);
+    assert_eq!(agents.realized_profit(0), 8.0);
+    let position = agents.position(1, 0).unwrap();
+    assert_eq!(position.number_of_shares(), 16);
+    assert_eq!(position.cost_basis(), 44.0);
+
+    companies.market_values[0].current_price = 5.0;
+    assert_eq!(agents.unrealized_profit(0, &companies), 16.0);
+    assert_eq!(agents.equity(0, &companies).unwrap(), 1_024.0);
+    agents.record_equity(1, &companies);
+    companies.market_values[0].current_price = 6.0;
+    agents.record_equity(2, &companies);
+    let history: Vec<(u64, f64)> = agents.equity_history(0).collect();
+    assert_eq!(history, [(1, 1_024.0), (2, 1_028.0)]);
+    assert!(agents.equity(2, &companies).is_err());
+}
+
+#[test]
+fn portfolios_follow_the_shares_and_are_saved() {
+    let mut config = Config {
+        seed: Some(5),
+        ..Default::default()
+    };
+    config.world.num_of_agents = 30;
+    config.world.num_of_companies = 2;
+    config.agents.equity_history_size = 3;
+    // the lots companies start with mostly cost more than agents bid,
+    // the ones released with the news are at the market price
+    config.cadences.news = 10;
+    let mut simulation = Simulation::rand(config).unwrap();
+    let file_path = std::env::temp_dir().join(format!("portfolio_{}.snapshot", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    simulation.save_snapshot(file_path).unwrap();
+    simulation.record_journal(Journal::new());
+    simulation.run_for(22).unwrap();
+
+    let agents = simulation.agents();
+    let mut owned_shares = 0;
+    for agent_id in agents.iter() {
+        for company_id in simulation.companies().iter() {
+            let escrowed_shares: u64 = agents
+                .escrow
+                .iter()
+                .filter(|(_, reservation)| {
+                    reservation.agent_id == agent_id && reservation.company_id == company_id
+                })
+                .map(|(_, reservation)| reservation.shares())
+                .sum();
+            let number_of_shares = agents.holdings.get(agent_id, company_id) + escrowed_shares;
+            let position = agents
+                .position(agent_id, company_id)
+                .map(|position| position.number_of_shares())
+                .unwrap_or_default();
+            assert_eq!(position, number_of_shares);
+            owned_shares += number_of_shares;
+        }
+    }
+    assert!(owned_shares > 0);
+    let equities = agents.equities(simulation.companies());
+    for agent_id in agents.iter() {
+        let equity = agents.equity(agent_id, simulation.companies()).unwrap();
+        assert!((equity - equities[agent_id as usize]).abs() < 1e-6);
+    }
+    let ticks: Vec<u64> = agents.equity_history(0).map(|(tick, _)| tick).collect();
+    // market values are updated every 5 ticks, and only the last 3 records are kept
+    assert_eq!(ticks, [10, 15, 20]);
+
+    let mut replayer = Replayer::new(Snapshot::load(file_path).unwrap());
+    replayer
+        .replay(simulation.market().journal.entries(), None)
+        .unwrap();
+    assert_eq!(replayer.agents().portfolios, agents.portfolios);
+    simulation.save_snapshot(file_path).unwrap();
+    assert_eq!(
+        Snapshot::load(file_path).unwrap().agents.portfolios,
+        agents.portfolios
+    );
+    _ = std::fs::remove_file(file_path);
+}
@@ -0,0 +1,190 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::{ActionState, Market, SelfTradePrevention},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+fn todo_transaction(agent_id: u64, action: TradeAction, number_of_shares: u64) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price: 1.0,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type: OrderType::Limit,
+    }
+}
+
+/// Agent 0 sells 10 shares and then buys 15 at the same price, while agent 1
+/// sells 10 shares in between
+fn trade_with_self(self_trade_prevention: SelfTradePrevention) -> (Agents, Market) {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 15.0, &[(0, 10)], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    market.self_trade_prevention = self_trade_prevention;
+    for (agent_id, action, number_of_shares) in [
+        (0, TradeAction::Sell, 10),
+        (1, TradeAction::Sell, 10),
+        (0, TradeAction::Buy, 15),
+    ] {
+        market
+            .trade(
+                false,
+                &todo_transaction(agent_id, action, number_of_shares),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    (agents, market)
+}
+
+#[test]
+fn cancel_newest() {
+    let (agents, mut market) = trade_with_self(SelfTradePrevention::CancelNewest);
+    assert_eq!(market.self_trades_prevented, 1);
+    assert_eq!(agents.balances.get(0).unwrap(), 15.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert!(offers.buyer_offers.is_empty());
+    assert_eq!(offers.seller_offers.len(), 2);
+}
+
+#[test]
+fn cancel_oldest() {
+    let (agents, mut market) = trade_with_self(SelfTradePrevention::CancelOldest);
+    assert_eq!(market.self_trades_prevented, 1);
+    // bought agent 1's shares and got its own back
+    assert_eq!(agents.holdings.get(0, 0), 20);
+    assert_eq!(agents.balances.get(1).unwrap(), 10.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert!(offers.seller_offers.is_empty());
+    assert_eq!(offers.best_buyer_offer().unwrap().data.number_of_shares, 5);
+}
+
+#[test]
+fn cancel_both() {
+    let (agents, mut market) = trade_with_self(SelfTradePrevention::CancelBoth);
+    assert_eq!(market.self_trades_prevented, 1);
+    assert_eq!(agents.holdings.get(0, 0), 10);
+    assert_eq!(agents.balances.get(0).unwrap(), 15.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert!(offers.buyer_offers.is_empty());
+    assert_eq!(offers.best_seller_offer().unwrap().offerer_id, 1);
+}
+
+#[test]
+fn decrement() {
+    let (agents, mut market) = trade_with_self(SelfTradePrevention::Decrement);
+    assert_eq!(market.self_trades_prevented, 1);
+    // 10 shares cancelled on both sides, the 5 left are bought from agent 1
+    assert_eq!(agents.holdings.get(0, 0), 15);
+    assert_eq!(agents.balances.get(0).unwrap(), 10.0);
+    assert_eq!(agents.balances.get(1).unwrap(), 5.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert!(offers.buyer_offers.is_empty());
+    assert_eq!(offers.best_seller_offer().unwrap().data.number_of_shares, 5);
+}
+
+#[test]
+fn fill_or_kill_counts_only_what_self_trade_prevention_lets_through() {
+    for (self_trade_prevention, killed) in [
+        (SelfTradePrevention::CancelNewest, true),
+        (SelfTradePrevention::CancelOldest, false),
+        (SelfTradePrevention::CancelBoth, true),
+        (SelfTradePrevention::Decrement, true),
+    ] {
+        let mut agents = Agents::load(&[
+            Agent::new(0, 15.0, &[(0, 10)], &[]),
+            Agent::new(1, 0.0, &[(0, 15)], &[]),
+        ]);
+        let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+        let mut market = Market::new();
+        market.self_trade_prevention = self_trade_prevention;
+        // agent 1's cheaper shares come before agent 0's own, and agent 1 has more behind them
+        for (agent_id, strike_price, number_of_shares) in [(1, 0.5, 5), (0, 1.0, 10), (1, 1.0, 10)]
+        {
+            let todo_transaction = TodoTransaction {
+                strike_price,
+                ..todo_transaction(agent_id, TradeAction::Sell, number_of_shares)
+            };
+            market
+                .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+                .unwrap();
+        }
+        let fill_or_kill = TodoTransaction {
+            order_type: OrderType::FillOrKill,
+            ..todo_transaction(0, TradeAction::Buy, 15)
+        };
+        let state = market
+            .trade(false, &fill_or_kill, &mut agents, &mut companies, 0.0)
+            .unwrap();
+        if killed {
+            assert!(
+                matches!(state, ActionState::Killed),
+                "{:?}: {:?}",
+                self_trade_prevention,
+                state
+            );
+            assert_eq!(agents.balances.get(0).unwrap(), 15.0);
+            assert_eq!(market.house.get_mut_trade_offers(0).seller_offers.len(), 3);
+        } else {
+            assert!(matches!(state, ActionState::InstantlyResolved(_)));
+            assert_eq!(agents.holdings.get(0, 0), 25);
+        }
+    }
+}
+
+#[test]
+fn market_buys_are_decremented_against_own_offers_only_once() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[(0, 5)], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    market.self_trade_prevention = SelfTradePrevention::Decrement;
+    // agent 0's own shares are at the best price, agent 1's are behind them
+    for (agent_id, strike_price, number_of_shares) in [(0, 1.0, 5), (1, 2.0, 10)] {
+        let todo_transaction = TodoTransaction {
+            strike_price,
+            ..todo_transaction(agent_id, TradeAction::Sell, number_of_shares)
+        };
+        market
+            .trade(false, &todo_transaction, &mut agents, &mut companies, 0.0)
+            .unwrap();
+    }
+    let market_buy = TodoTransaction {
+        order_type: OrderType::Market,
+        ..todo_transaction(0, TradeAction::Buy, 10)
+    };
+    let state = market
+        .trade(false, &market_buy, &mut agents, &mut companies, 0.0)
+        .unwrap();
+    // 5 shares are cancelled against its own offer, and the other 5 are bought
+    let ActionState::Cancelled(transactions) = state else {
+        panic!("{:?}", state);
+    };
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].number_of_shares, 5);
+    assert_eq!(agents.holdings.get(0, 0), 10);
+    assert_eq!(agents.balances.get(0).unwrap(), 90.0);
+    assert_eq!(
+        agents
+            .escrow
+            .iter()
+            .filter(|(_, reservation)| reservation.agent_id == 0)
+            .count(),
+        0
+    );
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.seller_offers.len(), 1);
+    assert_eq!(offers.best_seller_offer().unwrap().data.number_of_shares, 5);
+}
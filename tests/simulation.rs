@@ -0,0 +1,62 @@
+use stocks::{
+    config::Config,
+    ledger::LedgerRecord,
+    simulation::{Cadences, Simulation},
+};
+
+fn small_world(cadences: Cadences) -> Config {
+    let mut config = Config {
+        seed: Some(7),
+        cadences,
+        ..Default::default()
+    };
+    config.world.num_of_agents = 30;
+    config.world.num_of_companies = 3;
+    config
+}
+
+#[test]
+fn simulation_runs_for_and_until() {
+    let mut simulation = Simulation::rand(small_world(Cadences::default())).unwrap();
+    simulation.check_invariants();
+    assert_eq!(simulation.tick(), 0);
+
+    simulation.step().unwrap();
+    assert_eq!(simulation.tick(), 1);
+    assert_eq!(simulation.market().current_tick, 1);
+    simulation.run_for(9).unwrap();
+    assert_eq!(simulation.tick(), 10);
+    simulation
+        .run_until(|simulation| simulation.market().ledger.len() >= 5 || simulation.tick() == 100)
+        .unwrap();
+    assert!(simulation.market().ledger.len() >= 5);
+    assert!(simulation.first_violation().is_none());
+}
+
+#[test]
+fn cadences_decide_when_news_is_released() {
+    let company_trades = |cadences: Cadences| {
+        let mut simulation = Simulation::rand(small_world(cadences)).unwrap();
+        simulation.run_for(40).unwrap();
+        simulation
+            .market()
+            .ledger
+            .records()
+            .iter()
+            .filter(|record| matches!(record, LedgerRecord::CompanyTrade { .. }))
+            .map(LedgerRecord::tick)
+            .collect::<Vec<u64>>()
+    };
+
+    let never = company_trades(Cadences {
+        market_values: 5,
+        news: 0,
+    });
+    assert!(never.is_empty());
+    let every_ten_ticks = company_trades(Cadences {
+        market_values: 5,
+        news: 10,
+    });
+    assert!(!every_ten_ticks.is_empty());
+    assert!(every_ten_ticks.iter().all(|tick| tick % 10 == 0));
+}
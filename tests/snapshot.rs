@@ -0,0 +1,199 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use stocks::{
+    cli::DataDir,
+    config::Config,
+    entities::{agents::Agent, companies::MarketValue},
+    save,
+    simulation::Simulation,
+    snapshot::{Snapshot, SNAPSHOT_VERSION},
+    DeserializationError,
+};
+
+fn temp_dir(name: &str) -> String {
+    let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+    _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.to_str().unwrap().to_string()
+}
+
+fn small_simulation() -> Simulation {
+    let mut config = Config {
+        seed: Some(11),
+        ..Default::default()
+    };
+    config.world.num_of_agents = 30;
+    config.world.num_of_companies = 3;
+    Simulation::rand(config).unwrap()
+}
+
+#[test]
+fn restored_simulations_go_on_exactly_where_they_stopped() {
+    let dir = temp_dir("snapshot_round_trip");
+    let file_path = format!("{}/world.snapshot", dir);
+    let mut simulation = small_simulation();
+    // the first shares are handed out with the lots of the news of tick 40
+    simulation.run_for(35).unwrap();
+    assert!(simulation.agents().escrow.iter().next().is_some());
+    simulation.save_snapshot(&file_path).unwrap();
+
+    let snapshot = Snapshot::load(&file_path).unwrap();
+    assert_eq!(snapshot.header.version, SNAPSHOT_VERSION);
+    assert_eq!(snapshot.header.tick, 35);
+    assert_eq!(snapshot.header.config_hash, simulation.config().hash());
+    let mut restored = Simulation::from_snapshot(simulation.config().clone(), snapshot);
+    assert_eq!(restored.tick(), 35);
+
+    simulation.run_for(10).unwrap();
+    restored.run_for(10).unwrap();
+    assert_eq!(restored.market().current_tick, 45);
+    assert_eq!(restored.agents().balances.0, simulation.agents().balances.0);
+    assert!(restored
+        .agents()
+        .holdings
+        .iter()
+        .eq(simulation.agents().holdings.iter()));
+    assert!(restored
+        .agents()
+        .escrow
+        .iter()
+        .eq(simulation.agents().escrow.iter()));
+    let trades = |simulation: &Simulation| {
+        simulation
+            .market()
+            .ledger
+            .by_tick_range(36..=45)
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+    };
+    assert!(!trades(&simulation).is_empty());
+    assert_eq!(trades(&restored), trades(&simulation));
+    _ = std::fs::remove_dir_all(dir);
+}
+
+#[test]
+fn damaged_snapshots_are_rejected() {
+    let dir = temp_dir("snapshot_damaged");
+    let file_path = format!("{}/world.snapshot", dir);
+    small_simulation().save_snapshot(&file_path).unwrap();
+    let bytes = std::fs::read(&file_path).unwrap();
+    let read = |edit: &dyn Fn(&mut Vec<u8>)| {
+        let mut bytes = bytes.clone();
+        edit(&mut bytes);
+        Snapshot::read(&mut bytes.as_slice()).err()
+    };
+
+    assert!(read(&|_| {}).is_none());
+    assert!(matches!(
+        read(&|bytes| bytes[0] = b'X'),
+        Some(DeserializationError::NotASnapshot)
+    ));
+    assert!(matches!(
+        read(&|bytes| bytes[8..12].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes())),
+        Some(DeserializationError::UnsupportedVersion(version)) if version == SNAPSHOT_VERSION + 1
+    ));
+    assert!(matches!(
+        read(&|bytes| *bytes.last_mut().unwrap() ^= 1),
+        Some(DeserializationError::CorruptedSnapshot)
+    ));
+    assert!(matches!(
+        read(&|bytes| bytes.truncate(bytes.len() - 1)),
+        Some(DeserializationError::CorruptedSnapshot)
+    ));
+    _ = std::fs::remove_dir_all(dir);
+}
+
+/// Lots in the layout they were saved in before snapshots
+#[derive(Serialize)]
+struct BaselineLots {
+    strike_price: f64,
+    number_of_lots: u64,
+    lot_size: u64,
+    bets: HashMap<u64, u64>,
+    total_num_of_bets: u64,
+}
+
+/// A company in the layout it was saved in before snapshots
+#[derive(Serialize)]
+struct BaselineCompany {
+    id: u64,
+    market_value: MarketValue,
+    balance: f64,
+    expected_profit: f64,
+    news: f64,
+    lots: BaselineLots,
+    lot_finalization_time: u64,
+}
+
+fn baseline_company(id: u64, price: f64, bets: &[(u64, u64)]) -> BaselineCompany {
+    BaselineCompany {
+        id,
+        market_value: MarketValue {
+            current_price: price,
+            highest_price: price,
+            lowest_price: price,
+            overall_movement_start: price,
+            overall_movement_end: price,
+        },
+        balance: 1_000.0 * price,
+        expected_profit: 10.0,
+        news: 1.0,
+        lots: BaselineLots {
+            strike_price: price,
+            number_of_lots: 100,
+            lot_size: 10,
+            bets: bets.iter().copied().collect(),
+            total_num_of_bets: bets.iter().map(|(_, number_of_lots)| number_of_lots).sum(),
+        },
+        lot_finalization_time: 7,
+    }
+}
+
+#[test]
+fn worlds_saved_before_snapshots_are_migrated() {
+    let dir = temp_dir("snapshot_legacy");
+    let data_dir = DataDir::new(&dir);
+    let market_config = Config::default().market;
+    // the bet of agent 1 was already paid for out of its balance
+    let agents = vec![
+        Agent::new(0, 500.0, &[(0, 5)], &[]),
+        Agent::new(1, 260.0, &[], &[]),
+    ];
+    save(&agents, &data_dir.agents_file()).unwrap();
+    let companies = vec![
+        baseline_company(0, 12.0, &[]),
+        baseline_company(1, 24.0, &[(1, 2)]),
+    ];
+    save(&companies, &data_dir.companies_file()).unwrap();
+
+    let snapshot = data_dir.load_snapshot(&market_config).unwrap();
+    assert_eq!(snapshot.header.tick, 0);
+    assert_eq!(snapshot.header.config_hash, 0);
+    assert_eq!(snapshot.agents.num_of_agents, 2);
+    assert_eq!(snapshot.agents.balances.get(1).unwrap(), 260.0);
+    let companies = &snapshot.companies;
+    assert_eq!(companies.num_of_companies, 2);
+    assert_eq!(companies.get_current_price(1).unwrap(), 24.0);
+    assert_eq!(companies.balances[1], 24_000.0);
+    assert_eq!(companies.lot_finalization_times[1], 7);
+    let lots = &companies.lots[1];
+    assert_eq!(
+        (lots.strike_price, lots.lot_size, lots.total_num_of_bets),
+        (24.0, 10, 2)
+    );
+    assert_eq!(lots.get_bet(1), 2);
+    // what was paid for the bet is held for it, like the bets made since
+    let order_id = lots.bet_order_ids[&1];
+    let reservation = snapshot.agents.escrow.get(order_id).unwrap();
+    assert_eq!((reservation.company_id, reservation.cash()), (1, 480.0));
+    assert_eq!(
+        snapshot.market.house.offer_lifetime,
+        market_config.offer_lifetime
+    );
+
+    // files saved in any other layout aren't taken for old ones
+    let simulation = small_simulation();
+    save(simulation.companies().save(), &data_dir.companies_file()).unwrap();
+    assert!(data_dir.load_snapshot(&market_config).is_err());
+    _ = std::fs::remove_dir_all(dir);
+}
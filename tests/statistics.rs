@@ -0,0 +1,81 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::Market,
+    statistics::MarketStatistics,
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+};
+
+#[test]
+fn rolling_window_statistics() {
+    let mut statistics = MarketStatistics::new(3);
+    for (price, number_of_shares) in [(100.0, 1), (1.0, 10), (2.0, 10), (4.0, 5)] {
+        statistics.add_trade(0, price, number_of_shares);
+    }
+
+    let window = statistics.get(0).unwrap();
+    // the first trade fell out of the window
+    assert_eq!(window.len(), 3);
+    assert_eq!(window.mean().unwrap(), 7.0 / 3.0);
+    let mean: f64 = 7.0 / 3.0;
+    let variance = ((1.0 - mean).powi(2) + (2.0 - mean).powi(2) + (4.0 - mean).powi(2)) / 3.0;
+    assert_eq!(window.standard_deviation().unwrap(), variance.sqrt());
+    assert_eq!(window.returns(), vec![1.0, 1.0]);
+    let log_return = 2.0_f64.ln();
+    assert_eq!(
+        window.realized_volatility().unwrap(),
+        (2.0 * log_return * log_return).sqrt()
+    );
+    assert_eq!(window.volume(), 25);
+    assert_eq!(window.turnover(), 10.0 + 20.0 + 20.0);
+}
+
+#[test]
+fn every_company_only_sees_its_own_trades() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 20), (1, 20)], &[]),
+    ]);
+    let mut companies = Companies::load(&[
+        Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+    ]);
+    let mut market = Market::new();
+    for (company_id, strike_price) in [(0, 1.0), (1, 10.0), (0, 3.0), (1, 30.0)] {
+        for (agent_id, action) in [(1, TradeAction::Sell), (0, TradeAction::Buy)] {
+            market
+                .trade(
+                    false,
+                    &TodoTransaction {
+                        agent_id,
+                        company_id,
+                        strike_price,
+                        action,
+                        trade: Trade::new(10),
+                        order_type: OrderType::Limit,
+                    },
+                    &mut agents,
+                    &mut companies,
+                    0.0,
+                )
+                .unwrap();
+        }
+    }
+
+    let first = market.statistics.get(0).unwrap();
+    let second = market.statistics.get(1).unwrap();
+    assert_eq!(first.len(), 2);
+    assert_eq!(second.len(), 2);
+    assert_eq!(first.mean().unwrap(), 2.0);
+    assert_eq!(second.mean().unwrap(), 20.0);
+    assert_eq!(first.standard_deviation().unwrap(), 1.0);
+    assert_eq!(second.standard_deviation().unwrap(), 10.0);
+    assert_eq!(first.returns(), vec![2.0]);
+    assert_eq!(second.returns(), vec![2.0]);
+    assert_eq!(first.turnover(), 40.0);
+    assert_eq!(second.turnover(), 400.0);
+    assert!(market.statistics.get(2).is_none());
+}
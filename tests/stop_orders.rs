@@ -0,0 +1,137 @@
+use stocks::{
+    entities::{
+        agents::{Agent, Agents},
+        companies::{Companies, Company},
+    },
+    market::{ActionState, Market},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    trigger_book::StopOrder,
+};
+
+fn todo_transaction(
+    agent_id: u64,
+    strike_price: f64,
+    action: TradeAction,
+    number_of_shares: u64,
+) -> TodoTransaction {
+    TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade: Trade::new(number_of_shares),
+        order_type: OrderType::Limit,
+    }
+}
+
+#[test]
+fn stop_losses_cascade() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 1_000.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+        Agent::new(3, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for strike_price in [9.0, 8.0, 7.0] {
+        market
+            .trade(
+                false,
+                &todo_transaction(0, strike_price, TradeAction::Buy, 10),
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    market.add_stop_order(StopOrder::new(
+        2,
+        0,
+        TradeAction::Sell,
+        9.0,
+        None,
+        Trade::new(10),
+    ));
+    market.add_stop_order(StopOrder::new(
+        3,
+        0,
+        TradeAction::Sell,
+        8.0,
+        None,
+        Trade::new(10),
+    ));
+    // nothing happens until a trade is done
+    assert!(market
+        .trade_triggered_orders(&mut agents, &mut companies)
+        .is_empty());
+
+    market
+        .trade(
+            false,
+            &todo_transaction(1, 9.0, TradeAction::Sell, 10),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    let action_states = market.trade_triggered_orders(&mut agents, &mut companies);
+
+    assert_eq!(action_states.len(), 2);
+    assert!(market.triggers.is_empty());
+    assert_eq!(agents.balances.get(2).unwrap(), 80.0);
+    assert_eq!(agents.balances.get(3).unwrap(), 70.0);
+    assert_eq!(agents.holdings.get(0, 0), 30);
+}
+
+#[test]
+fn stop_limit_rests_once_triggered() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+        Agent::new(2, 100.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    let stop_order_id = market.add_stop_order(StopOrder::new(
+        2,
+        0,
+        TradeAction::Buy,
+        5.0,
+        Some(4.0),
+        Trade::new(10),
+    ));
+    // nothing is put up before the stop order is triggered
+    assert_eq!(agents.balances.get(2).unwrap(), 100.0);
+
+    market
+        .trade(
+            false,
+            &todo_transaction(1, 6.0, TradeAction::Sell, 5),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    market
+        .trade(
+            false,
+            &todo_transaction(0, 6.0, TradeAction::Buy, 5),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    let action_states = market.trade_triggered_orders(&mut agents, &mut companies);
+
+    assert_eq!(action_states.len(), 1);
+    assert!(matches!(
+        action_states[0],
+        Ok(ActionState::AddedToOffers(_))
+    ));
+    assert_eq!(agents.balances.get(2).unwrap(), 60.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.best_buyer_offer().unwrap().strike_price, 4.0);
+    assert!(market.cancel_stop_order(stop_order_id).is_err());
+}
@@ -0,0 +1,197 @@
+use rand::RngCore;
+use std::{cell::RefCell, rc::Rc};
+use stocks::{
+    config::Config,
+    simulation::Simulation,
+    snapshot::Snapshot,
+    strategies::{MarketView, Strategy},
+    trade_house::{OrderType, Trade, TradeAction},
+    transaction::TodoTransaction,
+    SimulationError,
+};
+
+fn config(num_of_agents: u64) -> Config {
+    let mut config = Config {
+        seed: Some(13),
+        ..Default::default()
+    };
+    config.world.num_of_agents = num_of_agents;
+    config.world.num_of_companies = 2;
+    config
+}
+
+/// Buys a share of company 0 way above its price, and remembers who it decided for
+struct Recording {
+    decided_for: Rc<RefCell<Vec<u64>>>,
+}
+
+impl Strategy for Recording {
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        self.decided_for.borrow_mut().push(agent_id);
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id: 0,
+            strike_price: view.price(0).unwrap() * 2.0,
+            action: TradeAction::Buy,
+            trade: Trade::new(1),
+            order_type: OrderType::Limit,
+        }])
+    }
+}
+
+#[test]
+fn agents_trade_with_the_strategy_they_are_assigned() {
+    let mut simulation = Simulation::rand(config(20)).unwrap();
+    let decided_for = Rc::new(RefCell::new(Vec::new()));
+    let strategy_id = simulation.add_strategy(Box::new(Recording {
+        decided_for: decided_for.clone(),
+    }));
+    for agent_id in [3, 7] {
+        simulation.assign_strategy(agent_id, strategy_id).unwrap();
+    }
+    simulation.run_for(2).unwrap();
+    assert_eq!(*decided_for.borrow(), [3, 7, 3, 7]);
+    for agent_id in [3, 7] {
+        assert_eq!(simulation.agents().strategy_id(agent_id), strategy_id);
+    }
+    assert_eq!(simulation.agents().strategy_id(4), 0);
+    assert!(simulation.assign_strategy(20, strategy_id).is_err());
+
+    let file_path =
+        std::env::temp_dir().join(format!("strategies_{}.snapshot", std::process::id()));
+    let file_path = file_path.to_str().unwrap();
+    simulation.save_snapshot(file_path).unwrap();
+    let snapshot = Snapshot::load(file_path).unwrap();
+    assert_eq!(
+        snapshot.agents.strategy_ids,
+        simulation.agents().strategy_ids
+    );
+    _ = std::fs::remove_file(file_path);
+}
+
+#[test]
+fn agents_are_split_between_strategies_by_their_shares() {
+    let mut config = config(12);
+    let yaml = "agents:\n  strategies:\n    - strategy:\n        kind: preferences\n      share: 1.0\n    - strategy:\n        kind: preferences\n      share: 3.0\n";
+    config.agents = Config::from_yaml(yaml).unwrap().agents;
+    let simulation = Simulation::rand(config).unwrap();
+    assert_eq!(
+        simulation.agents().strategy_ids,
+        [0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1]
+    );
+
+    for yaml in [
+        "agents:\n  strategies: []\n",
+        "agents:\n  strategies:\n    - strategy:\n        kind: preferences\n      share: -1.0\n",
+        "agents:\n  strategies:\n    - strategy:\n        kind: astrology\n      share: 1.0\n",
+    ] {
+        assert!(Config::from_yaml(yaml).is_err(), "{}", yaml);
+    }
+}
+
+/// Bids for a share of company 0 once, good till the given tick
+struct GoodTillTick {
+    last_tick: u64,
+    bid: Rc<RefCell<bool>>,
+}
+
+impl Strategy for GoodTillTick {
+    fn decide(
+        &self,
+        agent_id: u64,
+        _: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        if self.bid.replace(true) {
+            return Ok(Vec::new());
+        }
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id: 0,
+            strike_price: 0.01,
+            action: TradeAction::Buy,
+            trade: Trade::new(1),
+            order_type: OrderType::GoodTillTick(self.last_tick),
+        }])
+    }
+}
+
+#[test]
+fn good_till_tick_offers_rest_until_their_simulation_tick() {
+    let mut simulation = Simulation::rand(config(20)).unwrap();
+    let strategy_id = simulation.add_strategy(Box::new(GoodTillTick {
+        last_tick: 4,
+        bid: Rc::new(RefCell::new(false)),
+    }));
+    simulation.assign_strategy(3, strategy_id).unwrap();
+    let balance = simulation.agents().balances.get(3).unwrap();
+    let is_resting = |simulation: &Simulation| {
+        simulation
+            .market()
+            .house
+            .get_trade_offers(0)
+            .is_some_and(|offers| {
+                offers
+                    .buyer_offers
+                    .iter()
+                    .any(|offer| offer.offerer_id == 3)
+            })
+    };
+
+    for _ in 1..4 {
+        simulation.step().unwrap();
+        assert!(is_resting(&simulation), "tick {}", simulation.tick());
+    }
+    simulation.step().unwrap();
+    assert!(!is_resting(&simulation));
+    assert_eq!(simulation.agents().balances.get(3).unwrap(), balance);
+}
+
+/// Bids for a share of company 0 at half its price, but fails for odd agents
+struct FailsForOddAgents;
+
+impl Strategy for FailsForOddAgents {
+    fn decide(
+        &self,
+        agent_id: u64,
+        view: &MarketView,
+        _: &mut dyn RngCore,
+    ) -> Result<Vec<TodoTransaction>, SimulationError> {
+        if agent_id % 2 == 1 {
+            return Err(SimulationError::NoData);
+        }
+        Ok(vec![TodoTransaction {
+            agent_id,
+            company_id: 0,
+            strike_price: view.price(0).unwrap() / 2.0,
+            action: TradeAction::Buy,
+            trade: Trade::new(1),
+            order_type: OrderType::GoodTillCancelled,
+        }])
+    }
+}
+
+#[test]
+fn failing_agents_sit_the_tick_out_and_orders_are_sent_as_they_are() {
+    let mut simulation = Simulation::rand(config(20)).unwrap();
+    let strategy_id = simulation.add_strategy(Box::new(FailsForOddAgents));
+    for agent_id in [3, 4] {
+        simulation.assign_strategy(agent_id, strategy_id).unwrap();
+    }
+    let price = simulation.companies().get_current_price(0).unwrap();
+    simulation.step().unwrap();
+
+    let offers = simulation.market().house.get_trade_offers(0).unwrap();
+    let bids: Vec<_> = offers
+        .buyer_offers
+        .iter()
+        .filter(|offer| [3, 4].contains(&offer.offerer_id))
+        .map(|offer| (offer.offerer_id, offer.strike_price))
+        .collect();
+    assert_eq!(bids, [(4, price / 2.0)]);
+}
@@ -3,10 +3,11 @@ use stocks::{
         agents::{Agent, Agents},
         companies::{Companies, Company},
     },
-    market::Market,
-    trade_house::{Trade, TradeAction},
+    market::{ActionState, Market},
+    snapshot::Snapshot,
+    trade_house::{OrderType, Trade, TradeAction},
     transaction::TodoTransaction,
-    OFFER_LIFETIME,
+    SimulationError, OFFER_LIFETIME,
 };
 
 #[test]
@@ -23,6 +24,7 @@ fn getting_added_to_offers_list() {
                 strike_price: 1.0,
                 action: TradeAction::Buy,
                 trade: Trade::new(10),
+                order_type: OrderType::Limit,
             },
             &mut agents,
             &mut companies,
@@ -34,7 +36,10 @@ fn getting_added_to_offers_list() {
 
 #[test]
 fn offer_resolving() {
-    let mut agents = Agents::load(&[Agent::new(0, 100.0, &[], &[]), Agent::new(1, 0.0, &[(0, 100)], &[])]);
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 100)], &[]),
+    ]);
     let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
     let mut market = Market::new();
     market
@@ -46,6 +51,7 @@ fn offer_resolving() {
                 strike_price: 1.0,
                 action: TradeAction::Buy,
                 trade: Trade::new(100),
+                order_type: OrderType::Limit,
             },
             &mut agents,
             &mut companies,
@@ -61,6 +67,7 @@ fn offer_resolving() {
                 strike_price: 1.0,
                 action: TradeAction::Sell,
                 trade: Trade::new(50),
+                order_type: OrderType::Limit,
             },
             &mut agents,
             &mut companies,
@@ -74,7 +81,7 @@ fn offer_resolving() {
 
     // to put the trade up, the agent needs to give that money
     assert_eq!(agents.balances.get(0).unwrap(), 0.0);
-    assert_eq!(agents.balances.get(1).unwrap(), 50.0); 
+    assert_eq!(agents.balances.get(1).unwrap(), 50.0);
 
     assert_eq!(agents.holdings.get(0, 0), 50);
     assert_eq!(agents.holdings.get(1, 0), 50);
@@ -94,6 +101,7 @@ fn offer_refund() {
                 strike_price: 1.0,
                 action: TradeAction::Buy,
                 trade: Trade::new(100),
+                order_type: OrderType::Limit,
             },
             &mut agents,
             &mut companies,
@@ -114,3 +122,468 @@ fn offer_refund() {
     assert_eq!(failed_offer.0.strike_price, 1.0);
     assert_eq!(failed_offer.1, TradeAction::Buy);
 }
+
+#[test]
+fn best_price_is_filled_first() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, strike_price) in [(1, 3.0), (2, 2.0)] {
+        market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id,
+                    company_id: 0,
+                    strike_price,
+                    action: TradeAction::Sell,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    assert_eq!(
+        market
+            .house
+            .get_best_trade_offer(0, TradeAction::Sell)
+            .unwrap()
+            .offerer_id,
+        2
+    );
+
+    let state = market
+        .trade(
+            false,
+            &TodoTransaction {
+                agent_id: 0,
+                company_id: 0,
+                strike_price: 5.0,
+                action: TradeAction::Buy,
+                trade: Trade::new(10),
+                order_type: OrderType::Limit,
+            },
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    let ActionState::InstantlyResolved(transactions) = state else {
+        panic!("expected the buy to be resolved, got {:?}", state);
+    };
+    assert_eq!(transactions.len(), 1);
+    assert_eq!(transactions[0].seller_id, 2);
+    assert_eq!(transactions[0].strike_price, 2.0);
+    assert_eq!(agents.balances.get(2).unwrap(), 20.0);
+    assert_eq!(market.house.get_mut_trade_offers(0).seller_offers.len(), 1);
+}
+
+#[test]
+fn earlier_offer_is_filled_first_at_same_price() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 0.0, &[(0, 10)], &[]),
+        Agent::new(1, 100.0, &[], &[]),
+        Agent::new(2, 100.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for agent_id in [1, 2] {
+        market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id,
+                    company_id: 0,
+                    strike_price: 2.0,
+                    action: TradeAction::Buy,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+
+    market
+        .trade(
+            false,
+            &TodoTransaction {
+                agent_id: 0,
+                company_id: 0,
+                strike_price: 2.0,
+                action: TradeAction::Sell,
+                trade: Trade::new(10),
+                order_type: OrderType::Limit,
+            },
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    assert_eq!(agents.holdings.get(1, 0), 10);
+    assert_eq!(agents.holdings.get(2, 0), 0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.buyer_offers.len(), 1);
+    assert_eq!(offers.best_buyer_offer().unwrap().offerer_id, 2);
+}
+
+#[test]
+fn large_order_sweeps_multiple_price_levels() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+        Agent::new(2, 0.0, &[(0, 10)], &[]),
+        Agent::new(3, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    for (agent_id, strike_price) in [(1, 1.0), (2, 2.0), (3, 3.0)] {
+        market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id,
+                    company_id: 0,
+                    strike_price,
+                    action: TradeAction::Sell,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+
+    let state = market
+        .trade(
+            false,
+            &TodoTransaction {
+                agent_id: 0,
+                company_id: 0,
+                strike_price: 2.5,
+                action: TradeAction::Buy,
+                trade: Trade::new(25),
+                order_type: OrderType::Limit,
+            },
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+
+    let ActionState::PartiallyResolved(transactions, _) = state else {
+        panic!("expected the buy to be partially resolved, got {:?}", state);
+    };
+    let fills: Vec<(u64, u64, f64)> = transactions
+        .iter()
+        .map(|transaction| {
+            (
+                transaction.seller_id,
+                transaction.number_of_shares,
+                transaction.strike_price,
+            )
+        })
+        .collect();
+    assert_eq!(fills, vec![(1, 10, 1.0), (2, 10, 2.0)]);
+    assert_eq!(agents.holdings.get(0, 0), 20);
+
+    // the rest of the buy rests at its limit, the level it couldn't reach is untouched
+    let offers = market.house.get_mut_trade_offers(0);
+    let best_buyer_offer = offers.best_buyer_offer().unwrap();
+    assert_eq!(best_buyer_offer.strike_price, 2.5);
+    assert_eq!(best_buyer_offer.data.number_of_shares, 5);
+    assert_eq!(offers.seller_offers.len(), 1);
+    assert_eq!(offers.best_seller_offer().unwrap().offerer_id, 3);
+}
+
+#[test]
+fn cancelling_offer_refunds_agent() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    let mut offer_ids = Vec::new();
+    for (agent_id, strike_price, action) in
+        [(0, 1.0, TradeAction::Buy), (1, 2.0, TradeAction::Sell)]
+    {
+        let state = market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id,
+                    company_id: 0,
+                    strike_price,
+                    action,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+        let ActionState::AddedToOffers(offer_id) = state else {
+            panic!("expected the offer to rest in the book, got {:?}", state);
+        };
+        offer_ids.push(offer_id);
+    }
+    assert_eq!(agents.balances.get(0).unwrap(), 90.0);
+    assert_eq!(agents.holdings.get(1, 0), 0);
+
+    for offer_id in offer_ids.iter() {
+        market
+            .house
+            .cancel_trade_offer(&mut agents, *offer_id)
+            .unwrap();
+    }
+
+    assert_eq!(agents.balances.get(0).unwrap(), 100.0);
+    assert_eq!(agents.holdings.get(1, 0), 10);
+    assert!(market.house.get_mut_trade_offers(0).buyer_offers.is_empty());
+    assert!(market
+        .house
+        .get_mut_trade_offers(0)
+        .seller_offers
+        .is_empty());
+    assert!(market
+        .house
+        .cancel_trade_offer(&mut agents, offer_ids[0])
+        .is_err());
+}
+
+#[test]
+fn amending_offer_adjusts_escrow_and_priority() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 100.0, &[], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    let mut offer_ids = Vec::new();
+    for agent_id in [0, 1] {
+        let state = market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id,
+                    company_id: 0,
+                    strike_price: 2.0,
+                    action: TradeAction::Buy,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+        let ActionState::AddedToOffers(offer_id) = state else {
+            panic!("expected the offer to rest in the book, got {:?}", state);
+        };
+        offer_ids.push(offer_id);
+    }
+
+    // reducing the shares keeps the place in the queue
+    market
+        .house
+        .amend_trade_offer(&mut agents, offer_ids[0], 2.0, 5)
+        .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), 90.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.best_buyer_offer().unwrap().id, offer_ids[0]);
+
+    // increasing the shares sends it to the back
+    market
+        .house
+        .amend_trade_offer(&mut agents, offer_ids[0], 2.0, 20)
+        .unwrap();
+    assert_eq!(agents.balances.get(0).unwrap(), 60.0);
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.best_buyer_offer().unwrap().id, offer_ids[1]);
+
+    // agent can't afford it
+    assert!(market
+        .house
+        .amend_trade_offer(&mut agents, offer_ids[1], 20.0, 10)
+        .is_err());
+    assert_eq!(agents.balances.get(1).unwrap(), 80.0);
+}
+
+#[test]
+fn deviation_only_widens_what_is_matched() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 200.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 5)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    let todo_transaction = |agent_id, strike_price, action| TodoTransaction {
+        agent_id,
+        company_id: 0,
+        strike_price,
+        action,
+        trade: Trade::new(if agent_id == 0 { 10 } else { 5 }),
+        order_type: OrderType::Limit,
+    };
+    market
+        .trade(
+            false,
+            &todo_transaction(1, 11.0, TradeAction::Sell),
+            &mut agents,
+            &mut companies,
+            0.0,
+        )
+        .unwrap();
+    let Ok(ActionState::PartiallyResolved(transactions, offer_id)) = market.trade(
+        false,
+        &todo_transaction(0, 10.0, TradeAction::Buy),
+        &mut agents,
+        &mut companies,
+        2.0,
+    ) else {
+        panic!("expected the order to be partially resolved");
+    };
+    assert_eq!(transactions[0].strike_price, 11.0);
+    // the rest waits at the strike price asked for, not the widened one
+    let offers = market.house.get_mut_trade_offers(0);
+    assert_eq!(offers.best_buyer_offer().unwrap().strike_price, 10.0);
+    assert_eq!(agents.escrow.get(offer_id).unwrap().strike_price, 10.0);
+    assert_eq!(agents.balances.get(0).unwrap(), 200.0 - 55.0 - 50.0);
+}
+
+#[test]
+fn offers_are_found_by_id_until_they_leave_the_book() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 100.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10), (1, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[
+        Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+        Company::new(1, 100.0, 0.0, 0.0, (0.0, 0, 0)),
+    ]);
+    let mut market = Market::new();
+    let mut offer_ids = Vec::new();
+    for company_id in [0, 1] {
+        let state = market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id: 1,
+                    company_id,
+                    strike_price: 2.0,
+                    action: TradeAction::Sell,
+                    trade: Trade::new(10),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+        let ActionState::AddedToOffers(offer_id) = state else {
+            panic!("expected the offer to rest in the book, got {:?}", state);
+        };
+        offer_ids.push(offer_id);
+    }
+    assert_eq!(market.house.find_trade_offer(offer_ids[1]).unwrap().0, 1);
+
+    // a fully filled offer is gone, a partially filled one is still there
+    for (company_id, number_of_shares) in [(0, 10), (1, 4)] {
+        market
+            .trade(
+                false,
+                &TodoTransaction {
+                    agent_id: 0,
+                    company_id,
+                    strike_price: 2.0,
+                    action: TradeAction::Buy,
+                    trade: Trade::new(number_of_shares),
+                    order_type: OrderType::Limit,
+                },
+                &mut agents,
+                &mut companies,
+                0.0,
+            )
+            .unwrap();
+    }
+    assert!(market.house.find_trade_offer(offer_ids[0]).is_none());
+    let (company_id, _, offer) = market.house.find_trade_offer(offer_ids[1]).unwrap();
+    assert_eq!((company_id, offer.data.number_of_shares), (1, 6));
+
+    // the offers are found again once the market is loaded
+    let mut bytes = Vec::new();
+    Snapshot::new(0, 0, agents, companies, market, None)
+        .write(&mut bytes)
+        .unwrap();
+    let Snapshot {
+        mut agents,
+        mut market,
+        ..
+    } = Snapshot::read(&mut bytes.as_slice()).unwrap();
+    assert_eq!(market.house.find_trade_offer(offer_ids[1]).unwrap().0, 1);
+    market
+        .house
+        .cancel_trade_offer(&mut agents, offer_ids[1])
+        .unwrap();
+    assert!(market.house.find_trade_offer(offer_ids[1]).is_none());
+}
+
+#[test]
+fn offers_that_cant_be_afforded_are_not_replaced() {
+    let mut agents = Agents::load(&[
+        Agent::new(0, 15.0, &[], &[]),
+        Agent::new(1, 0.0, &[(0, 10)], &[]),
+    ]);
+    let mut companies = Companies::load(&[Company::new(0, 100.0, 0.0, 0.0, (0.0, 0, 0))]);
+    let mut market = Market::new();
+    let mut offer_ids = Vec::new();
+    for (agent_id, strike_price, action) in
+        [(0, 1.0, TradeAction::Buy), (1, 2.0, TradeAction::Sell)]
+    {
+        let todo_transaction = TodoTransaction {
+            agent_id,
+            company_id: 0,
+            strike_price,
+            action,
+            trade: Trade::new(10),
+            order_type: OrderType::GoodTillCancelled,
+        };
+        let Ok(ActionState::AddedToOffers(offer_id)) =
+            market.submit(&todo_transaction, &mut agents, &mut companies)
+        else {
+            panic!("expected the offer to rest in the book");
+        };
+        offer_ids.push(offer_id);
+    }
+
+    // crossing the ask at 2 would cost 20, and only 15 can be put up
+    assert!(matches!(
+        market.replace_offer(&mut agents, &mut companies, 0, offer_ids[0], 2.0, 10),
+        Err(SimulationError::Unspendable)
+    ));
+    let (_, _, offer) = market.house.find_trade_offer(offer_ids[0]).unwrap();
+    assert_eq!((offer.strike_price, offer.data.number_of_shares), (1.0, 10));
+    assert_eq!(agents.balances.get(0).unwrap(), 5.0);
+
+    let state = market
+        .replace_offer(&mut agents, &mut companies, 0, offer_ids[0], 2.0, 7)
+        .unwrap();
+    assert!(matches!(state, ActionState::InstantlyResolved(_)));
+    assert_eq!(agents.holdings.get(0, 0), 7);
+    assert_eq!(agents.balances.get(0).unwrap(), 1.0);
+}